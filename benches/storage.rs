@@ -0,0 +1,62 @@
+//! Compares reads from `RwLock<HashMap>` (default storage of cache actor) and
+//! `ArcSwap<HashMap>` (storage with `arc-swap` feature) under many concurrent
+//! read tasks.
+//!
+//! Run with `cargo bench --bench storage --features postgres,arc-swap`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use arc_swap::ArcSwap;
+
+const ENTRIES: usize = 1000;
+const TASKS: usize = 10_000;
+const READS_PER_TASK: usize = 100;
+const THREADS: usize = 16;
+
+fn entries() -> HashMap<usize, String> {
+    (0..ENTRIES).map(|i| (i, format!("shop {}", i))).collect()
+}
+
+/// Runs `TASKS` read tasks spread over `THREADS` threads.
+fn run(read: impl Fn(usize) -> usize + Sync) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for thread in 0..THREADS {
+            let read = &read;
+            s.spawn(move || {
+                let mut total = 0;
+                for task in (thread..TASKS).step_by(THREADS) {
+                    for i in 0..READS_PER_TASK {
+                        total += read((task + i) % ENTRIES);
+                    }
+                }
+                total
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let reads = TASKS * READS_PER_TASK;
+    println!(
+        "{:>8}: {} reads in {:.2?}: {:.0} reads/second",
+        name,
+        reads,
+        elapsed,
+        reads as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let rwlock = Arc::new(RwLock::new(entries()));
+    let elapsed = run(|id| rwlock.read().unwrap().get(&id).map_or(0, String::len));
+    report("RwLock", elapsed);
+
+    let arc_swap = ArcSwap::from_pointee(entries());
+    let elapsed = run(|id| arc_swap.load().get(&id).map_or(0, String::len));
+    report("ArcSwap", elapsed);
+}
@@ -0,0 +1,191 @@
+//! Derive macro for `actix_diesel_cache::Cache` trait.
+//!
+//! ```ignore
+//! #[derive(Queryable, Cache, Clone, Debug)]
+//! #[table_name = "shop"]
+//! #[cache(id = "id")]
+//! struct Shop {
+//!     id: i32,
+//!     name: String,
+//! }
+//! ```
+//!
+//! Table is taken from `#[table_name]` attribute (the same one diesel uses)
+//! and can be overridden with `#[cache(table = "...")]`. Field set with
+//! `#[cache(secondary = "...")]` is used as secondary key, for which
+//! `SecondaryIndexCache` is derived too. `read_many` selects rows whose id
+//! column, named as id field, is any of ids. Generated impls are generic over
+//! connection type, so they work for any connection whose backend can load
+//! the struct.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, Lit, Meta, NestedMeta};
+
+/// Derives `Cache` trait using field from `#[cache(id = "...")]` as id.
+#[proc_macro_derive(Cache, attributes(cache, table_name))]
+pub fn derive_cache(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    impl_cache(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn impl_cache(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut id = None;
+    let mut table = None;
+    let mut secondary = None;
+
+    for attr in &input.attrs {
+        if attr.path.is_ident("table_name") {
+            if let Meta::NameValue(nv) = attr.parse_meta()? {
+                if table.is_none() {
+                    table = Some(lit_ident(&nv.lit)?);
+                }
+            }
+        } else if attr.path.is_ident("cache") {
+            let list = match attr.parse_meta()? {
+                Meta::List(list) => list,
+                meta => {
+                    return Err(syn::Error::new_spanned(
+                        meta,
+                        "expected `#[cache(id = \"...\")]`",
+                    ))
+                }
+            };
+            for nested in list.nested {
+                match nested {
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("id") => {
+                        id = Some(lit_ident(&nv.lit)?);
+                    }
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("table") => {
+                        table = Some(lit_ident(&nv.lit)?);
+                    }
+                    NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("secondary") => {
+                        secondary = Some(lit_ident(&nv.lit)?);
+                    }
+                    nested => {
+                        return Err(syn::Error::new_spanned(
+                            nested,
+                            "unknown cache attribute, expected `id`, `table` or `secondary`",
+                        ))
+                    }
+                }
+            }
+        }
+    }
+
+    let name = &input.ident;
+    let id = id.ok_or_else(|| {
+        syn::Error::new_spanned(name, "missing `#[cache(id = \"...\")]` attribute")
+    })?;
+    let table = table.ok_or_else(|| {
+        syn::Error::new_spanned(name, "missing `#[table_name = \"...\"]` attribute")
+    })?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "Cache can be derived only for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "Cache can be derived only for structs",
+            ))
+        }
+    };
+    let field_ty = |field: &Ident| {
+        fields
+            .iter()
+            .find(|f| f.ident.as_ref() == Some(field))
+            .map(|f| &f.ty)
+            .ok_or_else(|| {
+                syn::Error::new_spanned(field, format!("no field `{}` in `{}`", field, name))
+            })
+    };
+    let id_ty = field_ty(&id)?;
+    let secondary_ty = secondary.as_ref().map(field_ty).transpose()?;
+
+    let (_, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut generics = input.generics.clone();
+    generics.params.push(syn::parse_quote!(__Conn));
+    let (impl_generics, _, _) = generics.split_for_impl();
+    let predicates = where_clause.map(|w| &w.predicates);
+
+    let bounds = quote! {
+        __Conn: ::diesel::Connection + Unpin + 'static,
+        __Conn::Backend: actix_diesel_cache::ConnBackend<#table::table>
+            + ::diesel::sql_types::HasSqlType<<#table::table as ::diesel::query_builder::AsQuery>::SqlType>,
+        <#table::table as ::diesel::query_builder::AsQuery>::Query: ::diesel::query_builder::QueryId
+            + ::diesel::query_builder::QueryFragment<__Conn::Backend>,
+        Self: ::diesel::Queryable<<#table::table as ::diesel::query_builder::AsQuery>::SqlType, __Conn::Backend>,
+        #predicates
+    };
+    let eq_any = quote! {
+        ::diesel::dsl::EqAny<#table::#id, ::std::vec::Vec<#id_ty>>
+    };
+    let read_many_bounds = quote! {
+        #table::table: ::diesel::query_dsl::methods::FilterDsl<#eq_any>,
+        ::diesel::dsl::Filter<#table::table, #eq_any>: ::diesel::query_dsl::LoadQuery<__Conn, Self>,
+    };
+    let secondary_impl = match (&secondary, secondary_ty) {
+        (Some(field), Some(ty)) => quote! {
+            impl #impl_generics actix_diesel_cache::SecondaryIndexCache<__Conn, #table::table>
+                for #name #ty_generics
+            where
+                #bounds
+            {
+                type SecondaryIndex = #ty;
+
+                fn secondary_key(&self) -> Option<Self::SecondaryIndex> {
+                    Some(self.#field.clone())
+                }
+            }
+        },
+        _ => quote! {},
+    };
+
+    Ok(quote! {
+        impl #impl_generics actix_diesel_cache::Cache<__Conn, #table::table> for #name #ty_generics
+        where
+            #read_many_bounds
+            #bounds
+        {
+            type Id = #id_ty;
+
+            fn get_id(&self) -> Self::Id {
+                self.#id.clone()
+            }
+
+            fn read_many(
+                ids: &[Self::Id],
+                c: &__Conn,
+            ) -> actix_diesel_cache::Result<actix_diesel_cache::CacheMap<Self::Id, Self>> {
+                use ::diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+                let entries: ::std::vec::Vec<Self> =
+                    #table::table.filter(#table::#id.eq_any(ids.to_vec())).load(c)?;
+                Ok(entries
+                    .into_iter()
+                    .map(|it| (actix_diesel_cache::Cache::<__Conn, #table::table>::get_id(&it), it))
+                    .collect())
+            }
+        }
+
+        #secondary_impl
+    })
+}
+
+fn lit_ident(lit: &Lit) -> syn::Result<Ident> {
+    match lit {
+        Lit::Str(s) => Ok(Ident::new(&s.value(), s.span())),
+        lit => Err(syn::Error::new_spanned(lit, "expected string literal")),
+    }
+}
@@ -0,0 +1,106 @@
+//! Stress test of cache actor. Spawns many concurrent tasks sending `Get` and
+//! `Save` messages to a single actor backed by in-memory SQLite and reports
+//! throughput.
+//!
+//! Run with `cargo run --release --example stress_test --features sqlite`.
+
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate diesel;
+
+use std::time::Instant;
+
+use actix::prelude::*;
+use actix_diesel_cache::{messages::*, CacheDbActor};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+
+const TASKS: usize = 100;
+const MESSAGES_PER_TASK: usize = 100;
+
+table! {
+    shop (id) {
+        id -> Integer,
+        name -> Text,
+        address -> Text,
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Queryable, Clone, Debug)]
+pub struct Shop {
+    id: i32,
+    name: String,
+    address: String,
+}
+
+#[derive(Insertable, Clone, Debug)]
+#[table_name = "shop"]
+pub struct ShopInsert {
+    name: String,
+    address: String,
+}
+
+impl actix_diesel_cache::Cache<SqliteConnection, shop::table> for Shop {
+    type Id = i32;
+    fn get_id(&self) -> Self::Id {
+        self.id
+    }
+
+    fn read_many(
+        ids: &[Self::Id],
+        c: &SqliteConnection,
+    ) -> actix_diesel_cache::Result<actix_diesel_cache::CacheMap<Self::Id, Self>> {
+        let shops: Vec<Shop> = shop::table.filter(shop::id.eq_any(ids.to_vec())).load(c)?;
+        Ok(shops.into_iter().map(|s| (s.id, s)).collect())
+    }
+}
+
+fn init_db() -> SqliteConnection {
+    let conn = SqliteConnection::establish(":memory:").unwrap();
+    conn.batch_execute(
+        "create table shop (
+            id integer primary key autoincrement not null,
+            name text not null,
+            address text not null
+        )",
+    )
+    .unwrap();
+    conn
+}
+
+fn main() {
+    System::new("stress_test").block_on(async {
+        let addr = CacheDbActor::<_, shop::table, Shop>::new(init_db())
+            .unwrap()
+            .start();
+
+        let start = Instant::now();
+        let tasks = (0..TASKS).map(|task| {
+            let addr = addr.clone();
+            async move {
+                for i in 0..MESSAGES_PER_TASK {
+                    let shop = ShopInsert {
+                        name: format!("shop {}-{}", task, i),
+                        address: String::from("Central street"),
+                    };
+                    addr.send(Save(shop)).await.unwrap().unwrap();
+                    let id = (task * MESSAGES_PER_TASK + i) as i32 + 1;
+                    addr.send(Get::new(id)).await.unwrap().unwrap();
+                }
+            }
+        });
+        futures::future::join_all(tasks).await;
+        let elapsed = start.elapsed();
+
+        let messages = TASKS * MESSAGES_PER_TASK * 2;
+        println!(
+            "{} messages in {:.2?}: {:.0} messages/second",
+            messages,
+            elapsed,
+            messages as f64 / elapsed.as_secs_f64()
+        );
+    });
+}
@@ -0,0 +1,103 @@
+//! Cache actor used from actix-web handlers. Cache of shops backed by
+//! in-memory SQLite is registered in app and taken by handler as `CacheRef`.
+//!
+//! Run with `cargo run --example web --features sqlite,actix-web` and open
+//! `http://127.0.0.1:8080/shop/1`.
+
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate diesel;
+
+use actix::prelude::*;
+use actix_diesel_cache::{CacheDbActor, CacheRef};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::SqliteConnection;
+
+table! {
+    shop (id) {
+        id -> Integer,
+        name -> Text,
+        address -> Text,
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Queryable, Clone, Debug)]
+pub struct Shop {
+    id: i32,
+    name: String,
+    address: String,
+}
+
+#[derive(Insertable, Clone, Debug)]
+#[table_name = "shop"]
+pub struct ShopInsert {
+    name: String,
+    address: String,
+}
+
+impl actix_diesel_cache::Cache<SqliteConnection, shop::table> for Shop {
+    type Id = i32;
+    fn get_id(&self) -> Self::Id {
+        self.id
+    }
+
+    fn read_many(
+        ids: &[Self::Id],
+        c: &SqliteConnection,
+    ) -> actix_diesel_cache::Result<actix_diesel_cache::CacheMap<Self::Id, Self>> {
+        let shops: Vec<Shop> = shop::table.filter(shop::id.eq_any(ids.to_vec())).load(c)?;
+        Ok(shops.into_iter().map(|s| (s.id, s)).collect())
+    }
+}
+
+/// Cache as seen by handlers, without connection and table types
+type ShopCache = CacheRef<i32, Shop, ShopInsert>;
+
+fn init_db() -> SqliteConnection {
+    let conn = SqliteConnection::establish(":memory:").unwrap();
+    conn.batch_execute(
+        "create table shop (
+            id integer primary key autoincrement not null,
+            name text not null,
+            address text not null
+        )",
+    )
+    .unwrap();
+    conn
+}
+
+async fn get_shop(cache: ShopCache, id: web::Path<i32>) -> HttpResponse {
+    match cache.get(id.into_inner()).await {
+        Ok(Some(shop)) => HttpResponse::Ok().body(shop.name),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    System::new("web").block_on(async {
+        let addr = CacheDbActor::<_, shop::table, Shop>::new(init_db())
+            .unwrap()
+            .start();
+        let cache = ShopCache::new(addr);
+        let shop = ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        };
+        cache.save(shop).await.unwrap();
+
+        HttpServer::new(move || {
+            cache
+                .clone()
+                .register(App::new())
+                .route("/shop/{id}", web::get().to(get_shop))
+        })
+        .bind("127.0.0.1:8080")?
+        .run()
+        .await
+    })
+}
@@ -0,0 +1,543 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::marker::{PhantomData, Unpin};
+#[cfg(feature = "r2d2")]
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use diesel::associations::HasTable;
+use diesel::connection::Connection;
+use diesel::pg::{Pg, PgConnection};
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+#[cfg(feature = "r2d2")]
+use diesel::r2d2::{ConnectionManager, ManageConnection, Pool, PooledConnection};
+use diesel::sql_types::HasSqlType;
+#[cfg(feature = "futures")]
+use futures::stream::{Stream, StreamExt};
+#[cfg(feature = "metrics")]
+use prometheus::Registry;
+
+#[cfg(feature = "r2d2")]
+use crate::conn::checkout_error;
+use crate::conn::ConnSource;
+use crate::storage::Storage;
+use crate::tls::{self, TlsOptions};
+#[cfg(any(feature = "serde", feature = "bincode"))]
+use crate::Serializer;
+#[cfg(all(feature = "futures", feature = "tokio"))]
+use crate::SpawnLoad;
+use crate::{
+    Cache, CacheDbActor, CacheMap, CacheMiddleware, ConnBackend, RefreshStrategy, Result,
+    RetryPolicy,
+};
+
+/// Builder for CacheDbActor. Allows to configure actor before loading entries
+/// from db.
+pub struct CacheDbActorBuilder<Conn, Table, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Connection for db
+    conn: ConnSource<Conn>,
+    /// Callback for evicted entries
+    on_evict: Option<fn(C::Id, C)>,
+    /// Entries to seed cache with instead of loading them from db
+    preloaded: Option<CacheMap<C::Id, C, S>>,
+    /// Log every cache operation
+    debug_verbose: bool,
+    /// Age after which entries are reported as old
+    max_entry_age: Option<Duration>,
+    /// When cache is reloaded
+    refresh_strategy: RefreshStrategy,
+    /// Time after last reload when cache is considered invalid on read
+    ttl: Option<Duration>,
+    /// Timeout of queries reloading cache
+    query_timeout: Option<Duration>,
+    /// Don't load entries from db in `build`
+    skip_initial_load: bool,
+    /// Number of entries to allocate space for
+    capacity: usize,
+    /// Maximum number of cached entries
+    max_entries: Option<usize>,
+    /// Number of retries of pool checkout when pool is exhausted
+    pool_checkout_retries: u8,
+    /// Policy of retrying db operations failed with transient errors
+    retry_policy: Option<Arc<dyn RetryPolicy>>,
+    /// Hooks called around handling of messages
+    middlewares: Vec<Arc<dyn CacheMiddleware<Conn, Table, C>>>,
+    /// Serve stale entries from `GetAll` while cache is reloaded
+    stale_while_revalidate: bool,
+    /// Loads entries of scheduled reload in blocking thread pool
+    #[cfg(all(feature = "futures", feature = "tokio"))]
+    spawn_load: Option<SpawnLoad<Conn, C::Id, C, S>>,
+    /// Fill ratio of cache map below which periodic update shrinks it
+    shrink_threshold: f64,
+    /// Format of cache dumps
+    #[cfg(any(feature = "serde", feature = "bincode"))]
+    serializer: Serializer,
+    /// Registry for prometheus metrics
+    #[cfg(feature = "metrics")]
+    metrics_registry: Option<Registry>,
+    /// Url of database to listen notifications of table changes from
+    #[cfg(feature = "postgres-notify")]
+    notify_url: Option<String>,
+    /// Phantom marker for saving table inside structure
+    t: PhantomData<Table>,
+}
+
+impl<Conn, Table, C> CacheDbActorBuilder<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Constructor. Connection is held for the whole lifetime of actor, see
+    /// [`CacheDbActor::new`].
+    pub fn new(conn: Conn) -> Self {
+        Self::with_source(ConnSource::Single(conn))
+    }
+
+    /// Constructor which establishes connection to `database_url`. Actor
+    /// started with [`CacheDbActor::new_supervised`] reestablishes it on
+    /// restart.
+    pub fn establish(database_url: &str) -> Result<Self> {
+        let conn = Conn::establish(database_url)?;
+        Ok(Self::with_source(ConnSource::Established {
+            url: database_url.to_owned(),
+            conn,
+        }))
+    }
+
+    /// Constructor which makes actor check out connection from pool for
+    /// every operation and return it back right after, so pool is not
+    /// starved by idle actors.
+    #[cfg(feature = "r2d2")]
+    pub fn from_connection_pool(pool: Pool<ConnectionManager<Conn>>) -> Self
+    where
+        Conn: Send,
+    {
+        Self::with_source(ConnSource::ManagedPool(Rc::new(move || {
+            let conn = pool.get().map_err(|e| checkout_error(&pool, e))?;
+            Ok(Box::new(conn) as Box<dyn std::ops::Deref<Target = Conn>>)
+        })))
+    }
+
+    fn with_source(conn: ConnSource<Conn>) -> Self {
+        Self {
+            conn,
+            on_evict: None,
+            preloaded: None,
+            debug_verbose: false,
+            max_entry_age: None,
+            refresh_strategy: RefreshStrategy::Timer(Duration::from_secs(60)),
+            ttl: None,
+            query_timeout: None,
+            skip_initial_load: false,
+            capacity: 0,
+            max_entries: None,
+            pool_checkout_retries: 0,
+            retry_policy: None,
+            middlewares: Vec::new(),
+            stale_while_revalidate: false,
+            #[cfg(all(feature = "futures", feature = "tokio"))]
+            spawn_load: None,
+            shrink_threshold: 0.5,
+            #[cfg(any(feature = "serde", feature = "bincode"))]
+            serializer: Serializer::default(),
+            #[cfg(feature = "metrics")]
+            metrics_registry: None,
+            #[cfg(feature = "postgres-notify")]
+            notify_url: None,
+            t: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C, S> CacheDbActorBuilder<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Sets callback which is called with every entry evicted from cache.
+    ///
+    /// Callback is called synchronously inside of actor.
+    pub fn on_evict(mut self, f: fn(C::Id, C)) -> Self {
+        self.on_evict = Some(f);
+        self
+    }
+
+    /// Sets interval of periodic updates. Default is one minute. Zero
+    /// interval disables periodic updates, so cache is updated only on writes.
+    pub fn refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_strategy = if refresh_interval == Duration::from_secs(0) {
+            RefreshStrategy::OnWrite
+        } else {
+            RefreshStrategy::Timer(refresh_interval)
+        };
+        self
+    }
+
+    /// Sets when cache is reloaded, see [`RefreshStrategy`]. Default is
+    /// timer with interval of one minute.
+    pub fn refresh_strategy(mut self, refresh_strategy: RefreshStrategy) -> Self {
+        self.refresh_strategy = refresh_strategy;
+        self
+    }
+
+    /// Skips loading entries from db in `build`. Cache starts empty and is
+    /// loaded on first read or timer update.
+    pub fn skip_initial_load(mut self, skip_initial_load: bool) -> Self {
+        self.skip_initial_load = skip_initial_load;
+        self
+    }
+
+    /// Makes actor load entries lazily, on first read, instead of in
+    /// `build`. Same as [`skip_initial_load`](Self::skip_initial_load).
+    pub fn lazy(self, lazy: bool) -> Self {
+        self.skip_initial_load(lazy)
+    }
+
+    /// Makes `GetAll` return stale entries at once if cache is invalid.
+    /// Reload is scheduled to run after reply, so caller is not blocked. It
+    /// runs on actor thread unless
+    /// [`revalidate_in_background`](Self::revalidate_in_background) is set.
+    pub fn stale_while_revalidate(mut self, stale_while_revalidate: bool) -> Self {
+        self.stale_while_revalidate = stale_while_revalidate;
+        self
+    }
+
+    /// Makes reloads scheduled by
+    /// [`stale_while_revalidate`](Self::stale_while_revalidate) load entries
+    /// in blocking thread pool of tokio, as `SpawnRefresh` does, so actor
+    /// keeps handling messages meanwhile. Works only for actor which checks
+    /// out connections of pool with `from_pool`, other actors reload on
+    /// their thread. Call it after [`with_hasher`](Self::with_hasher), which
+    /// resets it.
+    #[cfg(all(feature = "futures", feature = "tokio"))]
+    pub fn revalidate_in_background(mut self, revalidate_in_background: bool) -> Self
+    where
+        Conn: Send,
+        C: Send,
+        C::Id: Send,
+        S: Send,
+    {
+        self.spawn_load = if revalidate_in_background {
+            Some(CacheDbActor::<Conn, Table, C, S>::spawn_load)
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Retries `SaveWith`, `Delete` and periodic updates failed with
+    /// transient db errors as `policy` decides. Actor handles other messages
+    /// while it waits for retry.
+    ///
+    /// `Save` is not retried, as written value is moved to failed query.
+    /// `SaveWith` builds value for every attempt instead.
+    pub fn retry_policy(mut self, policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Registers middleware called around handling of messages. Middlewares
+    /// are called in order of registration.
+    pub fn with_middleware(mut self, middleware: impl CacheMiddleware<Conn, Table, C>) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Makes periodic update release memory of cache map, when number of
+    /// entries drops below `threshold` of its capacity. Default is `0.5`,
+    /// zero disables shrinking.
+    pub fn shrink_threshold(mut self, threshold: f64) -> Self {
+        self.shrink_threshold = threshold;
+        self
+    }
+
+    /// Sets format of [`DumpCache`](crate::messages::DumpCache) and
+    /// [`LoadCache`](crate::messages::LoadCache). Default is JSON if `serde`
+    /// feature is enabled, otherwise bincode.
+    #[cfg(any(feature = "serde", feature = "bincode"))]
+    pub fn with_serializer(mut self, serializer: Serializer) -> Self {
+        self.serializer = serializer;
+        self
+    }
+
+    /// Registers prometheus metrics of actor in `registry` when actor is
+    /// started. Metrics are labeled with name of table.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_registry(mut self, registry: Registry) -> Self {
+        self.metrics_registry = Some(registry);
+        self
+    }
+
+    /// Makes started actor listen to
+    /// [`NOTIFY_CHANNEL`](crate::notify::NOTIFY_CHANNEL) on separate
+    /// connection to `database_url` and reload cache whenever notification
+    /// payload is name of its table, e.g. `NOTIFY cache_invalidate, 'shop'`
+    /// sent by trigger or other process.
+    ///
+    /// Listener keeps address of actor until actor is stopped.
+    #[cfg(feature = "postgres-notify")]
+    pub fn listen_notifications(mut self, database_url: &str) -> Self {
+        self.notify_url = Some(database_url.to_owned());
+        self
+    }
+
+    /// Allocates space for `capacity` entries in cache before loading them,
+    /// e.g. when number of rows is known at startup, so map is not grown
+    /// during load
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Same as [`CacheDbActorBuilder::cache_capacity`]
+    pub fn with_initial_capacity(self, capacity: usize) -> Self {
+        self.cache_capacity(capacity)
+    }
+
+    /// Makes cache map hash ids with `S2` instead of `RandomState`, e.g.
+    /// [`FxBuildHasher`](crate::FxBuildHasher) with `fast-hash` feature or
+    /// [`AHashBuildHasher`](crate::AHashBuildHasher) with `ahash` feature.
+    /// Maps returned by messages which share cache map, e.g. `GetAll`, use
+    /// the same hasher.
+    pub fn with_hasher<S2>(self) -> CacheDbActorBuilder<Conn, Table, C, S2>
+    where
+        S2: BuildHasher + Default + Clone + 'static,
+    {
+        CacheDbActorBuilder {
+            conn: self.conn,
+            on_evict: self.on_evict,
+            preloaded: self.preloaded.map(|cache| cache.into_iter().collect()),
+            debug_verbose: self.debug_verbose,
+            max_entry_age: self.max_entry_age,
+            refresh_strategy: self.refresh_strategy,
+            ttl: self.ttl,
+            query_timeout: self.query_timeout,
+            skip_initial_load: self.skip_initial_load,
+            capacity: self.capacity,
+            max_entries: self.max_entries,
+            pool_checkout_retries: self.pool_checkout_retries,
+            retry_policy: self.retry_policy,
+            middlewares: self.middlewares,
+            stale_while_revalidate: self.stale_while_revalidate,
+            #[cfg(all(feature = "futures", feature = "tokio"))]
+            spawn_load: None,
+            shrink_threshold: self.shrink_threshold,
+            #[cfg(any(feature = "serde", feature = "bincode"))]
+            serializer: self.serializer,
+            #[cfg(feature = "metrics")]
+            metrics_registry: self.metrics_registry,
+            #[cfg(feature = "postgres-notify")]
+            notify_url: self.notify_url,
+            t: PhantomData,
+        }
+    }
+
+    /// Limits cache to `max_entries` entries. When limit is exceeded, least
+    /// recently used entries are evicted and passed to `on_evict`. Entries
+    /// are used when they are written or got with `Get`.
+    ///
+    /// Full reload selects only `max_entries` rows with
+    /// [`Cache::read_limited`], in place of [`Cache::custom_load`].
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Retries checkout of connection from exhausted pool up to `retries`
+    /// times with short delay before failing with `CacheError::Busy`. Actor
+    /// handles other messages while it waits for retry.
+    ///
+    /// Only writes and periodic updates, which are retried with
+    /// [`retry_policy`](Self::retry_policy) too, wait for connection. Reads
+    /// fail at once, so they don't queue up behind exhausted pool.
+    pub fn pool_checkout_retries(mut self, retries: u8) -> Self {
+        self.pool_checkout_retries = retries;
+        self
+    }
+
+    /// Enables logging of every cache hit, miss, save and reload. Logging
+    /// happens only in debug builds.
+    pub fn debug_verbose(mut self, debug_verbose: bool) -> Self {
+        self.debug_verbose = debug_verbose;
+        self
+    }
+
+    /// Makes cache invalid when `ttl` has passed since last reload. Expiry
+    /// is checked on reads, `Get` still serves cached entry and following
+    /// reads reload cache. Unlike refresh interval, idle actor doesn't touch
+    /// db.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Aborts reloads of cache taking longer than `query_timeout`, so slow
+    /// query doesn't block actor indefinitely. Failed reload leaves cached
+    /// entries as they were and periodic updates go on.
+    ///
+    /// On postgres reload runs in transaction with `SET LOCAL
+    /// statement_timeout`. On SQLite it sets `PRAGMA busy_timeout`, which
+    /// limits only waiting for locks of other connections. On MySQL it sets
+    /// `max_execution_time`, which limits only `SELECT` statements.
+    ///
+    /// SQLite and MySQL apply these settings to whole connection, so they are
+    /// reset after reload: `busy_timeout` to `0`, the SQLite default, and
+    /// `max_execution_time` to global value of server. Busy timeout set on
+    /// connection before is lost.
+    pub fn query_timeout(mut self, query_timeout: Duration) -> Self {
+        self.query_timeout = Some(query_timeout);
+        self
+    }
+
+    /// Reports entries which stayed in cache for longer than `max_entry_age`
+    /// with a warning on every timer update. Entries are not evicted.
+    pub fn max_entry_age(mut self, max_entry_age: Duration) -> Self {
+        self.max_entry_age = Some(max_entry_age);
+        self
+    }
+
+    #[cfg(feature = "futures")]
+    /// Seeds cache with entries from stream, so that `build` doesn't load
+    /// them from db. Returns number of entries read.
+    ///
+    /// Cache is marked invalid, so it is reconciled with db on first update.
+    pub async fn preheat_from_stream<St>(&mut self, mut stream: St) -> Result<usize>
+    where
+        St: Stream<Item = C> + Unpin,
+    {
+        let mut cache = CacheMap::default();
+        while let Some(it) = stream.next().await {
+            cache.insert(it.get_id(), it);
+        }
+        let len = cache.len();
+        self.preloaded = Some(cache);
+        Ok(len)
+    }
+
+    /// Builds actor and loads all entries from db
+    pub fn build(mut self) -> Result<CacheDbActor<Conn, Table, C, S>> {
+        let preloaded = self.preloaded.take();
+        let skip_initial_load = self.skip_initial_load;
+        let mut actor = self.build_empty();
+        match preloaded {
+            Some(cache) => {
+                actor.seed(cache);
+                actor.is_valid = false;
+            }
+            None if skip_initial_load => actor.is_valid = false,
+            None => actor.update()?,
+        }
+        Ok(actor)
+    }
+
+    /// Builds actor seeded with `data` without touching db. Entries are
+    /// trusted to be up to date, so cache is valid until next update.
+    pub fn build_preloaded(self, data: CacheMap<C::Id, C, S>) -> CacheDbActor<Conn, Table, C, S> {
+        let mut actor = self.build_empty();
+        actor.seed(data);
+        actor
+    }
+
+    fn build_empty(self) -> CacheDbActor<Conn, Table, C, S> {
+        CacheDbActor {
+            conn: self.conn,
+            cache: Storage::new(CacheMap::with_capacity_and_hasher(
+                self.capacity,
+                Default::default(),
+            )),
+            is_valid: true,
+            on_evict: self.on_evict,
+            stats: Default::default(),
+            debug_verbose: self.debug_verbose,
+            entry_timestamps: Default::default(),
+            secondary: None,
+            #[cfg(feature = "sorted")]
+            sorted: None,
+            max_entry_age: self.max_entry_age,
+            refresh_strategy: self.refresh_strategy,
+            ttl: self.ttl,
+            query_timeout: self.query_timeout,
+            last_refresh: Instant::now(),
+            loaded_count: 0,
+            capacity: self.capacity,
+            max_entries: self.max_entries,
+            lru: Default::default(),
+            pool_checkout_retries: self.pool_checkout_retries,
+            retry_policy: self.retry_policy,
+            middlewares: self.middlewares,
+            stale_while_revalidate: self.stale_while_revalidate,
+            shrink_threshold: self.shrink_threshold,
+            #[cfg(any(feature = "serde", feature = "bincode"))]
+            serializer: self.serializer,
+            revalidating: false,
+            #[cfg(all(feature = "futures", feature = "tokio"))]
+            spawn_load: self.spawn_load,
+            supervised: false,
+            reconnect_attempts: 0,
+            timer: None,
+            pending: Vec::new(),
+            subscribers: Vec::new(),
+            #[cfg(feature = "metrics")]
+            metrics_registry: self.metrics_registry,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "postgres-notify")]
+            notify_url: self.notify_url,
+            #[cfg(feature = "postgres-notify")]
+            notify_addr: None,
+            t: PhantomData,
+        }
+    }
+}
+
+impl<Table, C> CacheDbActorBuilder<PgConnection, Table, C>
+where
+    Pg: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Pg>,
+    C: Cache<PgConnection, Table>,
+{
+    /// Same as [`establish`](Self::establish) with TLS settings. Failures of
+    /// TLS, e.g. untrusted certificate of server, are reported as
+    /// [`CacheError::Tls`](crate::CacheError::Tls).
+    pub fn establish_tls(database_url: &str, options: &TlsOptions) -> Result<Self> {
+        let url = options.connection_url(database_url);
+        let conn = PgConnection::establish(&url).map_err(tls::connection_error)?;
+        Ok(Self::with_source(ConnSource::Established { url, conn }))
+    }
+}
+
+#[cfg(feature = "r2d2")]
+impl<M, Table, C> CacheDbActorBuilder<PooledConnection<M>, Table, C>
+where
+    M: ManageConnection,
+    PooledConnection<M>: Connection + Unpin + 'static,
+    <PooledConnection<M> as Connection>::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<<PooledConnection<M> as Connection>::Backend>,
+    C: Cache<PooledConnection<M>, Table>,
+{
+    /// Constructor which makes actor check out fresh connection from pool for
+    /// every operation.
+    pub fn from_pool(pool: Pool<M>) -> Self {
+        Self::with_source(ConnSource::Pool(Rc::new(move || {
+            pool.get().map_err(|e| checkout_error(&pool, e))
+        })))
+    }
+}
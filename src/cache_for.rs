@@ -0,0 +1,85 @@
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use diesel::backend::Backend;
+use diesel::deserialize::Queryable;
+
+/// Entry of type `C` cached for table `Table`. Wrapping entry lets one type
+/// have separate [`Cache`](crate::Cache) impls, e.g. for base table and its
+/// view, which differ in associated types or overridden methods:
+///
+/// ```ignore
+/// impl Cache<PgConnection, shop_view::table> for CacheFor<shop_view::table, Shop> {
+///     type Id = i32;
+///     fn get_id(&self) -> Self::Id {
+///         self.id
+///     }
+///
+///     fn read_many(ids: &[Self::Id], c: &PgConnection) -> Result<CacheMap<Self::Id, Self>> {
+///         let shops: Vec<Shop> = shop_view::table.filter(shop_view::id.eq_any(ids.to_vec())).load(c)?;
+///         Ok(shops.into_iter().map(|s| (s.id, CacheFor::new(s))).collect())
+///     }
+/// }
+/// ```
+///
+/// Wrapper derefs to entry, so its fields and methods are used as is.
+pub struct CacheFor<Table, C>(pub C, PhantomData<Table>);
+
+impl<Table, C> CacheFor<Table, C> {
+    /// Wraps entry
+    pub fn new(entry: C) -> Self {
+        Self(entry, PhantomData)
+    }
+
+    /// Unwraps entry
+    pub fn into_inner(self) -> C {
+        self.0
+    }
+}
+
+impl<Table, C> Deref for CacheFor<Table, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.0
+    }
+}
+
+impl<Table, C> DerefMut for CacheFor<Table, C> {
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.0
+    }
+}
+
+impl<Table, C: Clone> Clone for CacheFor<Table, C> {
+    fn clone(&self) -> Self {
+        Self::new(self.0.clone())
+    }
+}
+
+impl<Table, C: Debug> Debug for CacheFor<Table, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<Table, C: PartialEq> PartialEq for CacheFor<Table, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<Table, C: Eq> Eq for CacheFor<Table, C> {}
+
+impl<Table, C, ST, DB> Queryable<ST, DB> for CacheFor<Table, C>
+where
+    DB: Backend,
+    C: Queryable<ST, DB>,
+{
+    type Row = C::Row;
+
+    fn build(row: Self::Row) -> Self {
+        Self::new(C::build(row))
+    }
+}
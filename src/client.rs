@@ -0,0 +1,276 @@
+#[cfg(feature = "actix-web")]
+use std::any::type_name;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+#[cfg(feature = "actix-web")]
+use std::future::{ready, Ready};
+use std::hash::BuildHasher;
+use std::marker::Unpin;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use actix::prelude::*;
+#[cfg(feature = "actix-web")]
+use actix_service::ServiceFactory;
+#[cfg(feature = "actix-web")]
+use actix_web::dev::{MessageBody, Payload, ServiceRequest, ServiceResponse};
+#[cfg(feature = "actix-web")]
+use actix_web::{App, FromRequest, HttpRequest};
+
+use diesel::associations::HasTable;
+use diesel::connection::Connection;
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use diesel::sql_types::HasSqlType;
+
+use crate::messages::{Get, GetAll, Save};
+use crate::{Cache, CacheDbActor, CacheMap, ConnBackend, Result};
+
+/// Gets entries with the same id from two cache actors concurrently.
+pub async fn zip_with<Conn1, Table1, C1, S1, Conn2, Table2, C2, S2>(
+    a: &Addr<CacheDbActor<Conn1, Table1, C1, S1>>,
+    b: &Addr<CacheDbActor<Conn2, Table2, C2, S2>>,
+    id: C1::Id,
+) -> std::result::Result<(Result<Option<C1>>, Result<Option<C2>>), MailboxError>
+where
+    Conn1: Connection + Unpin + 'static,
+    Conn1::Backend: ConnBackend<Table1> + HasSqlType<Table1::SqlType>,
+    Table1: diesel::Table + HasTable<Table = Table1> + AsQuery + Unpin + 'static,
+    Table1::Query: QueryId + QueryFragment<Conn1::Backend>,
+    C1: Cache<Conn1, Table1> + Send,
+    C1::Id: Send,
+    S1: BuildHasher + Default + Clone + 'static,
+    Conn2: Connection + Unpin + 'static,
+    Conn2::Backend: ConnBackend<Table2> + HasSqlType<Table2::SqlType>,
+    Table2: diesel::Table + HasTable<Table = Table2> + AsQuery + Unpin + 'static,
+    Table2::Query: QueryId + QueryFragment<Conn2::Backend>,
+    C2: Cache<Conn2, Table2, Id = C1::Id> + Send,
+    S2: BuildHasher + Default + Clone + 'static,
+{
+    // Both messages are queued before awaiting, so actors handle them
+    // concurrently.
+    let a = a.send(Get::new(id.clone()));
+    let b = b.send(Get::new(id));
+    Ok((a.await?, b.await?))
+}
+
+/// Cheaply cloneable handle of cache actor with async methods, which can be
+/// shared between threads and tasks. Mailbox errors are returned as
+/// [`CacheError::Mailbox`](crate::CacheError::Mailbox).
+pub struct CacheHandle<Conn, Table, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    addr: Addr<CacheDbActor<Conn, Table, C, S>>,
+}
+
+impl<Conn, Table, C, S> CacheHandle<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Constructor
+    pub fn new(addr: Addr<CacheDbActor<Conn, Table, C, S>>) -> Self {
+        Self { addr }
+    }
+
+    /// Address of actor
+    pub fn addr(&self) -> &Addr<CacheDbActor<Conn, Table, C, S>> {
+        &self.addr
+    }
+
+    /// Gets entry by id, see [`Get`]
+    pub async fn get(&self, id: C::Id) -> Result<Option<C>> {
+        self.addr.send(Get::new(id)).await?
+    }
+
+    /// Saves one entry, see [`Save`]
+    pub async fn save<W>(&self, w: W) -> Result<()>
+    where
+        W: Send + 'static,
+        CacheDbActor<Conn, Table, C, S>:
+            Handler<Save<W>> + Actor<Context = Context<CacheDbActor<Conn, Table, C, S>>>,
+    {
+        self.addr.send(Save(w)).await?
+    }
+
+    /// Gets all entries, see [`GetAll`]
+    pub async fn get_all(&self) -> Result<Arc<RwLock<CacheMap<C::Id, C, S>>>>
+    where
+        Conn: Send,
+        Table: Send,
+        S: Send + Sync,
+    {
+        self.addr.send(GetAll::default()).await?
+    }
+}
+
+impl<Conn, Table, C, S> Clone for CacheHandle<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            addr: self.addr.clone(),
+        }
+    }
+}
+
+impl<Conn, Table, C, S> From<Addr<CacheDbActor<Conn, Table, C, S>>>
+    for CacheHandle<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn from(addr: Addr<CacheDbActor<Conn, Table, C, S>>) -> Self {
+        Self::new(addr)
+    }
+}
+
+/// Future returned by methods of [`CacheRef`]
+type RefFuture<T> = Pin<Box<dyn Future<Output = Result<T>>>>;
+
+/// Type-erased request sent by [`CacheRef`]
+type RefRequest<Arg, T> = Arc<dyn Fn(Arg) -> RefFuture<T> + Send + Sync>;
+
+/// Cache map shared by actor with callers of `GetAll`
+type SharedMap<Id, C, S> = Arc<RwLock<CacheMap<Id, C, S>>>;
+
+/// Handle of cache actor which hides connection and table types, so it can
+/// be passed around as e.g. `CacheRef<i32, Shop, ShopInsert>`. Types are
+/// inferred from address given to [`CacheRef::new`]. Id of entries and type
+/// written with [`CacheRef::save`] are still named, as [`Cache`] is generic
+/// over connection and table.
+pub struct CacheRef<Id, C, W, S = RandomState>
+where
+    W: Send + 'static,
+{
+    get: RefRequest<Id, Option<C>>,
+    get_all: RefRequest<(), SharedMap<Id, C, S>>,
+    save: Recipient<Save<W>>,
+}
+
+impl<Id, C, W, S> CacheRef<Id, C, W, S>
+where
+    Id: Send + Sync + 'static,
+    C: Send + Sync + 'static,
+    W: Send + 'static,
+    S: BuildHasher + Default + Clone + Send + Sync + 'static,
+{
+    /// Constructor
+    pub fn new<Conn, Table>(addr: Addr<CacheDbActor<Conn, Table, C, S>>) -> Self
+    where
+        Conn: Connection + Unpin + Send + 'static,
+        Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+        Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + Send + 'static,
+        Table::Query: QueryId + QueryFragment<Conn::Backend>,
+        C: Cache<Conn, Table, Id = Id>,
+        CacheDbActor<Conn, Table, C, S>: Handler<Get<Conn, Table, C>>
+            + Handler<GetAll<Conn, Table, C, S>>
+            + Handler<Save<W>>
+            + Actor<Context = Context<CacheDbActor<Conn, Table, C, S>>>,
+    {
+        let get_addr = addr.clone();
+        let get_all_addr = addr.clone();
+        Self {
+            get: Arc::new(move |id| {
+                let request = get_addr.send(Get::<Conn, Table, C>::new(id));
+                Box::pin(async move { request.await? })
+            }),
+            get_all: Arc::new(move |()| {
+                let request = get_all_addr.send(GetAll::<Conn, Table, C, S>::default());
+                Box::pin(async move { request.await? })
+            }),
+            save: addr.recipient(),
+        }
+    }
+
+    /// Gets entry by id, see [`Get`]
+    pub async fn get(&self, id: Id) -> Result<Option<C>> {
+        (self.get)(id).await
+    }
+
+    /// Saves one entry, see [`Save`]
+    pub async fn save(&self, w: W) -> Result<()> {
+        self.save.send(Save(w)).await?
+    }
+
+    /// Gets all entries, see [`GetAll`]
+    pub async fn get_all(&self) -> Result<Arc<RwLock<CacheMap<Id, C, S>>>> {
+        (self.get_all)(()).await
+    }
+
+    /// Registers handle as app data, so request handlers can take it as
+    /// argument. Same as `app.app_data(cache_ref)`.
+    #[cfg(feature = "actix-web")]
+    pub fn register<T, B>(self, app: App<T, B>) -> App<T, B>
+    where
+        B: MessageBody,
+        T: ServiceFactory<
+            Config = (),
+            Request = ServiceRequest,
+            Response = ServiceResponse<B>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    {
+        app.app_data(self)
+    }
+}
+
+/// Extracts handle registered with [`CacheRef::register`], fails with
+/// internal server error if there is none
+#[cfg(feature = "actix-web")]
+impl<Id, C, W, S> FromRequest for CacheRef<Id, C, W, S>
+where
+    Id: Send + Sync + 'static,
+    C: Send + Sync + 'static,
+    W: Send + 'static,
+    S: 'static,
+{
+    type Error = actix_web::Error;
+    type Future = Ready<std::result::Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(req.app_data::<Self>().cloned().ok_or_else(|| {
+            let msg = format!("{} is not registered in app", type_name::<Self>());
+            log::error!("{}", msg);
+            actix_web::error::ErrorInternalServerError(msg)
+        }))
+    }
+}
+
+impl<Id, C, W, S> Clone for CacheRef<Id, C, W, S>
+where
+    W: Send + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            get: Arc::clone(&self.get),
+            get_all: Arc::clone(&self.get_all),
+            save: self.save.clone(),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::ops::Deref;
+#[cfg(feature = "r2d2")]
+use std::rc::Rc;
+
+use diesel::connection::Connection;
+#[cfg(feature = "r2d2")]
+use diesel::r2d2::{ManageConnection, Pool, PoolError};
+
+#[cfg(feature = "r2d2")]
+use crate::CacheError;
+use crate::Result;
+
+/// Source of db connections for cache actor.
+pub(crate) enum ConnSource<Conn> {
+    /// One connection owned by actor for its whole lifetime
+    Single(Conn),
+    /// Connection established by actor, which can be reestablished
+    Established {
+        /// Url of database
+        url: String,
+        /// Current connection
+        conn: Conn,
+    },
+    /// Fresh connection checked out of pool for every operation
+    #[cfg(feature = "r2d2")]
+    Pool(Rc<dyn Fn() -> Result<Conn>>),
+    /// Connection checked out of pool of `Conn` connections for every
+    /// operation and returned back after it
+    #[cfg(feature = "r2d2")]
+    ManagedPool(Rc<dyn Fn() -> Result<Box<dyn Deref<Target = Conn>>>>),
+}
+
+impl<Conn: Clone> Clone for ConnSource<Conn> {
+    fn clone(&self) -> Self {
+        match self {
+            ConnSource::Single(conn) => ConnSource::Single(conn.clone()),
+            ConnSource::Established { url, conn } => ConnSource::Established {
+                url: url.clone(),
+                conn: conn.clone(),
+            },
+            #[cfg(feature = "r2d2")]
+            ConnSource::Pool(get) => ConnSource::Pool(Rc::clone(get)),
+            #[cfg(feature = "r2d2")]
+            ConnSource::ManagedPool(get) => ConnSource::ManagedPool(Rc::clone(get)),
+        }
+    }
+}
+
+/// Connection borrowed from actor or checked out of pool.
+pub(crate) enum ConnRef<'a, Conn> {
+    Borrowed(&'a Conn),
+    #[cfg(feature = "r2d2")]
+    Owned(Conn),
+    #[cfg(feature = "r2d2")]
+    Pooled(Box<dyn Deref<Target = Conn>>),
+}
+
+impl<Conn> ConnSource<Conn> {
+    pub(crate) fn get(&self) -> Result<ConnRef<'_, Conn>> {
+        match self {
+            ConnSource::Single(conn) | ConnSource::Established { conn, .. } => {
+                Ok(ConnRef::Borrowed(conn))
+            }
+            #[cfg(feature = "r2d2")]
+            ConnSource::Pool(get) => get().map(ConnRef::Owned),
+            #[cfg(feature = "r2d2")]
+            ConnSource::ManagedPool(get) => get().map(ConnRef::Pooled),
+        }
+    }
+}
+
+impl<Conn> ConnSource<Conn> {
+    /// Connection which can be moved out of actor, e.g. to blocking thread
+    /// pool. Only pool checks out connections owned by caller.
+    #[cfg(all(feature = "futures", feature = "tokio"))]
+    pub(crate) fn get_owned(&self) -> Option<Result<Conn>> {
+        match self {
+            #[cfg(feature = "r2d2")]
+            ConnSource::Pool(get) => Some(get()),
+            _ => None,
+        }
+    }
+}
+
+/// Error of pool checkout. Timeout with all connections checked out means
+/// that pool is exhausted, rather than that db is unreachable.
+#[cfg(feature = "r2d2")]
+pub(crate) fn checkout_error<M: ManageConnection>(pool: &Pool<M>, e: PoolError) -> CacheError {
+    let state = pool.state();
+    if state.idle_connections == 0 && state.connections == pool.max_size() {
+        CacheError::Busy
+    } else {
+        CacheError::Pool(e)
+    }
+}
+
+impl<Conn: Connection> ConnSource<Conn> {
+    /// Reestablishes connection. Connection given by user can't be
+    /// reestablished and pool checks out fresh connection anyway, so only
+    /// established connection is replaced.
+    pub(crate) fn reconnect(&mut self) -> Result<()> {
+        if let ConnSource::Established { url, conn } = self {
+            *conn = Conn::establish(url)?;
+        }
+        Ok(())
+    }
+}
+
+impl<Conn> Deref for ConnRef<'_, Conn> {
+    type Target = Conn;
+
+    fn deref(&self) -> &Conn {
+        match self {
+            ConnRef::Borrowed(conn) => conn,
+            #[cfg(feature = "r2d2")]
+            ConnRef::Owned(conn) => conn,
+            #[cfg(feature = "r2d2")]
+            ConnRef::Pooled(conn) => conn,
+        }
+    }
+}
@@ -3,37 +3,412 @@
 
 #![deny(missing_docs)]
 
-use std::collections::HashMap;
+use std::any::Any;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fmt::Debug;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 use std::marker::{PhantomData, Unpin};
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use actix::prelude::*;
+#[cfg(feature = "futures")]
+use futures::sink::SinkExt;
 
 use diesel::associations::HasTable;
 use diesel::backend::Backend;
 use diesel::backend::SupportsReturningClause;
 use diesel::connection::Connection;
-use diesel::deserialize::Queryable;
+use diesel::deserialize::{Queryable, QueryableByName};
+use diesel::dsl::{EqAny, Filter, Find, Gt, Limit};
+use diesel::expression::array_comparison::AsInExpression;
+use diesel::expression::bound::Bound;
+use diesel::expression::operators::Eq as EqOp;
+use diesel::expression::AsExpression;
 use diesel::insertable::CanInsertInSingleQuery;
+#[cfg(feature = "mysql")]
+use diesel::mysql::Mysql;
 #[cfg(feature = "postgres")]
 use diesel::pg::Pg;
 use diesel::prelude::*;
-use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
-use diesel::sql_types::HasSqlType;
+use diesel::query_builder::{
+    AsChangeset, AsQuery, DeleteStatement, IntoUpdateTarget, QueryFragment, QueryId, SqlQuery,
+    UndecoratedInsertRecord, UpdateStatement,
+};
+use diesel::query_dsl::methods::{ExecuteDsl, FilterDsl, FindDsl, LimitDsl};
+use diesel::query_dsl::LoadQuery;
+#[cfg(feature = "r2d2")]
+use diesel::r2d2::{ConnectionManager, ManageConnection, Pool, PooledConnection};
+use diesel::result::DatabaseErrorKind;
+use diesel::sql_types::{BigInt as BigIntSql, HasSqlType};
 #[cfg(feature = "sqlite")]
-use diesel::sqlite::Sqlite;
+use diesel::{
+    dsl::sql,
+    expression::SqlLiteral,
+    sql_types::{BigInt, Bool},
+    sqlite::Sqlite,
+};
+#[cfg(feature = "metrics")]
+use prometheus::Registry;
 
 /// Messages for cache actor
 pub mod messages;
 use messages::*;
 
+/// Builder for cache actor
+pub mod builder;
+pub use builder::CacheDbActorBuilder;
+
+/// Helper functions for working with cache actors
+pub mod client;
+pub use client::{CacheHandle, CacheRef};
+
+/// Statistics of cache actor
+pub mod stats;
+use stats::CacheStats;
+
+/// Immutable snapshot of cache
+pub mod snapshot;
+pub use snapshot::{CacheCheckpointToken, CacheReadGuard, CacheSnapshot};
+
+/// Wrapper for caching one entry type for several tables
+pub mod cache_for;
+pub use cache_for::CacheFor;
+
+/// Cache actor for `SyncArbiter`
+pub mod sync_actor;
+pub use sync_actor::SyncCacheDbActor;
+
+/// Actor caching many tables with one connection
+pub mod multi;
+pub use multi::MultiCache;
+
+/// In-memory fake of cache actor for tests
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "testing")]
+pub use testing::FakeCacheDbActor;
+
+/// Formats of cache dumps
+#[cfg(any(feature = "serde", feature = "bincode"))]
+pub mod serializer;
+#[cfg(any(feature = "serde", feature = "bincode"))]
+pub use serializer::{Serializable, Serializer};
+
+/// Retries of db operations failed with transient errors
+pub mod retry;
+pub use retry::RetryPolicy;
+
+/// Hooks around handling of messages
+pub mod middleware;
+pub use middleware::CacheMiddleware;
+
+mod conn;
+use conn::ConnSource;
+
+mod storage;
+use storage::Storage;
+
+mod lru;
+use lru::LruQueue;
+
+mod limit;
+use limit::Limited;
+
+mod secondary;
+use secondary::SecondaryIndex;
+
+#[cfg(feature = "sorted")]
+mod sorted;
+#[cfg(feature = "sorted")]
+use sorted::SortedIndex;
+
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "metrics")]
+use metrics::CacheMetrics;
+
+/// Invalidation of cache by postgres notifications
+#[cfg(feature = "postgres-notify")]
+pub mod notify;
+#[cfg(feature = "postgres-notify")]
+use notify::SharedAddr;
+
+/// TLS connections to postgres
+pub mod tls;
+pub use tls::{SslMode, TlsOptions};
+
+/// Name of table type. Diesel tables are all named `table` inside module of
+/// the table, so module name is used for them.
+#[cfg(any(feature = "metrics", feature = "postgres-notify"))]
+pub(crate) fn table_label<Table>() -> &'static str {
+    let mut segments = std::any::type_name::<Table>().rsplit("::");
+    match segments.next() {
+        Some("table") => segments.next().unwrap_or("table"),
+        Some(name) => name,
+        None => "",
+    }
+}
+
+/// Enters tracing span of handler until the end of enclosing scope. Span
+/// carries table type name. Expands to nothing without `tracing` feature.
+macro_rules! handler_span {
+    ($handler:expr) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            "cache_handler",
+            handler = $handler,
+            table = std::any::type_name::<Table>()
+        )
+        .entered();
+    };
+}
+
+/// Derive macro for [`Cache`] trait
+#[cfg(feature = "derive")]
+pub use actix_diesel_cache_derive::Cache;
+
 /// Error of cache actor
-pub type Error = diesel::result::Error;
+#[derive(Debug)]
+pub enum CacheError {
+    /// Error of database
+    Db(diesel::result::Error),
+    /// Lock of cache map was poisoned by panic in another thread
+    LockPoisoned,
+    /// Entry was not found, contains description of entry
+    NotFound(String),
+    /// Connection to db could not be established
+    Connection(diesel::ConnectionError),
+    /// Connection could not be checked out of pool
+    #[cfg(feature = "r2d2")]
+    Pool(diesel::r2d2::PoolError),
+    /// All connections of pool are checked out
+    Busy,
+    /// Cache could not be serialized or deserialized
+    #[cfg(feature = "serde")]
+    Serde(serde_json::Error),
+    /// Cache could not be encoded with bincode
+    #[cfg(feature = "bincode")]
+    BincodeEncode(bincode_crate::error::EncodeError),
+    /// Cache could not be decoded with bincode
+    #[cfg(feature = "bincode")]
+    BincodeDecode(bincode_crate::error::DecodeError),
+    /// Entry was changed since expected version, see [`VersionedSave`]
+    VersionConflict {
+        /// Version known to writer
+        expected: u64,
+        /// Version of entry in db
+        actual: u64,
+    },
+    /// Message could not be delivered to actor, e.g. it is stopped
+    Mailbox(MailboxError),
+    /// TLS connection to db could not be established, e.g. certificate of
+    /// server is not trusted. Contains message of libpq.
+    Tls(String),
+    /// Blocking task loading entries was cancelled, e.g. runtime is shut
+    /// down. Panic of task is resumed in caller instead.
+    #[cfg(feature = "tokio")]
+    Cancelled(tokio::task::JoinError),
+}
+
+impl std::fmt::Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::Db(e) => write!(f, "database error: {}", e),
+            CacheError::LockPoisoned => write!(f, "cache lock is poisoned"),
+            CacheError::NotFound(what) => write!(f, "not found: {}", what),
+            CacheError::Connection(e) => write!(f, "connection error: {}", e),
+            #[cfg(feature = "r2d2")]
+            CacheError::Pool(e) => write!(f, "connection pool error: {}", e),
+            CacheError::Busy => write!(f, "all connections of pool are busy"),
+            #[cfg(feature = "serde")]
+            CacheError::Serde(e) => write!(f, "serialization error: {}", e),
+            #[cfg(feature = "bincode")]
+            CacheError::BincodeEncode(e) => write!(f, "serialization error: {}", e),
+            #[cfg(feature = "bincode")]
+            CacheError::BincodeDecode(e) => write!(f, "deserialization error: {}", e),
+            CacheError::VersionConflict { expected, actual } => write!(
+                f,
+                "version conflict: expected version {}, actual {}",
+                expected, actual
+            ),
+            CacheError::Mailbox(e) => write!(f, "actor mailbox error: {}", e),
+            CacheError::Tls(msg) => write!(f, "tls error: {}", msg),
+            #[cfg(feature = "tokio")]
+            CacheError::Cancelled(e) => write!(f, "blocking task was cancelled: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CacheError::Db(e) => Some(e),
+            CacheError::Connection(e) => Some(e),
+            #[cfg(feature = "r2d2")]
+            CacheError::Pool(e) => Some(e),
+            #[cfg(feature = "serde")]
+            CacheError::Serde(e) => Some(e),
+            #[cfg(feature = "bincode")]
+            CacheError::BincodeEncode(e) => Some(e),
+            #[cfg(feature = "bincode")]
+            CacheError::BincodeDecode(e) => Some(e),
+            CacheError::Mailbox(e) => Some(e),
+            #[cfg(feature = "tokio")]
+            CacheError::Cancelled(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<diesel::result::Error> for CacheError {
+    fn from(e: diesel::result::Error) -> Self {
+        CacheError::Db(e)
+    }
+}
+
+impl From<diesel::ConnectionError> for CacheError {
+    fn from(e: diesel::ConnectionError) -> Self {
+        CacheError::Connection(e)
+    }
+}
+
+impl From<MailboxError> for CacheError {
+    fn from(e: MailboxError) -> Self {
+        CacheError::Mailbox(e)
+    }
+}
+
+impl CacheError {
+    /// Checks whether error is caused by lost connection to db
+    pub fn is_connection_lost(&self) -> bool {
+        match self {
+            CacheError::Db(diesel::result::Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                _,
+            )) => true,
+            CacheError::Connection(_) => true,
+            #[cfg(feature = "r2d2")]
+            CacheError::Pool(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Makes `?` work on cache errors in request handlers returning
+/// `actix_web::Result`. Missing entries are mapped to `404 Not Found`,
+/// version conflicts to `409 Conflict`, exhausted connection pool to
+/// `503 Service Unavailable` and other errors to `500 Internal Server Error`.
+#[cfg(feature = "actix-web")]
+impl actix_web::ResponseError for CacheError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        use actix_web::http::StatusCode;
+
+        match self {
+            CacheError::Db(diesel::result::Error::NotFound) | CacheError::NotFound(_) => {
+                StatusCode::NOT_FOUND
+            }
+            CacheError::VersionConflict { .. } => StatusCode::CONFLICT,
+            CacheError::Busy => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for CacheError {
+    fn from(e: serde_json::Error) -> Self {
+        CacheError::Serde(e)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl From<bincode_crate::error::EncodeError> for CacheError {
+    fn from(e: bincode_crate::error::EncodeError) -> Self {
+        CacheError::BincodeEncode(e)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl From<bincode_crate::error::DecodeError> for CacheError {
+    fn from(e: bincode_crate::error::DecodeError) -> Self {
+        CacheError::BincodeDecode(e)
+    }
+}
+
+/// Hasher of cache maps with `fast-hash` feature, for
+/// [`CacheDbActorBuilder::with_hasher`]. `FxHasher` is much faster for
+/// integer ids, but is not resistant to hash flooding. Ids come from db, so
+/// it is safe unless they are controlled by untrusted users.
+#[cfg(feature = "fast-hash")]
+pub type FxBuildHasher = std::hash::BuildHasherDefault<rustc_hash::FxHasher>;
+
+/// Hasher of cache maps with `ahash` feature, for
+/// [`CacheDbActorBuilder::with_hasher`]. It is fast for string ids too and
+/// resists hash flooding.
+#[cfg(feature = "ahash")]
+pub use ahash_crate::RandomState as AHashBuildHasher;
+
+/// Map of cached entries by id
+pub type CacheMap<K, V, S = RandomState> = HashMap<K, V, S>;
+
+/// Set of cached ids
+pub type CacheSet<K, S = RandomState> = HashSet<K, S>;
 
 /// Result
-pub type Result<V> = std::result::Result<V, Error>;
+pub type Result<V> = std::result::Result<V, CacheError>;
+
+/// Delete statement for entry with specific id
+pub type DeleteOne<Table, Id> = DeleteStatement<
+    <Find<Table, Id> as HasTable>::Table,
+    <Find<Table, Id> as IntoUpdateTarget>::WhereClause,
+>;
+
+/// Query filtering entries with any of ids
+pub type FilterIds<Table, Id> = Filter<Table, EqAny<<Table as diesel::Table>::PrimaryKey, Vec<Id>>>;
+
+/// Delete statement for entries with any of ids
+pub type DeleteIds<Table, Id> = DeleteStatement<
+    <FilterIds<Table, Id> as HasTable>::Table,
+    <FilterIds<Table, Id> as IntoUpdateTarget>::WhereClause,
+>;
+
+/// Update statement for entry with specific id
+pub type UpdateOne<Table, Id, F> = UpdateStatement<
+    <Find<Table, Id> as HasTable>::Table,
+    <Find<Table, Id> as IntoUpdateTarget>::WhereClause,
+    <F as AsChangeset>::Changeset,
+>;
+
+/// Version of [`VersionedCache`] entry bound in query
+pub type VersionArg = Bound<BigIntSql, i64>;
+
+/// Filter matching version of [`VersionedCache`] entry
+pub type VersionEq<V> = EqOp<V, VersionArg>;
+
+/// Assignment incrementing version of [`VersionedCache`] entry
+pub type VersionBump<V> = EqOp<V, diesel::expression::ops::Add<V, VersionArg>>;
+
+/// Query for entry with specific id, only if it has specific version
+pub type FindVersion<Table, Id, V> = Filter<Find<Table, Id>, VersionEq<V>>;
+
+/// Update statement for entry with specific id and version, which also
+/// increments version
+pub type UpdateVersioned<Table, Id, V, F> = UpdateStatement<
+    <FindVersion<Table, Id, V> as HasTable>::Table,
+    <FindVersion<Table, Id, V> as IntoUpdateTarget>::WhereClause,
+    <(F, VersionBump<V>) as AsChangeset>::Changeset,
+>;
+
+/// Update statement for all entries of table
+pub type UpdateAll<Table, F> = UpdateStatement<
+    <Table as HasTable>::Table,
+    <Table as IntoUpdateTarget>::WhereClause,
+    <F as AsChangeset>::Changeset,
+>;
 
 /// DefaultConnBackend
 pub trait DefaultConnBackend<T: diesel::Table + AsQuery>: Backend {}
@@ -44,32 +419,88 @@ pub trait ReturningConnBackend<T: AsQuery + diesel::Table>:
 {
 }
 
-#[cfg(feature = "sqlite")]
+#[cfg(any(feature = "sqlite", feature = "mysql"))]
 /// ConnBackend
-pub trait ConnBackend<T: diesel::Table + AsQuery>: DefaultConnBackend<T> {}
+pub trait ConnBackend<T: diesel::Table + AsQuery>: DefaultConnBackend<T> {
+    /// Statement limiting duration of following queries in transaction, if
+    /// backend supports it
+    fn timeout_sql(_timeout: Duration) -> Option<String> {
+        None
+    }
+
+    /// Statement restoring limit set by [`ConnBackend::timeout_sql`], if it
+    /// outlives transaction
+    fn reset_timeout_sql() -> Option<&'static str> {
+        None
+    }
+}
 
 #[cfg(feature = "postgres")]
 /// ConnBackend
-pub trait ConnBackend<T: diesel::Table>: ReturningConnBackend<T> {}
+pub trait ConnBackend<T: diesel::Table>: ReturningConnBackend<T> {
+    /// Statement limiting duration of following queries in transaction, if
+    /// backend supports it
+    fn timeout_sql(_timeout: Duration) -> Option<String> {
+        None
+    }
+
+    /// Statement restoring limit set by [`ConnBackend::timeout_sql`], if it
+    /// outlives transaction
+    fn reset_timeout_sql() -> Option<&'static str> {
+        None
+    }
+}
 
 #[cfg(feature = "sqlite")]
 impl<T: diesel::Table + AsQuery> DefaultConnBackend<T> for Sqlite {}
 #[cfg(feature = "postgres")]
 impl<T: diesel::Table + AsQuery> DefaultConnBackend<T> for Pg {}
+#[cfg(feature = "mysql")]
+impl<T: diesel::Table + AsQuery> DefaultConnBackend<T> for Mysql {}
 
 #[cfg(feature = "sqlite")]
-impl<T: diesel::Table + AsQuery> ConnBackend<T> for Sqlite {}
+impl<T: diesel::Table + AsQuery> ConnBackend<T> for Sqlite {
+    fn timeout_sql(timeout: Duration) -> Option<String> {
+        Some(format!("PRAGMA busy_timeout = {}", timeout.as_millis()))
+    }
+
+    fn reset_timeout_sql() -> Option<&'static str> {
+        Some("PRAGMA busy_timeout = 0")
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl<T: diesel::Table + AsQuery> ConnBackend<T> for Mysql {
+    fn timeout_sql(timeout: Duration) -> Option<String> {
+        Some(format!("SET max_execution_time = {}", timeout.as_millis()))
+    }
+
+    fn reset_timeout_sql() -> Option<&'static str> {
+        Some("SET max_execution_time = DEFAULT")
+    }
+}
 
 #[cfg(feature = "postgres")]
 impl<T: diesel::Table + AsQuery> ReturningConnBackend<T> for Pg {}
 
 #[cfg(feature = "postgres")]
-impl<T: diesel::Table + AsQuery> ConnBackend<T> for Pg {}
+impl<T: diesel::Table + AsQuery> ConnBackend<T> for Pg {
+    fn timeout_sql(timeout: Duration) -> Option<String> {
+        Some(format!(
+            "SET LOCAL statement_timeout = {}",
+            timeout.as_millis()
+        ))
+    }
+}
 
 /// Trait for CacheDbActor. Requires at compile time for type to be queryable in
 /// table and database backend.
 ///
 /// Connection backend should have all types in table.
+///
+/// Diesel 1.x has no `alias!`, so to cache renamed table or view declare it
+/// with separate `table!` and `#[sql_name = "..."]` attribute. Such table is
+/// an ordinary diesel table, so the same entry type can be cached for both.
 pub trait Cache<Conn, Table>:
     Queryable<Table::SqlType, Conn::Backend> + Sized + Debug + Clone + 'static
 where
@@ -79,22 +510,161 @@ where
     Table::Query: QueryId + QueryFragment<Conn::Backend>,
 {
     /// Id type for getting specific records
-    type Id: Hash + Eq + Clone;
+    ///
+    /// Id is used as key of the cache map as is, so it is hashed on every
+    /// lookup. If hashing of primary key is expensive, pick faster hasher
+    /// with [`CacheDbActorBuilder::with_hasher`] or use cheaper surrogate
+    /// column as id.
+    ///
+    /// For tables with composite primary key use tuple of key columns, e.g.
+    /// `(i32, i32)`, in the same order as in `table!` declaration.
+    type Id: Hash + Eq + Clone + Debug;
 
     /// Get id of item
     fn get_id(&self) -> Self::Id;
 
+    /// Get id of item, borrowing it when possible. Calls
+    /// [`Cache::get_id`] by default.
+    ///
+    /// Keys of cache map are owned, so borrowed id is still cloned when
+    /// entry is put in cache. Override it with `Cow::Borrowed(&self.id)` when
+    /// `get_id` builds id, e.g. formats it from several fields.
+    fn id_ref(&self) -> Cow<'_, Self::Id> {
+        Cow::Owned(self.get_id())
+    }
+
+    /// Called after every full reload of cache with entries before and
+    /// after reload. Does nothing by default.
+    fn on_refresh<S: BuildHasher>(
+        _old_state: &CacheMap<Self::Id, Self, S>,
+        _new_state: &CacheMap<Self::Id, Self, S>,
+    ) {
+    }
+
     /// Read all entries from db
-    fn read_all(c: &Conn) -> Result<HashMap<Self::Id, Self>> {
+    ///
+    /// To cache only subset of table, override it with filtered query, e.g.
+    /// `shop::table.filter(shop::active.eq(true)).load(c)`. Filter can't be
+    /// an associated type, as associated type defaults are unstable and
+    /// would break existing implementations.
+    fn read_all(c: &Conn) -> Result<CacheMap<Self::Id, Self>> {
+        Self::read_all_with_capacity(c, 0)
+    }
+
+    /// Same as [`Cache::read_all`], with space allocated for at least
+    /// `capacity` entries, e.g. when number of rows is known in advance.
+    /// Cache of actor is allocated with
+    /// [`CacheDbActorBuilder::cache_capacity`] instead.
+    fn read_all_with_capacity(c: &Conn, capacity: usize) -> Result<CacheMap<Self::Id, Self>> {
         let vec: Vec<Self> = Table::table().load(c)?;
-        let mut out = HashMap::with_capacity(vec.len());
+        let mut out =
+            CacheMap::with_capacity_and_hasher(capacity.max(vec.len()), Default::default());
         for it in vec {
-            let id = it.get_id();
+            let id = it.id_ref().into_owned();
             out.insert(id, it);
         }
         Ok(out)
     }
 
+    /// Load all entries for reloads of [`CacheDbActor`], calls
+    /// [`Cache::read_all`] by default. Cache limited with
+    /// [`CacheDbActorBuilder::max_entries`] is loaded with
+    /// [`Cache::read_limited`] instead.
+    ///
+    /// Override it when entries are built from query other than select of
+    /// table, e.g. with columns of joined table:
+    /// `shop::table.inner_join(city::table).select((shop::id, shop::name, city::name)).load(c)`.
+    fn custom_load(c: &Conn) -> Result<Vec<Self>> {
+        Ok(Self::read_all(c)?.into_values().collect())
+    }
+
+    /// Load at most `limit` entries for cache limited with
+    /// [`CacheDbActorBuilder::max_entries`], selects first `limit` rows of
+    /// table by default. Override it together with [`Cache::read_all`] or
+    /// [`Cache::custom_load`], e.g. with the same filter.
+    fn read_limited(c: &Conn, limit: usize) -> Result<Vec<Self>> {
+        Ok(Limited::new(Table::table().as_query(), limit).load(c)?)
+    }
+
+    /// Read entries with any of ids from db. Used by `Get` and `GetMany` to
+    /// load missing entries without full reload of cache, e.g.
+    /// `shop::table.filter(shop::id.eq_any(ids.to_vec())).load(c)`.
+    ///
+    /// It is required, as ids are not always primary key of table, e.g. with
+    /// [`CacheFor`], and composite primary keys can't be compared with
+    /// `eq_any`. `#[derive(Cache)]` queries column named as id field.
+    fn read_many(ids: &[Self::Id], c: &Conn) -> Result<CacheMap<Self::Id, Self>>;
+
+    /// Read one entry from db by id
+    fn read_one(id: &Self::Id, c: &Conn) -> Result<Option<Self>>
+    where
+        Table: FindDsl<Self::Id>,
+        Find<Table, Self::Id>: LimitDsl,
+        Limit<Find<Table, Self::Id>>: LoadQuery<Conn, Self>,
+    {
+        Ok(Table::table()
+            .find(id.clone())
+            .limit(1)
+            .get_result(c)
+            .optional()?)
+    }
+
+    /// Delete one entry from db by id. Returns number of deleted rows.
+    fn delete_one(id: &Self::Id, c: &Conn) -> Result<usize>
+    where
+        Table: FindDsl<Self::Id>,
+        Find<Table, Self::Id>: IntoUpdateTarget,
+        DeleteOne<Table, Self::Id>: ExecuteDsl<Conn>,
+    {
+        Ok(diesel::delete(Table::table().find(id.clone())).execute(c)?)
+    }
+
+    /// Delete entries from db by ids in one query. Returns number of deleted
+    /// rows.
+    fn delete_many<I>(ids: I, c: &Conn) -> Result<usize>
+    where
+        I: IntoIterator<Item = Self::Id>,
+        Table::PrimaryKey: ExpressionMethods,
+        Vec<Self::Id>: AsInExpression<<Table::PrimaryKey as Expression>::SqlType>,
+        Table: FilterDsl<EqAny<Table::PrimaryKey, Vec<Self::Id>>>,
+        FilterIds<Table, Self::Id>: IntoUpdateTarget,
+        DeleteIds<Table, Self::Id>: ExecuteDsl<Conn>,
+    {
+        let ids: Vec<Self::Id> = ids.into_iter().collect();
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let table = Table::table();
+        let filter = table.primary_key().eq_any(ids);
+        Ok(diesel::delete(FilterDsl::filter(table, filter)).execute(c)?)
+    }
+
+    /// Update columns of one entry in db by id. Returns number of updated
+    /// rows.
+    fn update_one_db<F>(id: &Self::Id, changeset: F, c: &Conn) -> Result<usize>
+    where
+        Table: FindDsl<Self::Id>,
+        Find<Table, Self::Id>: IntoUpdateTarget,
+        F: AsChangeset<Target = <Find<Table, Self::Id> as HasTable>::Table>,
+        UpdateOne<Table, Self::Id, F>: AsQuery + ExecuteDsl<Conn>,
+    {
+        Ok(diesel::update(Table::table().find(id.clone()))
+            .set(changeset)
+            .execute(c)?)
+    }
+
+    /// Update columns set by changeset in all rows of table, e.g. with
+    /// changeset deriving `AsChangeset` with only changed fields. Returns
+    /// number of updated rows.
+    fn update_db<F>(changeset: F, c: &Conn) -> Result<usize>
+    where
+        Table: IntoUpdateTarget,
+        F: AsChangeset<Target = Table>,
+        UpdateAll<Table, F>: AsQuery + ExecuteDsl<Conn>,
+    {
+        Ok(diesel::update(Table::table()).set(changeset).execute(c)?)
+    }
+
     #[cfg(feature = "postgres")]
     /// Write one entry to db returning affected row.
     ///
@@ -115,7 +685,34 @@ where
             + SupportsReturningClause
             + HasSqlType<<Table::AllColumns as diesel::Expression>::SqlType>,
     {
-        diesel::insert_into(Table::table()).values(w).get_result(c)
+        Ok(diesel::insert_into(Table::table())
+            .values(w)
+            .get_result(c)?)
+    }
+
+    #[cfg(feature = "sqlite")]
+    /// Write one entry to db returning affected row.
+    ///
+    /// SQLite has no `RETURNING` clause, so row is read back by
+    /// `last_insert_rowid()`. Table should not be declared `WITHOUT ROWID`.
+    /// Rowid is tracked per connection, so inserts through other
+    /// connections in between don't affect returned row.
+    fn write_one_with_result_sqlite<C, W>(w: W, c: &Conn) -> Result<C>
+    where
+        Conn: Connection<Backend = Sqlite>,
+        Table::FromClause: QueryFragment<Conn::Backend>,
+        Table: FilterDsl<SqlLiteral<Bool>>,
+        Filter<Table, SqlLiteral<Bool>>: LimitDsl,
+        Limit<Filter<Table, SqlLiteral<Bool>>>: LoadQuery<Conn, C>,
+        W: Insertable<Table>,
+        W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    {
+        diesel::insert_into(Table::table()).values(w).execute(c)?;
+        let rowid: i64 = diesel::select(sql::<BigInt>("last_insert_rowid()")).get_result(c)?;
+        Ok(Table::table()
+            .filter(sql::<Bool>(&format!("rowid = {}", rowid)))
+            .limit(1)
+            .get_result(c)?)
     }
 
     /// Write one entry to db.
@@ -128,178 +725,3832 @@ where
         W: Insertable<Table>,
         W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
     {
-        diesel::insert_into(Table::table()).values(w).execute(c)
+        Ok(diesel::insert_into(Table::table()).values(w).execute(c)?)
+    }
+
+    /// Insert entry or update existing one on conflict. Returns number of
+    /// affected rows.
+    ///
+    /// Conflict target and updated columns are specific to table, so by
+    /// default entry is just inserted with [`Cache::write_one`]. Override it
+    /// with `ON CONFLICT DO UPDATE` query for your table.
+    fn upsert<W>(w: W, c: &Conn) -> Result<usize>
+    where
+        Table::FromClause: QueryFragment<Conn::Backend>,
+        W: Insertable<Table>,
+        W::Values: CanInsertInSingleQuery<Conn::Backend>
+            + QueryFragment<Conn::Backend>
+            + UndecoratedInsertRecord<Table>,
+    {
+        Self::write_one(w, c)
+    }
+
+    /// Write many entries to db in one query. Returns number of inserted rows.
+    ///
+    /// Entries should be insertable in table in one query.
+    fn write_many<W>(w: Vec<W>, c: &Conn) -> Result<usize>
+    where
+        Table::FromClause: QueryFragment<Conn::Backend>,
+        Vec<W>: Insertable<Table>,
+        <Vec<W> as Insertable<Table>>::Values:
+            CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    {
+        Ok(diesel::insert_into(Table::table()).values(w).execute(c)?)
     }
 }
 
-/// Actix Actor for caching database.
-/// Has fast reads and slow writes. Updates its records once in a minute and on inserts.
-pub struct CacheDbActor<Conn, Table, C>
+/// Entries which are marked deleted in db instead of being removed, see
+/// [`SoftDelete`]. Kept apart from [`Cache`], so sending `SoftDelete` for
+/// entries without it fails to compile.
+///
+/// Soft deleted rows are still in table, so [`Cache::read_all`] should be
+/// overridden to skip them, otherwise they come back on next reload.
+pub trait SoftDeleteCache<Conn, Table>: Cache<Conn, Table>
 where
     Conn: Connection + Unpin + 'static,
     Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
     Table: diesel::Table + HasTable<Table = Table> + AsQuery,
     Table::Query: QueryId + QueryFragment<Conn::Backend>,
-    C: Cache<Conn, Table>,
 {
-    /// Connection for db
-    conn: Conn,
-    /// All items read from db
-    cache: Arc<RwLock<HashMap<C::Id, C>>>,
-    /// Cache valid
-    is_valid: bool,
-    /// Phantom marker for saving table inside structure
-    t: PhantomData<Table>,
+    /// Marks entry deleted in db, e.g. sets its `deleted_at` column. Returns
+    /// number of updated rows.
+    fn soft_delete(id: &Self::Id, c: &Conn) -> Result<usize>;
 }
 
-impl<Conn, Table, C> CacheDbActor<Conn, Table, C>
+/// Entries inserted or updated on conflict with [`UpsertWithResult`]. Kept
+/// apart from [`Cache`], as conflict target and updated columns are specific
+/// to table and plain insert would fail for existing entries.
+#[cfg(feature = "postgres")]
+pub trait UpsertCache<Conn, Table>: Cache<Conn, Table>
 where
     Conn: Connection + Unpin + 'static,
     Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
-    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
     Table::Query: QueryId + QueryFragment<Conn::Backend>,
-    C: Cache<Conn, Table>,
 {
-    /// Constructor
-    pub fn new(conn: Conn) -> Result<Self> {
-        let (cache, t) = Default::default();
-        let mut s = Self {
-            conn,
-            cache,
-            is_valid: true,
-            t,
-        };
-        s.update()?;
-        Ok(s)
-    }
-
-    fn update(&mut self) -> Result<()> {
-        self.cache = Arc::new(RwLock::new(C::read_all(&self.conn)?));
-        Ok(())
-    }
-
-    fn update_one(&mut self, id: C::Id, v: C) -> Option<C> {
-        let mut cache_guard = self.cache.write().unwrap();
-        (*cache_guard).insert(id, v)
-    }
-
-    fn get(&self, id: C::Id) -> Option<C> {
-        let cache_guard = self.cache.read().unwrap();
-        (*cache_guard).get(&id).cloned()
-    }
-
-    fn timer_update(&mut self, context: &mut Context<Self>) {
-        let dur = std::time::Duration::from_secs(60);
-        let _ = self.update();
-        TimerFunc::new(dur, Self::timer_update).spawn(context);
-    }
+    /// Same as [`Cache::upsert`] returning affected row, e.g.
+    /// `.on_conflict(shop::id).do_update()
+    /// .set(shop::name.eq(excluded(shop::name))).get_result(c)`.
+    fn upsert_with_result<W>(w: W, c: &Conn) -> Result<Self>
+    where
+        Table::FromClause: QueryFragment<Conn::Backend>,
+        W: Insertable<Table>,
+        W::Values: CanInsertInSingleQuery<Conn::Backend>
+            + QueryFragment<Conn::Backend>
+            + UndecoratedInsertRecord<Table>,
+        Self: diesel::Queryable<
+            <<Table as diesel::Table>::AllColumns as diesel::Expression>::SqlType,
+            <Conn as diesel::Connection>::Backend,
+        >,
+        Table::AllColumns: QueryFragment<Conn::Backend>,
+        Conn::Backend: ConnBackend<Table>
+            + SupportsReturningClause
+            + HasSqlType<<Table::AllColumns as diesel::Expression>::SqlType>;
 }
 
-impl<Conn, Table, C> Actor for CacheDbActor<Conn, Table, C>
+/// Entries looked up by non-primary field with [`GetBySecondary`]. Several
+/// entries can share the same secondary key.
+///
+/// Index of keys is built on first lookup by secondary key, so entries which
+/// are looked up only by id don't pay for it.
+pub trait SecondaryIndexCache<Conn, Table>: Cache<Conn, Table>
 where
     Conn: Connection + Unpin + 'static,
     Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
-    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
     Table::Query: QueryId + QueryFragment<Conn::Backend>,
-    C: Cache<Conn, Table>,
 {
-    type Context = Context<Self>;
+    /// Secondary key type, e.g. name of shop
+    type SecondaryIndex: Hash + Eq + Clone + Debug;
 
-    fn started(&mut self, context: &mut Context<Self>) {
-        self.timer_update(context)
-    }
+    /// Get secondary key of item. Items without secondary key are not
+    /// indexed.
+    fn secondary_key(&self) -> Option<Self::SecondaryIndex>;
 }
 
-impl<Conn, Table, C> Handler<GetAll<Conn, Table, C>> for CacheDbActor<Conn, Table, C>
+/// Entries with version column for optimistic locking, see [`VersionedSave`].
+pub trait VersionedCache<Conn, Table>: Cache<Conn, Table>
 where
     Conn: Connection + Unpin + 'static,
     Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
-    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
     Table::Query: QueryId + QueryFragment<Conn::Backend>,
-    C: Cache<Conn, Table>,
 {
-    type Result = Result<Arc<RwLock<HashMap<C::Id, C>>>>;
+    /// Column with version of item, e.g. `doc::version`. Its sql type
+    /// should be `BigInt`.
+    type VersionColumn: Column<Table = Table> + Expression<SqlType = BigIntSql>;
 
-    fn handle(&mut self, _: GetAll<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
-        if !self.is_valid {
-            // Flushing not by timer because we are not supposed to have error in
-            // exported data.
-            self.update()?;
-        }
-        Ok(Arc::clone(&self.cache))
-    }
+    /// Column with version of item
+    const VERSION_COLUMN: Self::VersionColumn;
+
+    /// Get version of item
+    fn version(&self) -> u64;
 }
 
-#[cfg(feature = "postgres")]
-impl<Conn, Table, W, C> Handler<SaveWithResult<Conn, Table, W, C>> for CacheDbActor<Conn, Table, C>
+/// Entries with column of last modification time, see [`GetChangedSince`].
+pub trait TimestampedCache<Conn, Table>: Cache<Conn, Table>
 where
     Conn: Connection + Unpin + 'static,
-    Conn::Backend: ConnBackend<Table>
-        + HasSqlType<Table::SqlType>
-        + HasSqlType<<Table::AllColumns as diesel::Expression>::SqlType>,
-    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
     Table::Query: QueryId + QueryFragment<Conn::Backend>,
-    Table::FromClause: QueryFragment<Conn::Backend>,
-    Table::AllColumns: QueryFragment<Conn::Backend>,
-    C: Cache<Conn, Table>
-        + diesel::Queryable<
-            <<Table as diesel::Table>::AllColumns as diesel::Expression>::SqlType,
-            <Conn as diesel::Connection>::Backend,
-        >,
-    W: Insertable<Table>,
-    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
 {
-    type Result = Result<C>;
+    /// Column type, e.g. `shop::updated_at`
+    type TimestampColumn: Column<Table = Table>;
+    /// Value of column, e.g. `std::time::SystemTime` or
+    /// `chrono::NaiveDateTime`
+    type Timestamp: AsExpression<<Self::TimestampColumn as Expression>::SqlType>;
 
-    fn handle(
-        &mut self,
-        pred: SaveWithResult<Conn, Table, W, C>,
-        _: &mut Context<Self>,
-    ) -> Self::Result {
-        let row = C::write_one_with_result(pred.w, &self.conn)?;
-        self.update_one(C::get_id(&row), row.clone());
-        Ok(row)
-    }
+    /// Column of last modification time
+    const TIMESTAMP_COLUMN: Self::TimestampColumn;
 }
 
-impl<Conn, Table, C, W> Handler<Save<W>> for CacheDbActor<Conn, Table, C>
-where
-    Conn: Connection + Unpin + 'static,
-    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
-    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
-    Table::Query: QueryId + QueryFragment<Conn::Backend>,
-    Table::FromClause: QueryFragment<Conn::Backend>,
-    C: Cache<Conn, Table>,
-    W: Insertable<Table> + 'static,
-    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
-{
-    type Result = Result<()>;
+/// When cache actor reloads entries from db, set with
+/// [`CacheDbActorBuilder::refresh_strategy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshStrategy {
+    /// Reload after writes and periodically with interval
+    Timer(Duration),
+    /// Reload only on `Invalidate`. Entries written through actor are not
+    /// seen until then, so use it only when cache is reloaded explicitly.
+    OnDemand,
+    /// Reload after writes only, for tables written only through actor
+    OnWrite,
+}
 
-    fn handle(&mut self, pred: Save<W>, _: &mut Context<Self>) -> Self::Result {
-        self.is_valid = false;
-        C::write_one(pred.0, &self.conn)?;
-        self.update()?;
-        Ok(())
+impl RefreshStrategy {
+    /// Interval of periodic updates
+    pub(crate) fn interval(self) -> Option<Duration> {
+        match self {
+            RefreshStrategy::Timer(dur) => Some(dur),
+            RefreshStrategy::OnDemand | RefreshStrategy::OnWrite => None,
+        }
     }
 }
 
-impl<Conn, Table, C> Handler<Get<Conn, Table, C>> for CacheDbActor<Conn, Table, C>
+/// Actix Actor for caching database.
+/// Has fast reads and slow writes. Updates its records on inserts and
+/// periodically, once in a minute by default.
+///
+/// Cache map is hashed with `S`, see [`CacheDbActorBuilder::with_hasher`].
+pub struct CacheDbActor<Conn, Table, C, S = RandomState>
 where
     Conn: Connection + Unpin + 'static,
     Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
-    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
     Table::Query: QueryId + QueryFragment<Conn::Backend>,
     C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
 {
-    type Result = Result<Option<C>>;
-
-    fn handle(&mut self, Get { id }: Get<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
-        match self.get(id.clone()) {
-            Some(out) => Ok(Some(out)),
+    /// Connection for db
+    conn: ConnSource<Conn>,
+    /// All items read from db
+    cache: Storage<C::Id, C, S>,
+    /// Cache valid
+    is_valid: bool,
+    /// Callback for evicted entries
+    on_evict: Option<fn(C::Id, C)>,
+    /// Usage statistics
+    stats: CacheStats,
+    /// Log every cache operation. Has no effect in release builds.
+    debug_verbose: bool,
+    /// Time when each entry was put in cache
+    entry_timestamps: CacheMap<C::Id, Instant, S>,
+    /// Index by secondary key, built on first lookup by it
+    secondary: Option<SecondaryIndex<C::Id, C>>,
+    /// Sorted ids, built on first `GetRange`
+    #[cfg(feature = "sorted")]
+    sorted: Option<SortedIndex<C::Id>>,
+    /// Age after which entries are reported as old
+    max_entry_age: Option<Duration>,
+    /// When cache is reloaded
+    refresh_strategy: RefreshStrategy,
+    /// Time after last reload when cache is considered invalid on read
+    ttl: Option<Duration>,
+    /// Timeout of queries reloading cache
+    query_timeout: Option<Duration>,
+    /// Time of last reload or initial load
+    last_refresh: Instant,
+    /// Number of entries loaded by last reload
+    loaded_count: usize,
+    /// Number of entries to allocate space for
+    capacity: usize,
+    /// Maximum number of cached entries, least recently used ones are evicted
+    max_entries: Option<usize>,
+    /// Ids from least to most recently used, tracked only with max entries
+    lru: LruQueue<C::Id, S>,
+    /// Number of retries of pool checkout when pool is exhausted
+    pool_checkout_retries: u8,
+    /// Policy of retrying db operations failed with transient errors
+    retry_policy: Option<Arc<dyn RetryPolicy>>,
+    /// Hooks called around handling of messages
+    middlewares: Vec<Arc<dyn CacheMiddleware<Conn, Table, C>>>,
+    /// Serve stale entries from `GetAll` while cache is reloaded
+    stale_while_revalidate: bool,
+    /// Fill ratio of cache map below which periodic update shrinks it
+    shrink_threshold: f64,
+    /// Format of cache dumps
+    #[cfg(any(feature = "serde", feature = "bincode"))]
+    serializer: Serializer,
+    /// Reload of cache is scheduled
+    revalidating: bool,
+    /// Loads entries of scheduled reload in blocking thread pool
+    #[cfg(all(feature = "futures", feature = "tokio"))]
+    spawn_load: Option<SpawnLoad<Conn, C::Id, C, S>>,
+    /// Actor is started by supervisor, so it is restarted when connection
+    /// is lost
+    supervised: bool,
+    /// Number of failed reconnections in a row
+    reconnect_attempts: u32,
+    /// Next periodic update
+    timer: Option<SpawnHandle>,
+    /// Queued db writes
+    pending: Vec<PendingWrite<Conn>>,
+    /// Recipients of cache events
+    subscribers: Vec<Subscriber<CacheEvent<Conn, Table, C>>>,
+    /// Registry where metrics are registered on start
+    #[cfg(feature = "metrics")]
+    metrics_registry: Option<Registry>,
+    /// Prometheus metrics, registered on start
+    #[cfg(feature = "metrics")]
+    metrics: Option<CacheMetrics>,
+    /// Url of database to listen notifications of table changes from
+    #[cfg(feature = "postgres-notify")]
+    notify_url: Option<String>,
+    /// Address shared with running notification listener
+    #[cfg(feature = "postgres-notify")]
+    notify_addr: Option<SharedAddr>,
+    /// Phantom marker for saving table inside structure
+    t: PhantomData<Table>,
+}
+
+// Actor is never pinned in place, its fields are only used through `&mut`,
+// so ids and entries don't have to be `Unpin`.
+impl<Conn, Table, C, S> Unpin for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+}
+
+/// Delay between retries of checkout from exhausted pool
+const POOL_CHECKOUT_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// Result of blocking task of tokio. Panic of task is resumed, so it is not
+/// turned into error of cache.
+#[cfg(feature = "tokio")]
+fn join_blocking<T>(res: std::result::Result<T, tokio::task::JoinError>) -> Result<T> {
+    res.map_err(|e| {
+        if e.is_panic() {
+            std::panic::resume_unwind(e.into_panic());
+        }
+        CacheError::Cancelled(e)
+    })
+}
+
+/// Starts load of entries in blocking thread pool of tokio, see
+/// [`CacheDbActorBuilder::revalidate_in_background`]
+#[cfg(all(feature = "futures", feature = "tokio"))]
+pub(crate) type SpawnLoad<Conn, Id, C, S> =
+    fn(
+        Conn,
+        Option<String>,
+        usize,
+        Option<usize>,
+    ) -> tokio::task::JoinHandle<Result<CacheMap<Id, C, S>>>;
+
+/// Db write queued by [`SaveBehind`]
+type PendingWrite<Conn> = Box<dyn FnOnce(&Conn) -> Result<()>>;
+
+/// Type-erased recipient of cache events. Keeps actor free of `Send` bounds
+/// which `Recipient` requires.
+struct Subscriber<E> {
+    /// Sends event, returns `false` if recipient is dead
+    notify: Box<dyn Fn(E) -> bool>,
+    /// Registered `Recipient`, used to find it on unsubscribe
+    recipient: Box<dyn Any>,
+}
+
+impl<Conn, Table, C> CacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Constructor
+    ///
+    /// Connection is held for the whole lifetime of actor. If it is checked
+    /// out of pool, e.g. `PooledConnection`, it is returned back only when
+    /// actor is dropped, so every running actor takes one connection of pool.
+    /// Use [`CacheDbActor::from_pool`] or
+    /// [`CacheDbActor::from_connection_pool`] instead to check out connection
+    /// for every operation only.
+    pub fn new(conn: Conn) -> Result<Self> {
+        CacheDbActorBuilder::new(conn).build()
+    }
+
+    /// Constructor which loads entries in blocking thread pool of tokio, so
+    /// async caller is not blocked by initial load.
+    #[cfg(feature = "tokio")]
+    pub async fn new_async(conn: Conn) -> Result<Self>
+    where
+        Conn: Send,
+        C: Send,
+        C::Id: Send,
+    {
+        // Actor itself is not `Send`, so only entries are loaded in task
+        let (conn, cache) = join_blocking(
+            tokio::task::spawn_blocking(move || {
+                let cache = Self::load(&conn, 0, None);
+                (conn, cache)
+            })
+            .await,
+        )?;
+        Ok(CacheDbActorBuilder::new(conn).build_preloaded(cache?))
+    }
+
+    /// Constructor which establishes connection to `database_url` and starts
+    /// actor under supervisor. When periodic update finds that connection is
+    /// lost, actor is restarted and connection is reestablished. Failed
+    /// reconnections are retried with exponential backoff.
+    pub fn new_supervised(database_url: &str) -> Result<Addr<Self>> {
+        let mut actor = CacheDbActorBuilder::establish(database_url)?.build()?;
+        actor.supervised = true;
+        Ok(Supervisor::start(move |_| actor))
+    }
+
+    /// Constructor with custom interval of periodic updates. Zero interval
+    /// disables periodic updates.
+    pub fn with_refresh_interval(conn: Conn, refresh_interval: Duration) -> Result<Self> {
+        CacheDbActorBuilder::new(conn)
+            .refresh_interval(refresh_interval)
+            .build()
+    }
+
+    /// Constructor which checks out connection from pool for every operation
+    /// and returns it back right after. Unlike [`CacheDbActor::from_pool`]
+    /// entries are cached for plain `Conn`, not for pooled connection type.
+    #[cfg(feature = "r2d2")]
+    pub fn from_connection_pool(pool: Pool<ConnectionManager<Conn>>) -> Result<Self>
+    where
+        Conn: Send,
+    {
+        CacheDbActorBuilder::from_connection_pool(pool).build()
+    }
+
+    /// Constructor which seeds cache with `data` instead of loading it from
+    /// db. Periodic updates work as usual.
+    pub fn from_preloaded(conn: Conn, data: CacheMap<C::Id, C>) -> Self {
+        CacheDbActorBuilder::new(conn).build_preloaded(data)
+    }
+}
+
+impl<Conn, Table, C, S> CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Creates actor with cloned connection, which shares cache map with this
+    /// one, e.g. to spread reads over several actors. Writes and reloads of
+    /// either actor are visible to both, while statistics, subscribers and
+    /// queued writes are per actor.
+    ///
+    /// With `arc-swap` feature concurrent writes of actors running in
+    /// different threads may overwrite each other.
+    pub fn clone_shared(&self) -> Self
+    where
+        Conn: Clone,
+    {
+        CacheDbActor {
+            conn: self.conn.clone(),
+            cache: self.cache.share(),
+            is_valid: self.is_valid,
+            on_evict: self.on_evict,
+            stats: Default::default(),
+            debug_verbose: self.debug_verbose,
+            entry_timestamps: self.entry_timestamps.clone(),
+            secondary: None,
+            #[cfg(feature = "sorted")]
+            sorted: None,
+            max_entry_age: self.max_entry_age,
+            refresh_strategy: self.refresh_strategy,
+            ttl: self.ttl,
+            query_timeout: self.query_timeout,
+            last_refresh: self.last_refresh,
+            loaded_count: self.loaded_count,
+            capacity: self.capacity,
+            max_entries: self.max_entries,
+            lru: self.lru.clone(),
+            pool_checkout_retries: self.pool_checkout_retries,
+            retry_policy: self.retry_policy.clone(),
+            middlewares: self.middlewares.clone(),
+            stale_while_revalidate: self.stale_while_revalidate,
+            shrink_threshold: self.shrink_threshold,
+            #[cfg(any(feature = "serde", feature = "bincode"))]
+            serializer: self.serializer,
+            revalidating: false,
+            #[cfg(all(feature = "futures", feature = "tokio"))]
+            spawn_load: self.spawn_load,
+            supervised: false,
+            reconnect_attempts: 0,
+            timer: None,
+            pending: Vec::new(),
+            subscribers: Vec::new(),
+            #[cfg(feature = "metrics")]
+            metrics_registry: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "postgres-notify")]
+            notify_url: self.notify_url.clone(),
+            #[cfg(feature = "postgres-notify")]
+            notify_addr: None,
+            t: PhantomData,
+        }
+    }
+
+    /// Number of entries loaded by last reload, zero if cache was never
+    /// loaded from db. Entries written or evicted since are not counted.
+    pub fn entries_count_at_last_refresh(&self) -> usize {
+        self.loaded_count
+    }
+
+    /// Time of last reload, `None` if cache was never loaded from db
+    pub fn last_refresh_instant(&self) -> Option<Instant> {
+        self.stats.last_refresh
+    }
+
+    /// Whether cache is valid, otherwise it is reloaded on next read
+    pub fn is_valid(&self) -> bool {
+        self.is_valid
+    }
+
+    /// Connection for next operation. Checkout from exhausted pool fails at
+    /// once with `CacheError::Busy`, operations run with
+    /// [`with_retry`](Self::with_retry) retry it later.
+    fn conn(&self) -> Result<conn::ConnRef<'_, Conn>> {
+        self.conn.get()
+    }
+
+    fn update(&mut self) -> Result<()> {
+        let res = self.reload();
+        for m in &self.middlewares {
+            m.after_reload(res.is_ok());
+        }
+        res
+    }
+
+    /// Loads entries with [`Cache::custom_load`] into map with space for at
+    /// least `capacity` entries. With `limit` entries are loaded with
+    /// [`Cache::read_limited`] instead.
+    fn load(c: &Conn, capacity: usize, limit: Option<usize>) -> Result<CacheMap<C::Id, C, S>> {
+        let vec = match limit {
+            Some(limit) => {
+                let mut vec = C::read_limited(c, limit)?;
+                // Overridden query may return more rows
+                vec.truncate(limit);
+                vec
+            }
+            None => C::custom_load(c)?,
+        };
+        let mut out =
+            CacheMap::with_capacity_and_hasher(capacity.max(vec.len()), Default::default());
+        for it in vec {
+            let id = it.id_ref().into_owned();
+            out.insert(id, it);
+        }
+        Ok(out)
+    }
+
+    /// Loads all entries, aborting query after query timeout
+    fn load_with_timeout(&self) -> Result<CacheMap<C::Id, C, S>> {
+        let conn = self.conn()?;
+        Self::load_with_sql(&*conn, self.timeout_sql(), self.capacity, self.max_entries)
+    }
+
+    /// Statement setting query timeout, if it is set and supported by backend
+    fn timeout_sql(&self) -> Option<String> {
+        self.query_timeout
+            .and_then(<Conn::Backend as ConnBackend<Table>>::timeout_sql)
+    }
+
+    /// Same as [`Self::load`], executing `timeout_sql` in transaction first.
+    /// Limits set for whole connection are reset after load.
+    fn load_with_sql(
+        conn: &Conn,
+        timeout_sql: Option<String>,
+        capacity: usize,
+        limit: Option<usize>,
+    ) -> Result<CacheMap<C::Id, C, S>> {
+        let sql = match timeout_sql {
+            Some(sql) => sql,
+            None => return Self::load(conn, capacity, limit),
+        };
+        let res = conn.transaction(|| {
+            conn.batch_execute(&sql)?;
+            Self::load(conn, capacity, limit)
+        });
+        if let Some(reset) = <Conn::Backend as ConnBackend<Table>>::reset_timeout_sql() {
+            conn.batch_execute(reset)?;
+        }
+        res
+    }
+
+    /// Executes queued writes before reload, otherwise their entries would
+    /// disappear from cache. Failed writes are already logged and dropped,
+    /// and writes kept in queue when connection is not available are
+    /// retried on next flush, so reload goes on.
+    fn flush_before_reload(&mut self) {
+        if let Err(e) = self.flush() {
+            log::warn!("cache write-behind flush before reload failed: {}", e);
+        }
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        self.flush_before_reload();
+        let started = Instant::now();
+        let cache = self.load_with_timeout()?;
+        self.apply_reload(cache, started)
+    }
+
+    /// Replaces cache with entries loaded by reload started at `_started`
+    fn apply_reload(&mut self, cache: CacheMap<C::Id, C, S>, _started: Instant) -> Result<()> {
+        let mut events = Vec::new();
+        if self.on_evict.is_some() || !self.subscribers.is_empty() {
+            let cache_guard = self.cache.read()?;
+            for (id, v) in (*cache_guard).iter() {
+                if !cache.contains_key(id) {
+                    if let Some(on_evict) = self.on_evict {
+                        on_evict(id.clone(), v.clone());
+                    }
+                    events.push(CacheEvent::Deleted(id.clone()));
+                }
+            }
+            if !self.subscribers.is_empty() {
+                events.extend(
+                    cache
+                        .iter()
+                        .filter(|(id, _)| !(*cache_guard).contains_key(id))
+                        .map(|(_, v)| CacheEvent::Inserted(v.clone())),
+                );
+            }
+        }
+        if self.verbose() {
+            log::debug!("cache RELOAD {} entries", cache.len());
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(rows = cache.len(), "cache reloaded");
+        self.loaded_count = cache.len();
+        let old = self.seed(cache);
+        self.evict_lru()?;
+        C::on_refresh(&*old.read()?, &*self.cache.read()?);
+        self.is_valid = true;
+        self.last_refresh = Instant::now();
+        self.stats.refreshes += 1;
+        self.stats.last_refresh = Some(self.last_refresh);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.refresh(_started.elapsed());
+            metrics.set_entries(self.cache.read()?.len());
+        }
+        for event in events {
+            self.notify(event);
+        }
+        Ok(())
+    }
+
+    /// Replaces all cached entries without touching db. Returns previous
+    /// entries.
+    fn seed(&mut self, mut cache: CacheMap<C::Id, C, S>) -> Storage<C::Id, C, S> {
+        cache.reserve(self.capacity.saturating_sub(cache.len()));
+        let now = Instant::now();
+        self.entry_timestamps = cache.keys().map(|id| (id.clone(), now)).collect();
+        self.secondary = None;
+        #[cfg(feature = "sorted")]
+        {
+            self.sorted = None;
+        }
+        if self.max_entries.is_some() {
+            // Entries which were used before reload keep their order, new ones
+            // are the first to be evicted.
+            let used: Vec<C::Id> = self
+                .lru
+                .take()
+                .into_iter()
+                .filter(|id| cache.contains_key(id))
+                .collect();
+            let used_set: CacheSet<&C::Id> = used.iter().collect();
+            for id in cache.keys().filter(|id| !used_set.contains(id)) {
+                self.lru.touch(id);
+            }
+            for id in &used {
+                self.lru.touch(id);
+            }
+        }
+        self.cache.replace(cache)
+    }
+
+    /// Marks entry as most recently used
+    fn touch(&mut self, id: &C::Id) {
+        if self.max_entries.is_none() {
+            return;
+        }
+        self.lru.touch(id);
+    }
+
+    /// Evicts least recently used entries until cache fits in max entries
+    fn evict_lru(&mut self) -> Result<()> {
+        let max_entries = match self.max_entries {
+            Some(max_entries) => max_entries,
+            None => return Ok(()),
+        };
+        while self.cache.read()?.len() > max_entries {
+            let id = match self.lru.pop() {
+                Some(id) => id,
+                None => break,
+            };
+            if let Some(v) = self.remove_one(&id)? {
+                if let Some(on_evict) = self.on_evict {
+                    on_evict(id, v);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends event to all subscribers and drops dead ones.
+    fn notify(&mut self, event: CacheEvent<Conn, Table, C>) {
+        self.subscribers
+            .retain(|subscriber| (subscriber.notify)(event.clone()));
+    }
+
+    /// Invalidates cache if ttl has passed since last reload
+    fn expire_by_ttl(&mut self) {
+        if let Some(ttl) = self.ttl {
+            if self.last_refresh.elapsed() > ttl {
+                self.is_valid = false;
+            }
+        }
+    }
+
+    /// Reloads cache if it is invalid. Counts read as miss if reload was
+    /// needed and as hit otherwise.
+    fn refresh_if_invalid(&mut self) -> Result<()> {
+        self.expire_by_ttl();
+        if self.is_valid {
+            self.record_read(true);
+            return Ok(());
+        }
+        self.record_read(false);
+        self.update()
+    }
+
+    /// Whether cache was reloaded within acceptable staleness, if it is set
+    fn fresh_within(&self, max_age: Option<Duration>) -> bool {
+        matches!(max_age, Some(max_age) if self.last_refresh.elapsed() <= max_age)
+    }
+
+    /// Reloads invalid or expired cache before lookup, unless it was
+    /// reloaded within `max_age`. Returns whether cache was reloaded.
+    fn reload_before_lookup(&mut self, max_age: Option<Duration>) -> Result<bool> {
+        if self.fresh_within(max_age) {
+            return Ok(false);
+        }
+        self.expire_by_ttl();
+        if self.is_valid {
+            return Ok(false);
+        }
+        self.update()?;
+        Ok(true)
+    }
+
+    /// Loads entry missing in cache with [`Cache::read_many`]
+    fn load_missing(&mut self, id: &C::Id) -> Result<()> {
+        let mut found = C::read_many(std::slice::from_ref(id), &*self.conn()?)?;
+        if let Some(v) = found.remove(id) {
+            self.update_one(id.clone(), v)?;
+        }
+        Ok(())
+    }
+
+    /// Reloads cache after successful write unless reloads are on demand
+    fn reload_after_write(&mut self) -> Result<()> {
+        if self.refresh_strategy == RefreshStrategy::OnDemand {
+            self.is_valid = true;
+            return Ok(());
+        }
+        self.update()
+    }
+
+    /// Schedules reload of invalid cache after current message is handled.
+    /// Does nothing if cache is valid or reload is already scheduled.
+    ///
+    /// Entries are loaded in blocking thread pool if
+    /// [`CacheDbActorBuilder::revalidate_in_background`] is set and
+    /// connection is checked out of pool. Otherwise reload runs on actor
+    /// thread, blocking other messages until it is finished.
+    fn revalidate_later(&mut self, context: &mut Context<Self>) {
+        if self.is_valid || self.revalidating {
+            return;
+        }
+        self.revalidating = true;
+        #[cfg(all(feature = "futures", feature = "tokio"))]
+        if let Some((spawn_load, conn)) = self
+            .spawn_load
+            .and_then(|spawn_load| Some((spawn_load, self.conn.get_owned()?)))
+        {
+            let conn = match conn {
+                Ok(conn) => conn,
+                Err(e) => {
+                    self.revalidating = false;
+                    log::error!("cache revalidation failed: {}", e);
+                    return;
+                }
+            };
+            self.flush_before_reload();
+            let started = Instant::now();
+            let load = spawn_load(conn, self.timeout_sql(), self.capacity, self.max_entries);
+            self.apply_spawned_load(context, load, started, |act, res| {
+                act.revalidating = false;
+                if let Err(e) = res {
+                    log::error!("cache revalidation failed: {}", e);
+                }
+            });
+            return;
+        }
+        context.run_later(Duration::from_secs(0), |act, _| {
+            act.revalidating = false;
+            if let Err(e) = act.update() {
+                log::error!("cache revalidation failed: {}", e);
+            }
+        });
+    }
+
+    /// Applies entries loaded in blocking thread pool when load is finished,
+    /// unless cache was reloaded by other message meanwhile. `done` is
+    /// called with result of reload.
+    #[cfg(all(feature = "futures", feature = "tokio"))]
+    fn apply_spawned_load<F>(
+        &mut self,
+        context: &mut Context<Self>,
+        load: tokio::task::JoinHandle<Result<CacheMap<C::Id, C, S>>>,
+        started: Instant,
+        done: F,
+    ) where
+        F: FnOnce(&mut Self, Result<()>) + 'static,
+    {
+        context.spawn(load.into_actor(self).map(move |res, act, _| {
+            let res = join_blocking(res).and_then(|cache| {
+                let cache = cache?;
+                // Cache reloaded by other message while loading is newer
+                if act.last_refresh > started {
+                    return Ok(());
+                }
+                act.apply_reload(cache, started)
+            });
+            for m in &act.middlewares {
+                m.after_reload(res.is_ok());
+            }
+            done(act, res);
+        }));
+    }
+
+    /// Counts read served from cache or one which needed reload
+    fn record_read(&mut self, hit: bool) {
+        if hit {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.read(hit);
+        }
+    }
+
+    /// Counts write to db
+    fn record_write(&self) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.write();
+        }
+    }
+
+    /// Registers metrics in registry given to builder
+    #[cfg(feature = "metrics")]
+    fn register_metrics(&mut self) {
+        let registry = match self.metrics_registry.take() {
+            Some(registry) => registry,
+            None => return,
+        };
+        match CacheMetrics::register(&registry, table_label::<Table>()) {
+            Ok(metrics) => {
+                metrics.set_entries(self.cache.read().map_or(0, |cache| cache.len()));
+                self.metrics = Some(metrics);
+            }
+            Err(e) => log::error!("cache metrics registration failed: {}", e),
+        }
+    }
+
+    fn update_one(&mut self, id: C::Id, v: C) -> Result<Option<C>> {
+        if self.verbose() {
+            log::debug!("cache SAVE value={:#?}", v);
+        }
+        #[cfg(feature = "tracing")]
+        if self.verbose() {
+            tracing::trace!(id = ?id, "cache save");
+        }
+        self.entry_timestamps.insert(id.clone(), Instant::now());
+        self.touch(&id);
+        let event = if self.subscribers.is_empty() {
+            None
+        } else {
+            Some(v.clone())
+        };
+        let old = self.cache.modify(|cache| cache.insert(id.clone(), v))?;
+        self.reindex(&id, old.as_ref())?;
+        if let Some(v) = event {
+            self.notify(match old {
+                Some(_) => CacheEvent::Updated(v),
+                None => CacheEvent::Inserted(v),
+            });
+        }
+        self.evict_lru()?;
+        Ok(old)
+    }
+
+    /// Moves id in secondary index from key of replaced entry to key of entry
+    /// which is now cached
+    fn reindex(&mut self, id: &C::Id, old: Option<&C>) -> Result<()> {
+        #[cfg(feature = "sorted")]
+        if let (Some(index), None) = (&mut self.sorted, old) {
+            index.insert(id);
+        }
+        let index = match &mut self.secondary {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        if let Some(old) = old {
+            index.remove(id, old);
+        }
+        if let Some(v) = (*self.cache.read()?).get(id) {
+            index.insert(id, v);
+        }
+        Ok(())
+    }
+
+    /// Puts entries to cache in one write of the map. Returns number of put
+    /// entries.
+    fn merge(&mut self, entries: CacheMap<C::Id, C>) -> Result<usize> {
+        let entries: Vec<(C::Id, C)> = entries.into_iter().collect();
+        let ids: Vec<C::Id> = entries.iter().map(|(id, _)| id.clone()).collect();
+        let events: Vec<C> = if self.subscribers.is_empty() {
+            Vec::new()
+        } else {
+            entries.iter().map(|(_, v)| v.clone()).collect()
+        };
+        let old: Vec<Option<C>> = self.cache.modify(|cache| {
+            entries
+                .into_iter()
+                .map(|(id, v)| cache.insert(id, v))
+                .collect()
+        })?;
+        let now = Instant::now();
+        for (id, old) in ids.into_iter().zip(&old) {
+            self.entry_timestamps.insert(id.clone(), now);
+            self.touch(&id);
+            self.reindex(&id, old.as_ref())?;
+        }
+        for (v, old) in events.into_iter().zip(&old) {
+            self.notify(match old {
+                Some(_) => CacheEvent::Updated(v),
+                None => CacheEvent::Inserted(v),
+            });
+        }
+        self.evict_lru()?;
+        Ok(old.len())
+    }
+
+    fn verbose(&self) -> bool {
+        cfg!(debug_assertions) && self.debug_verbose
+    }
+
+    fn remove_one(&mut self, id: &C::Id) -> Result<Option<C>> {
+        self.entry_timestamps.remove(id);
+        self.lru.remove(id);
+        let old = self.cache.modify(|cache| cache.remove(id))?;
+        if let (Some(index), Some(old)) = (&mut self.secondary, &old) {
+            index.remove(id, old);
+        }
+        #[cfg(feature = "sorted")]
+        if let (Some(index), Some(_)) = (&mut self.sorted, &old) {
+            index.remove(id);
+        }
+        if old.is_some() {
+            self.notify(CacheEvent::Deleted(id.clone()));
+        }
+        Ok(old)
+    }
+
+    /// Removes entries from cache in one write of the map. Returns number of
+    /// removed entries.
+    fn remove_many(&mut self, ids: &[C::Id]) -> Result<usize> {
+        let removed: Vec<(C::Id, C)> = self.cache.modify(|cache| {
+            ids.iter()
+                .filter_map(|id| cache.remove(id).map(|v| (id.clone(), v)))
+                .collect()
+        })?;
+        for (id, v) in &removed {
+            self.entry_timestamps.remove(id);
+            self.lru.remove(id);
+            if let Some(index) = &mut self.secondary {
+                index.remove(id, v);
+            }
+            #[cfg(feature = "sorted")]
+            if let Some(index) = &mut self.sorted {
+                index.remove(id);
+            }
+            self.notify(CacheEvent::Deleted(id.clone()));
+        }
+        Ok(removed.len())
+    }
+
+    fn get(&self, id: C::Id) -> Result<Option<C>> {
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard).get(&id).cloned())
+    }
+
+    fn get_with_timestamp(&self, id: &C::Id) -> Result<Option<(C, Instant)>> {
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard)
+            .get(id)
+            .cloned()
+            .zip(self.entry_timestamps.get(id).copied()))
+    }
+
+    fn contains(&self, id: &C::Id) -> Result<bool> {
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard).contains_key(id))
+    }
+
+    fn get_many(&self, ids: &[C::Id]) -> Result<CacheMap<C::Id, C>> {
+        let cache_guard = self.cache.read()?;
+        Ok(ids
+            .iter()
+            .filter_map(|id| (*cache_guard).get(id).map(|it| (id.clone(), it.clone())))
+            .collect())
+    }
+
+    /// Gets entries by ids, missing ones are loaded with one
+    /// [`Cache::read_many`] query
+    fn get_many_or_load(&mut self, ids: &[C::Id]) -> Result<CacheMap<C::Id, C>> {
+        let out = self.get_many(ids)?;
+        if ids.iter().all(|id| out.contains_key(id)) {
+            self.record_read(true);
+            return Ok(out);
+        }
+        self.record_read(false);
+        let missing: Vec<C::Id> = ids
+            .iter()
+            .filter(|id| !out.contains_key(id))
+            .cloned()
+            .collect();
+        let found = C::read_many(&missing, &*self.conn()?)?;
+        self.merge(found)?;
+        self.get_many(ids)
+    }
+
+    fn evict_older_than(&mut self, max_age: Duration) -> Result<usize> {
+        let stale: Vec<C::Id> = self
+            .entry_timestamps
+            .iter()
+            .filter(|(_, inserted)| inserted.elapsed() > max_age)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &stale {
+            if let Some(v) = self.remove_one(id)? {
+                if let Some(on_evict) = self.on_evict {
+                    on_evict(id.clone(), v);
+                }
+            }
+        }
+        Ok(stale.len())
+    }
+
+    /// Releases memory of cache map beyond configured capacity
+    fn shrink(&self) -> Result<()> {
+        let capacity = self.capacity;
+        self.cache.modify(|cache| {
+            cache.shrink_to_fit();
+            cache.reserve(capacity.saturating_sub(cache.len()));
+        })
+    }
+
+    /// Shrinks cache map if it is filled less than shrink threshold
+    fn shrink_if_sparse(&self) -> Result<()> {
+        let (len, allocated) = {
+            let cache = self.cache.read()?;
+            (cache.len(), cache.capacity())
+        };
+        if allocated > self.capacity && (len as f64) < allocated as f64 * self.shrink_threshold {
+            self.shrink()?;
+        }
+        Ok(())
+    }
+
+    /// Cancels timer, detaches notification listener and flushes queued
+    /// writes. Safe to call several times, second call has nothing to do.
+    fn release(&mut self, context: &mut Context<Self>) {
+        if let Some(timer) = self.timer.take() {
+            context.cancel_future(timer);
+        }
+        #[cfg(feature = "postgres-notify")]
+        if let Some(addr) = self.notify_addr.take() {
+            *addr.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+        // Queued writes would be lost otherwise. Actor stops even if flush
+        // fails, so that it doesn't hang shutdown.
+        if let Err(e) = self.flush() {
+            log::error!("cache flush on stop failed: {}", e);
+            #[cfg(feature = "tracing")]
+            tracing::error!(error = %e, "cache flush on stop failed");
+        }
+    }
+
+    /// Warns about entries which are in cache for longer than max entry age.
+    fn warn_old_entries(&self) {
+        let max_age = match self.max_entry_age {
+            Some(max_age) => max_age,
+            None => return,
+        };
+        for (id, inserted) in self.entry_timestamps.iter() {
+            let age = inserted.elapsed();
+            if age > max_age {
+                log::warn!(
+                    "cache entry {:?} is {} seconds old, consider refreshing",
+                    id,
+                    age.as_secs()
+                );
+            }
+        }
+    }
+
+    /// Delay before retry of operation failed with `e` for `attempt` time,
+    /// if retry policy allows it
+    fn retry_delay(&self, e: &CacheError, attempt: u32) -> Option<Duration> {
+        match (e, &self.retry_policy) {
+            (CacheError::Busy, _) if attempt <= u32::from(self.pool_checkout_retries) => {
+                Some(POOL_CHECKOUT_RETRY_DELAY)
+            }
+            (CacheError::Db(e), Some(policy)) if policy.should_retry(e, attempt) => {
+                log::warn!("cache db operation failed, retrying: {}", e);
+                Some(policy.delay(attempt))
+            }
+            _ => None,
+        }
+    }
+
+    /// Runs db operation and retries it after delay while it fails with
+    /// errors which retry policy considers transient. Other messages are
+    /// handled between attempts.
+    fn with_retry<R, F>(&mut self, mut op: F, attempt: u32) -> ResponseActFuture<Self, Result<R>>
+    where
+        R: 'static,
+        F: FnMut(&mut Self) -> Result<R> + 'static,
+    {
+        let res = op(self);
+        let delay = match res
+            .as_ref()
+            .err()
+            .and_then(|e| self.retry_delay(e, attempt))
+        {
+            Some(delay) => delay,
+            None => return Box::pin(actix::fut::ready(res)),
+        };
+        Box::pin(
+            actix::clock::delay_for(delay)
+                .into_actor(self)
+                .then(move |_, act, _| act.with_retry(op, attempt + 1)),
+        )
+    }
+
+    /// Schedules retry of failed periodic update if retry policy allows it
+    fn retry_update_later(&mut self, e: &CacheError, attempt: u32, context: &mut Context<Self>) {
+        if let Some(delay) = self.retry_delay(e, attempt) {
+            context.run_later(delay, move |act, context| {
+                if let Err(e) = act.update() {
+                    act.retry_update_later(&e, attempt + 1, context);
+                }
+            });
+        }
+    }
+
+    fn timer_update(&mut self, context: &mut Context<Self>) {
+        if let Err(e) = self.update() {
+            // While reconnecting, restart is already scheduled with backoff
+            if self.supervised && self.reconnect_attempts == 0 && e.is_connection_lost() {
+                log::warn!("cache lost connection to db, restarting: {}", e);
+                context.stop();
+                return;
+            }
+            self.retry_update_later(&e, 1, context);
+        }
+        if let Err(e) = self.shrink_if_sparse() {
+            log::warn!("failed to shrink cache: {}", e);
+        }
+        self.warn_old_entries();
+        if let Some(dur) = self.refresh_strategy.interval() {
+            self.timer = Some(context.spawn(TimerFunc::new(dur, Self::timer_update)));
+        }
+    }
+}
+
+#[cfg(all(feature = "futures", feature = "tokio"))]
+impl<Conn, Table, C, S> CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + Send + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send,
+    C::Id: Send,
+    S: BuildHasher + Default + Clone + Send + 'static,
+{
+    /// Loads entries on owned connection in blocking thread pool of tokio
+    pub(crate) fn spawn_load(
+        conn: Conn,
+        timeout_sql: Option<String>,
+        capacity: usize,
+        limit: Option<usize>,
+    ) -> tokio::task::JoinHandle<Result<CacheMap<C::Id, C, S>>> {
+        tokio::task::spawn_blocking(move || {
+            Self::load_with_sql(&conn, timeout_sql, capacity, limit)
+        })
+    }
+}
+
+#[cfg(feature = "r2d2")]
+impl<M, Table, C> CacheDbActor<PooledConnection<M>, Table, C>
+where
+    M: ManageConnection,
+    PooledConnection<M>: Connection + Unpin + 'static,
+    <PooledConnection<M> as Connection>::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<<PooledConnection<M> as Connection>::Backend>,
+    C: Cache<PooledConnection<M>, Table>,
+{
+    /// Constructor which checks out fresh connection from pool for every
+    /// operation, so actor survives dropped connections.
+    pub fn from_pool(pool: Pool<M>) -> Result<Self> {
+        CacheDbActorBuilder::from_pool(pool).build()
+    }
+}
+
+#[cfg(any(feature = "serde", feature = "bincode"))]
+impl<Conn, Table, C, S> CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Serializable,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Serializes all cached entries in format set with
+    /// [`CacheDbActorBuilder::with_serializer`]. Only entries are stored, ids
+    /// are restored with [`Cache::get_id`], so composite ids are supported.
+    pub fn serialize_cache(&self) -> Result<Vec<u8>> {
+        let cache_guard = self.cache.read()?;
+        let entries: Vec<&C> = (*cache_guard).values().collect();
+        self.serializer.encode(&entries)
+    }
+
+    /// Replaces cached entries with ones serialized by
+    /// [`CacheDbActor::serialize_cache`]. Cache is considered valid until
+    /// next update.
+    pub fn restore_cache(&mut self, bytes: &[u8]) -> Result<()> {
+        let entries: Vec<C> = self.serializer.decode(bytes)?;
+        self.seed(entries.into_iter().map(|it| (it.get_id(), it)).collect());
+        self.is_valid = true;
+        Ok(())
+    }
+}
+
+/// Same as [`CacheDbActor::clone_shared`], clones share cache map
+impl<Conn, Table, C, S> Clone for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Clone + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn clone(&self) -> Self {
+        self.clone_shared()
+    }
+}
+
+/// Same as [`CacheDbActor::new`] with default connection, e.g. for test
+/// harnesses or `SystemService`. Panics if entries can't be loaded.
+#[cfg(feature = "default-impl")]
+impl<Conn, Table, C, S> Default for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Default + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn default() -> Self {
+        CacheDbActorBuilder::new(Conn::default())
+            .with_hasher()
+            .build()
+            .expect("default construction of cache actor failed")
+    }
+}
+
+impl<Conn, Table, C, S> Actor for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Context = Context<Self>;
+
+    fn started(&mut self, context: &mut Context<Self>) {
+        #[cfg(feature = "metrics")]
+        self.register_metrics();
+        #[cfg(feature = "postgres-notify")]
+        if let Some(url) = self.notify_url.clone() {
+            let addr = Arc::new(std::sync::Mutex::new(Some(context.address().recipient())));
+            self.notify_addr = Some(Arc::clone(&addr));
+            actix::spawn(notify::listen(url, table_label::<Table>(), addr));
+        }
+        // Entries are loaded or seeded on build, so first update waits for
+        // interval
+        if let Some(dur) = self.refresh_strategy.interval() {
+            self.timer = Some(context.spawn(TimerFunc::new(dur, Self::timer_update)));
+        }
+    }
+
+    fn stopping(&mut self, context: &mut Context<Self>) -> Running {
+        self.release(context);
+        Running::Stop
+    }
+
+    // `stopping` is skipped when arbiter goes down without asking actor to
+    // stop, e.g. after panic in other actor, so cleanup is repeated here.
+    fn stopped(&mut self, context: &mut Context<Self>) {
+        self.release(context);
+        let entry_count = self.cache.read().map_or(0, |cache| cache.len());
+        log::info!("cache stopped with {} entries in memory", entry_count);
+        #[cfg(feature = "tracing")]
+        tracing::info!(entry_count, "cache stopped");
+    }
+}
+
+impl<Conn, Table, C, S> Supervised for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn restarting(&mut self, context: &mut Context<Self>) {
+        self.revalidating = false;
+        match self.conn.reconnect() {
+            Ok(()) => {
+                self.reconnect_attempts = 0;
+                self.is_valid = false;
+            }
+            Err(e) => {
+                self.reconnect_attempts += 1;
+                // 100ms, 200ms, 400ms, ... up to about a minute
+                let exponent = self.reconnect_attempts.min(10) - 1;
+                let backoff = Duration::from_millis(100) * 2u32.pow(exponent);
+                log::error!(
+                    "cache reconnection failed {} times, retrying in {:?}: {}",
+                    self.reconnect_attempts,
+                    backoff,
+                    e
+                );
+                context.run_later(backoff, |_, context| context.stop());
+            }
+        }
+    }
+}
+
+impl<Conn, Table, C, S> CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Executes queued db writes. All writes are attempted, first error is
+    /// returned. Failed writes are logged and dropped.
+    fn flush(&mut self) -> Result<usize> {
+        if self.pending.is_empty() {
+            return Ok(0);
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let conn = match self.conn.get() {
+            Ok(conn) => conn,
+            Err(e) => {
+                self.pending = pending;
+                return Err(e);
+            }
+        };
+        let mut out = Ok(pending.len());
+        for write in pending {
+            if let Err(e) = write(&conn) {
+                log::error!("cache write-behind failed: {}", e);
+                if out.is_ok() {
+                    out = Err(e);
+                }
+            }
+        }
+        out
+    }
+}
+
+// Actor is dropped without `stopping` and `stopped` when system stops, so
+// queued writes are flushed here as last resort.
+impl<Conn, Table, C, S> Drop for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::error!("cache flush on drop failed: {}", e);
+        }
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetAll<Conn, Table, C, S>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Arc<RwLock<CacheMap<C::Id, C, S>>>>;
+
+    fn handle(
+        &mut self,
+        msg: GetAll<Conn, Table, C, S>,
+        context: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetAll");
+        if self.fresh_within(msg.max_age) {
+            self.record_read(true);
+            return Ok(self.cache.shared());
+        }
+        self.expire_by_ttl();
+        if self.stale_while_revalidate && !self.is_valid {
+            self.record_read(false);
+            self.revalidate_later(context);
+            return Ok(self.cache.shared());
+        }
+        // Flushing not by timer because we are not supposed to have error in
+        // exported data.
+        self.refresh_if_invalid()?;
+        Ok(self.cache.shared())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<BorrowCache<Conn, Table, C, S>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<CacheReadGuard<Conn, Table, C, S>>;
+
+    fn handle(&mut self, _: BorrowCache<Conn, Table, C, S>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("BorrowCache");
+        self.refresh_if_invalid()?;
+        Ok(CacheReadGuard {
+            map: self.cache.shared(),
+        })
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Checkpoint<Conn, Table, C, S>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<CacheCheckpointToken<Conn, Table, C, S>>;
+
+    fn handle(&mut self, _: Checkpoint<Conn, Table, C, S>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Checkpoint");
+        Ok(CacheCheckpointToken {
+            entries: self.cache.snapshot()?,
+        })
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Restore<Conn, Table, C, S>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        Restore(token): Restore<Conn, Table, C, S>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Restore");
+        let cache =
+            Arc::try_unwrap(token.entries).unwrap_or_else(|shared| CacheMap::clone(&shared));
+        self.seed(cache);
+        self.is_valid = false;
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Snapshot<Conn, Table, C, S>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<CacheSnapshot<Conn, Table, C, S>>;
+
+    fn handle(&mut self, _: Snapshot<Conn, Table, C, S>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Snapshot");
+        self.refresh_if_invalid()?;
+        Ok(CacheSnapshot::new(self.cache.snapshot()?))
+    }
+}
+
+impl<Conn, Table, C, S> Handler<ValidateCache<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + PartialEq,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<CacheDrift<Conn, Table, C>>>;
+
+    fn handle(&mut self, _: ValidateCache<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("ValidateCache");
+        let mut db = Self::load(&*self.conn()?, 0, None)?;
+        let cache_guard = self.cache.read()?;
+        let mut out = Vec::new();
+        for (id, cached) in (*cache_guard).iter() {
+            match db.remove(id) {
+                None => out.push(CacheDrift::MissingInDb(id.clone())),
+                Some(db_value) if db_value != *cached => out.push(CacheDrift::ValueMismatch {
+                    cached: cached.clone(),
+                    db_value,
+                }),
+                Some(_) => {}
+            }
+        }
+        out.extend(db.into_values().map(CacheDrift::MissingInCache));
+        Ok(out)
+    }
+}
+
+#[cfg(any(feature = "serde", feature = "bincode"))]
+impl<Conn, Table, C, S> Handler<DumpCache> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Serializable,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<u8>>;
+
+    fn handle(&mut self, _: DumpCache, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("DumpCache");
+        self.serialize_cache()
+    }
+}
+
+#[cfg(any(feature = "serde", feature = "bincode"))]
+impl<Conn, Table, C, S> Handler<LoadCache> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Serializable,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, LoadCache(bytes): LoadCache, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("LoadCache");
+        self.restore_cache(&bytes)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<SetRefreshInterval> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(
+        &mut self,
+        SetRefreshInterval(dur): SetRefreshInterval,
+        context: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("SetRefreshInterval");
+        if let Some(timer) = self.timer.take() {
+            context.cancel_future(timer);
+        }
+        self.refresh_strategy = if dur == Duration::from_secs(0) {
+            RefreshStrategy::OnWrite
+        } else {
+            self.timer = Some(context.spawn(TimerFunc::new(dur, Self::timer_update)));
+            RefreshStrategy::Timer(dur)
+        };
+        Ok(())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<BulkLoad<C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<usize>;
+
+    fn handle(&mut self, BulkLoad(entries): BulkLoad<C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("BulkLoad");
+        let cache: CacheMap<C::Id, C, S> =
+            entries.into_iter().map(|it| (it.get_id(), it)).collect();
+        let len = cache.len();
+        self.seed(cache);
+        self.evict_lru()?;
+        self.is_valid = true;
+        Ok(len)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Peek<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Option<C>>;
+
+    fn handle(&mut self, Peek { id }: Peek<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Peek");
+        let out = (*self.cache.read()?).get(&id).cloned();
+        self.record_read(out.is_some());
+        Ok(out)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<PeekMany<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<CacheMap<C::Id, Option<C>>>;
+
+    fn handle(
+        &mut self,
+        PeekMany(ids): PeekMany<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("PeekMany");
+        let out: CacheMap<C::Id, Option<C>> = {
+            let cache_guard = self.cache.read()?;
+            ids.into_iter()
+                .map(|id| {
+                    let v = (*cache_guard).get(&id).cloned();
+                    (id, v)
+                })
+                .collect()
+        };
+        for v in out.values() {
+            self.record_read(v.is_some());
+        }
+        Ok(out)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetAllVec<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(&mut self, _: GetAllVec<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("GetAllVec");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard).values().cloned().collect())
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<Conn, Table, C, S> Handler<StreamAll<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = ResponseFuture<Result<usize>>;
+
+    fn handle(
+        &mut self,
+        StreamAll {
+            mut tx, batch_size, ..
+        }: StreamAll<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("StreamAll");
+        let ids = self
+            .refresh_if_invalid()
+            .and_then(|()| Ok(self.cache.read()?.keys().cloned().collect::<Vec<_>>()));
+        let cache = self.cache.share();
+        Box::pin(async move {
+            let mut sent = 0;
+            for ids in ids?.chunks(batch_size.max(1)) {
+                // Lock is released before sending, as receiver may be slow
+                let batch: Vec<C> = {
+                    let cache_guard = cache.read()?;
+                    ids.iter()
+                        .filter_map(|id| cache_guard.get(id).cloned())
+                        .collect()
+                };
+                for it in batch {
+                    if tx.send(it).await.is_err() {
+                        return Ok(sent);
+                    }
+                    sent += 1;
+                }
+            }
+            Ok(sent)
+        })
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl<Conn, Table, W, C, S> Handler<UpsertWithResult<Conn, Table, W, C>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table>
+        + SupportsReturningClause
+        + HasSqlType<Table::SqlType>
+        + HasSqlType<<Table::AllColumns as diesel::Expression>::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    Table::AllColumns: QueryFragment<Conn::Backend>,
+    C: UpsertCache<Conn, Table>
+        + diesel::Queryable<
+            <<Table as diesel::Table>::AllColumns as diesel::Expression>::SqlType,
+            <Conn as diesel::Connection>::Backend,
+        >,
+    W: Insertable<Table>,
+    W::Values: CanInsertInSingleQuery<Conn::Backend>
+        + QueryFragment<Conn::Backend>
+        + UndecoratedInsertRecord<Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<C>;
+
+    fn handle(
+        &mut self,
+        pred: UpsertWithResult<Conn, Table, W, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("UpsertWithResult");
+        self.record_write();
+        let row = C::upsert_with_result(pred.w, &*self.conn()?)?;
+        self.update_one(row.id_ref().into_owned(), row.clone())?;
+        Ok(row)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl<Conn, Table, W, C, S> Handler<SaveWithResult<Conn, Table, W, C>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table>
+        + HasSqlType<Table::SqlType>
+        + HasSqlType<<Table::AllColumns as diesel::Expression>::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    Table::AllColumns: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>
+        + diesel::Queryable<
+            <<Table as diesel::Table>::AllColumns as diesel::Expression>::SqlType,
+            <Conn as diesel::Connection>::Backend,
+        >,
+    W: Insertable<Table>,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<C>;
+
+    fn handle(
+        &mut self,
+        pred: SaveWithResult<Conn, Table, W, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("SaveWithResult");
+        self.record_write();
+        let row: C = C::write_one_with_result(pred.w, &*self.conn()?)?;
+        self.update_one(row.id_ref().into_owned(), row.clone())?;
+        Ok(row)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<Conn, Table, W, C, S> Handler<SaveWithResult<Conn, Table, W, C>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection<Backend = Sqlite> + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    Table: FilterDsl<SqlLiteral<Bool>>,
+    Filter<Table, SqlLiteral<Bool>>: LimitDsl,
+    Limit<Filter<Table, SqlLiteral<Bool>>>: LoadQuery<Conn, C>,
+    C: Cache<Conn, Table>,
+    W: Insertable<Table>,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<C>;
+
+    fn handle(
+        &mut self,
+        pred: SaveWithResult<Conn, Table, W, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("SaveWithResult");
+        self.record_write();
+        let row = C::write_one_with_result_sqlite(pred.w, &*self.conn()?)?;
+        self.update_one(row.id_ref().into_owned(), row.clone())?;
+        Ok(row)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl<Conn, Table, W, C, S> Handler<GetOrInsert<Conn, Table, W, C>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table>
+        + HasSqlType<Table::SqlType>
+        + HasSqlType<<Table::AllColumns as diesel::Expression>::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    Table::AllColumns: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>
+        + diesel::Queryable<
+            <<Table as diesel::Table>::AllColumns as diesel::Expression>::SqlType,
+            <Conn as diesel::Connection>::Backend,
+        >,
+    W: Insertable<Table>,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<C>;
+
+    fn handle(
+        &mut self,
+        GetOrInsert { id, value }: GetOrInsert<Conn, Table, W, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetOrInsert");
+        if let Some(existing) = self.get(id)? {
+            self.record_read(true);
+            return Ok(existing);
+        }
+        self.record_write();
+        let row: C = C::write_one_with_result(value, &*self.conn()?)?;
+        self.update_one(row.id_ref().into_owned(), row.clone())?;
+        Ok(row)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl<Conn, Table, W, C, S> Handler<EntryOrInsert<Conn, Table, W, C>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table>
+        + HasSqlType<Table::SqlType>
+        + HasSqlType<<Table::AllColumns as diesel::Expression>::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    Table::AllColumns: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>
+        + diesel::Queryable<
+            <<Table as diesel::Table>::AllColumns as diesel::Expression>::SqlType,
+            <Conn as diesel::Connection>::Backend,
+        >,
+    W: Insertable<Table>,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<(C, bool)>;
+
+    fn handle(
+        &mut self,
+        EntryOrInsert { id, factory }: EntryOrInsert<Conn, Table, W, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("EntryOrInsert");
+        if let Some(existing) = self.get(id)? {
+            self.record_read(true);
+            return Ok((existing, false));
+        }
+        self.record_write();
+        let row: C = C::write_one_with_result(factory(), &*self.conn()?)?;
+        self.update_one(row.id_ref().into_owned(), row.clone())?;
+        Ok((row, true))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<Conn, Table, W, C, S> Handler<GetOrInsert<Conn, Table, W, C>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection<Backend = Sqlite> + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    Table: FilterDsl<SqlLiteral<Bool>>,
+    Filter<Table, SqlLiteral<Bool>>: LimitDsl,
+    Limit<Filter<Table, SqlLiteral<Bool>>>: LoadQuery<Conn, C>,
+    C: Cache<Conn, Table>,
+    W: Insertable<Table>,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<C>;
+
+    fn handle(
+        &mut self,
+        GetOrInsert { id, value }: GetOrInsert<Conn, Table, W, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetOrInsert");
+        if let Some(existing) = self.get(id)? {
+            self.record_read(true);
+            return Ok(existing);
+        }
+        self.record_write();
+        let row = C::write_one_with_result_sqlite(value, &*self.conn()?)?;
+        self.update_one(row.id_ref().into_owned(), row.clone())?;
+        Ok(row)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<Conn, Table, W, C, S> Handler<EntryOrInsert<Conn, Table, W, C>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection<Backend = Sqlite> + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    Table: FilterDsl<SqlLiteral<Bool>>,
+    Filter<Table, SqlLiteral<Bool>>: LimitDsl,
+    Limit<Filter<Table, SqlLiteral<Bool>>>: LoadQuery<Conn, C>,
+    C: Cache<Conn, Table>,
+    W: Insertable<Table>,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<(C, bool)>;
+
+    fn handle(
+        &mut self,
+        EntryOrInsert { id, factory }: EntryOrInsert<Conn, Table, W, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("EntryOrInsert");
+        if let Some(existing) = self.get(id)? {
+            self.record_read(true);
+            return Ok((existing, false));
+        }
+        self.record_write();
+        let row = C::write_one_with_result_sqlite(factory(), &*self.conn()?)?;
+        self.update_one(row.id_ref().into_owned(), row.clone())?;
+        Ok((row, true))
+    }
+}
+
+impl<Conn, Table, C, W, S> Handler<Save<W>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    W: Insertable<Table> + 'static,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, pred: Save<W>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Save");
+        self.record_write();
+        for m in &self.middlewares {
+            m.before_save();
+        }
+        self.is_valid = false;
+        let res = self
+            .conn()
+            .and_then(|conn| C::write_one(pred.0, &*conn))
+            .and_then(|_rows| {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(rows = _rows, "cache save");
+                self.reload_after_write()
+            });
+        for m in &self.middlewares {
+            m.after_save(res.is_ok());
+        }
+        res
+    }
+}
+
+impl<Conn, Table, C, W, F, S> Handler<SaveWith<F>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    F: FnMut() -> W + 'static,
+    W: Insertable<Table>,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = ResponseActFuture<Self, Result<()>>;
+
+    fn handle(&mut self, SaveWith(mut make): SaveWith<F>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("SaveWith");
+        self.record_write();
+        for m in &self.middlewares {
+            m.before_save();
+        }
+        self.is_valid = false;
+        let save = move |act: &mut Self| {
+            let _rows = C::write_one(make(), &*act.conn()?)?;
+            #[cfg(feature = "tracing")]
+            tracing::debug!(rows = _rows, "cache save");
+            act.reload_after_write()
+        };
+        Box::pin(self.with_retry(save, 1).map(|res, act, _| {
+            for m in &act.middlewares {
+                m.after_save(res.is_ok());
+            }
+            res
+        }))
+    }
+}
+
+impl<Conn, Table, W, C, S> Handler<ConditionalSave<Conn, Table, W, C, S>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    W: Insertable<Table> + 'static,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<bool>;
+
+    fn handle(
+        &mut self,
+        ConditionalSave {
+            value, predicate, ..
+        }: ConditionalSave<Conn, Table, W, C, S>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("ConditionalSave");
+        self.refresh_if_invalid()?;
+        if !predicate(&*self.cache.read()?) {
+            return Ok(false);
+        }
+        self.record_write();
+        self.is_valid = false;
+        C::write_one(value, &*self.conn()?)?;
+        self.reload_after_write()?;
+        Ok(true)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<SaveBehind<C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Insertable<Table>,
+    C::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, SaveBehind(entry): SaveBehind<C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("SaveBehind");
+        self.record_write();
+        for m in &self.middlewares {
+            m.before_save();
+        }
+        let write = entry.clone();
+        self.pending.push(Box::new(move |conn: &Conn| {
+            C::write_one(write, conn).map(drop)
+        }));
+        let res = self.update_one(entry.get_id(), entry).map(drop);
+        for m in &self.middlewares {
+            m.after_save(res.is_ok());
+        }
+        res
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Flush> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<usize>;
+
+    fn handle(&mut self, _: Flush, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Flush");
+        self.flush()
+    }
+}
+
+impl<Conn, Table, C, W, S> Handler<Upsert<W>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    W: Insertable<Table> + 'static,
+    W::Values: CanInsertInSingleQuery<Conn::Backend>
+        + QueryFragment<Conn::Backend>
+        + UndecoratedInsertRecord<Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, pred: Upsert<W>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Upsert");
+        self.record_write();
+        self.is_valid = false;
+        C::upsert(pred.0, &*self.conn()?)?;
+        self.reload_after_write()?;
+        Ok(())
+    }
+}
+
+impl<Conn, Table, C, W, S> Handler<Update<W>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + IntoUpdateTarget + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    W: AsChangeset<Target = Table> + 'static,
+    UpdateAll<Table, W>: AsQuery + ExecuteDsl<Conn>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<usize>;
+
+    fn handle(&mut self, Update(changeset): Update<W>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Update");
+        self.record_write();
+        self.is_valid = false;
+        let rows = C::update_db(changeset, &*self.conn()?)?;
+        self.reload_after_write()?;
+        Ok(rows)
+    }
+}
+
+impl<Conn, Table, C, S> CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: SecondaryIndexCache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Gets entries with secondary key, building index on first lookup
+    fn get_by_secondary(&mut self, key: &C::SecondaryIndex) -> Result<Vec<C>> {
+        let cache_guard = self.cache.read()?;
+        let index = self
+            .secondary
+            .get_or_insert_with(|| SecondaryIndex::new(C::secondary_key, (*cache_guard).iter()));
+        Ok(index
+            .get(key)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| (*cache_guard).get(id).cloned())
+            .collect())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetBySecondary<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: SecondaryIndexCache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(
+        &mut self,
+        GetBySecondary { key }: GetBySecondary<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetBySecondary");
+        // Entries are read from db by id only, so miss reloads whole cache
+        let reloaded = self.reload_before_lookup(None)?;
+        let found = self.get_by_secondary(&key)?;
+        if !found.is_empty() {
+            self.record_read(true);
+            return Ok(found);
+        }
+        self.record_read(false);
+        if reloaded {
+            return Ok(found);
+        }
+        self.update()?;
+        self.get_by_secondary(&key)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<FindFirst<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Option<C>>;
+
+    fn handle(&mut self, msg: FindFirst<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("FindFirst");
+        let find = |cache: &Storage<C::Id, C, S>| -> Result<Option<C>> {
+            let cache_guard = cache.read()?;
+            Ok((*cache_guard)
+                .values()
+                .find(|it| (msg.predicate)(it))
+                .cloned())
+        };
+        match find(&self.cache)? {
+            None if !self.is_valid => {
+                self.update()?;
+                find(&self.cache)
+            }
+            out => Ok(out),
+        }
+    }
+}
+
+impl<Conn, Table, C, F, S> Handler<GetAllFiltered<Conn, Table, C, F>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table: FilterDsl<F>,
+    Filter<Table, F>: LoadQuery<Conn, C>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(
+        &mut self,
+        msg: GetAllFiltered<Conn, Table, C, F>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetAllFiltered");
+        Ok(Table::table().filter(msg.filter).load(&*self.conn()?)?)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetChangedSince<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table: FilterDsl<Gt<C::TimestampColumn, C::Timestamp>>,
+    Filter<Table, Gt<C::TimestampColumn, C::Timestamp>>: LoadQuery<Conn, C>,
+    C: TimestampedCache<Conn, Table>,
+    <C::TimestampColumn as diesel::Expression>::SqlType: diesel::sql_types::SingleValue,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(
+        &mut self,
+        GetChangedSince { since }: GetChangedSince<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetChangedSince");
+        let filter = C::TIMESTAMP_COLUMN.gt(since);
+        Ok(Table::table().filter(filter).load(&*self.conn()?)?)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<FilterAll<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(&mut self, msg: FilterAll<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("FilterAll");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard)
+            .values()
+            .filter(|it| (msg.predicate)(it))
+            .cloned()
+            .collect())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<AnySatisfy<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<bool>;
+
+    fn handle(&mut self, msg: AnySatisfy<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("AnySatisfy");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard).values().any(|it| (msg.predicate)(it)))
+    }
+}
+
+impl<Conn, Table, C, S> Handler<AllSatisfy<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<bool>;
+
+    fn handle(&mut self, msg: AllSatisfy<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("AllSatisfy");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard).values().all(|it| (msg.predicate)(it)))
+    }
+}
+
+impl<Conn, Table, C, S> Handler<ForEach<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, mut msg: ForEach<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("ForEach");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        (*cache_guard).values().for_each(|it| (msg.f)(it));
+        Ok(())
+    }
+}
+
+impl<Conn, Table, C, Acc, S> Handler<Reduce<Conn, Table, C, Acc>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    Acc: Send + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Acc>;
+
+    fn handle(
+        &mut self,
+        Reduce { init, f, .. }: Reduce<Conn, Table, C, Acc>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Reduce");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard).values().fold(init, f))
+    }
+}
+
+impl<Conn, Table, C, S> Handler<SwapEntry<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Option<C>>;
+
+    fn handle(
+        &mut self,
+        SwapEntry { id, new_value }: SwapEntry<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("SwapEntry");
+        let old = self.update_one(id, new_value)?;
+        self.is_valid = false;
+        Ok(old)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<UpdateCache<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Option<C>>;
+
+    fn handle(
+        &mut self,
+        UpdateCache { id, mutate }: UpdateCache<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("UpdateCache");
+        let mut v = match (*self.cache.read()?).get(&id).cloned() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        mutate(&mut v);
+        self.update_one(id, v.clone())?;
+        Ok(Some(v))
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetByAge<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(&mut self, msg: GetByAge<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("GetByAge");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        Ok(self
+            .entry_timestamps
+            .iter()
+            .filter(|(_, inserted)| inserted.elapsed() <= msg.max_age)
+            .filter_map(|(id, _)| (*cache_guard).get(id).cloned())
+            .collect())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetPage<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    C::Id: Ord,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(
+        &mut self,
+        GetPage { offset, limit, .. }: GetPage<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetPage");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        let mut entries: Vec<(&C::Id, &C)> = (*cache_guard).iter().collect();
+        entries.sort_by_key(|(id, _)| *id);
+        Ok(entries
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(_, it)| it.clone())
+            .collect())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<EvictOlderThan> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<usize>;
+
+    fn handle(&mut self, msg: EvictOlderThan, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("EvictOlderThan");
+        self.evict_older_than(msg.max_age)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetOrDefault<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<C>;
+
+    fn handle(
+        &mut self,
+        GetOrDefault { id, default }: GetOrDefault<Conn, Table, C>,
+        context: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetOrDefault");
+        let out = <Self as Handler<Get<Conn, Table, C>>>::handle(self, Get::new(id), context)?;
+        Ok(out.unwrap_or(default))
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Get<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Option<C>>;
+
+    fn handle(
+        &mut self,
+        Get { id, max_age }: Get<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Get");
+        for m in &self.middlewares {
+            m.before_get(&id);
+        }
+        let reloaded = self.reload_before_lookup(max_age)?;
+        let out = self.get(id.clone())?;
+        for m in &self.middlewares {
+            m.after_get(&id, out.is_some());
+        }
+        match out {
+            Some(out) => {
+                if self.verbose() {
+                    log::trace!("cache HIT id={:?} value={:#?}", id, out);
+                }
+                #[cfg(feature = "tracing")]
+                tracing::trace!(id = ?id, "cache hit");
+                self.record_read(true);
+                self.touch(&id);
+                Ok(Some(out))
+            }
+            None => {
+                if self.verbose() {
+                    log::trace!("cache MISS id={:?}", id);
+                }
+                #[cfg(feature = "tracing")]
+                tracing::trace!(id = ?id, "cache miss");
+                self.record_read(false);
+                if reloaded || self.fresh_within(max_age) {
+                    return Ok(None);
+                }
+                self.load_missing(&id)?;
+                self.get(id)
+            }
+        }
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetWithTimestamp<Conn, Table, C>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Option<(C, Instant)>>;
+
+    fn handle(
+        &mut self,
+        GetWithTimestamp { id }: GetWithTimestamp<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetWithTimestamp");
+        let reloaded = self.reload_before_lookup(None)?;
+        if let Some(out) = self.get_with_timestamp(&id)? {
+            self.record_read(true);
+            return Ok(Some(out));
+        }
+        self.record_read(false);
+        if reloaded {
+            return Ok(None);
+        }
+        self.load_missing(&id)?;
+        self.get_with_timestamp(&id)
+    }
+}
+
+impl<Conn, Table, C, V, S> Handler<GetAllByField<Conn, Table, C, V>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    V: PartialEq + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(
+        &mut self,
+        GetAllByField { field, value, .. }: GetAllByField<Conn, Table, C, V>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetAllByField");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard)
+            .values()
+            .filter(|it| field(it) == &value)
+            .cloned()
+            .collect())
+    }
+}
+
+impl<Conn, Table, C, K, S> Handler<SortedGetAll<Conn, Table, C, K>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    K: Ord + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(
+        &mut self,
+        SortedGetAll { key, .. }: SortedGetAll<Conn, Table, C, K>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("SortedGetAll");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        let mut keyed: Vec<(K, C)> = (*cache_guard)
+            .values()
+            .map(|it| (key(it), it.clone()))
+            .collect();
+        // Stable sort, so entries with equal keys keep their relative order.
+        keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(keyed.into_iter().map(|(_, it)| it).collect())
+    }
+}
+
+impl<Conn, Table, C, K, S> Handler<GroupBy<Conn, Table, C, K>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    K: Hash + Eq + Clone + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<CacheMap<K, Vec<C>>>;
+
+    fn handle(&mut self, msg: GroupBy<Conn, Table, C, K>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("GroupBy");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        let mut out: CacheMap<K, Vec<C>> = CacheMap::default();
+        for it in (*cache_guard).values() {
+            out.entry((msg.key_fn)(it)).or_default().push(it.clone());
+        }
+        Ok(out)
+    }
+}
+
+impl<Conn, Table, C, K, S> Handler<GetAllSorted<Conn, Table, C, K>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    K: Ord + Clone + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(
+        &mut self,
+        msg: GetAllSorted<Conn, Table, C, K>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetAllSorted");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        let mut keyed: Vec<(K, C)> = (*cache_guard)
+            .values()
+            .map(|it| ((msg.key_fn)(it), it.clone()))
+            .collect();
+        if msg.descending {
+            keyed.sort_by(|(a, _), (b, _)| b.cmp(a));
+        } else {
+            keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        }
+        Ok(keyed.into_iter().map(|(_, it)| it).collect())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetRange<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    C::Id: Ord,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(
+        &mut self,
+        GetRange { start, end }: GetRange<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetRange");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        #[cfg(feature = "sorted")]
+        {
+            let index = self
+                .sorted
+                .get_or_insert_with(|| SortedIndex::new((*cache_guard).keys()));
+            Ok(index
+                .range(start, end)
+                .filter_map(|id| (*cache_guard).get(id).cloned())
+                .collect())
+        }
+        #[cfg(not(feature = "sorted"))]
+        {
+            let mut found: Vec<(&C::Id, &C)> = (*cache_guard)
+                .iter()
+                .filter(|(id, _)| (&start..=&end).contains(id))
+                .collect();
+            found.sort_by_key(|(id, _)| *id);
+            Ok(found.into_iter().map(|(_, it)| it.clone()).collect())
+        }
+    }
+}
+
+impl<Conn, Table, C, K, S> Handler<GetTopN<Conn, Table, C, K>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    C::Id: Ord,
+    K: Ord + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(&mut self, msg: GetTopN<Conn, Table, C, K>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("GetTopN");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        let keyed = (*cache_guard)
+            .iter()
+            .map(|(id, it)| Reverse(((msg.key_fn)(it), id)));
+        Ok(smallest_n(keyed, msg.n)
+            .into_iter()
+            .filter_map(|Reverse((_, id))| (*cache_guard).get(id).cloned())
+            .collect())
+    }
+}
+
+impl<Conn, Table, C, K, S> Handler<GetBottomN<Conn, Table, C, K>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    C::Id: Ord,
+    K: Ord + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<C>>;
+
+    fn handle(
+        &mut self,
+        msg: GetBottomN<Conn, Table, C, K>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetBottomN");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        let keyed = (*cache_guard).iter().map(|(id, it)| ((msg.key_fn)(it), id));
+        Ok(smallest_n(keyed, msg.n)
+            .into_iter()
+            .filter_map(|(_, id)| (*cache_guard).get(id).cloned())
+            .collect())
+    }
+}
+
+/// Selects `n` smallest items in ascending order. Heap holds at most `n + 1`
+/// items, so items are not sorted all at once.
+fn smallest_n<T: Ord>(items: impl Iterator<Item = T>, n: usize) -> Vec<T> {
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+    for it in items {
+        heap.push(it);
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    heap.into_sorted_vec()
+}
+
+impl<Conn, Table, C, S> Handler<LoadedCount> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = usize;
+
+    fn handle(&mut self, _: LoadedCount, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("LoadedCount");
+        self.entries_count_at_last_refresh()
+    }
+}
+
+impl<Conn, Table, C, S> Handler<LastRefreshInstant> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Option<Instant>;
+
+    fn handle(&mut self, _: LastRefreshInstant, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("LastRefreshInstant");
+        self.last_refresh_instant()
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetStats> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = CacheStats;
+
+    fn handle(&mut self, _: GetStats, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("GetStats");
+        CacheStats {
+            entry_count: self.cache.read().map_or(0, |cache| cache.len()),
+            ..self.stats
+        }
+    }
+}
+
+impl<Conn, Table, W, C, S> Handler<SaveWithId<Conn, Table, W, C>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: FindDsl<C::Id>,
+    Find<Table, C::Id>: LimitDsl,
+    Limit<Find<Table, C::Id>>: LoadQuery<Conn, C>,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    W: Insertable<Table> + 'static,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<C>;
+
+    fn handle(
+        &mut self,
+        SaveWithId { id, value }: SaveWithId<Conn, Table, W, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("SaveWithId");
+        self.record_write();
+        C::write_one(value, &*self.conn()?)?;
+        let row = C::read_one(&id, &*self.conn()?)?
+            .ok_or_else(|| CacheError::NotFound(format!("entry with id {:?}", id)))?;
+        self.update_one(id, row.clone())?;
+        Ok(row)
+    }
+}
+
+impl<Conn, Table, C, F, S> Handler<Transaction<F>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    F: FnOnce(&Conn) -> Result<Vec<CacheOp<Conn, Table, C>>> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, Transaction(f): Transaction<F>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Transaction");
+        self.record_write();
+        let ops = {
+            let conn = self.conn()?;
+            conn.transaction(|| f(&*conn))?
+        };
+        for op in ops {
+            match op {
+                CacheOp::Insert(v) | CacheOp::Update(v) => self.update_one(v.get_id(), v)?,
+                CacheOp::Delete(id) => self.remove_one(&id)?,
+            };
+        }
+        Ok(())
+    }
+}
+
+impl<Conn, Table, C, F, R, S> Handler<Transact<F, R>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    F: FnOnce(&Conn) -> Result<R> + Send + 'static,
+    R: Send + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<R>;
+
+    fn handle(&mut self, msg: Transact<F, R>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Transact");
+        self.record_write();
+        let out = {
+            let conn = self.conn()?;
+            conn.transaction(|| (msg.0)(&*conn))?
+        };
+        self.is_valid = false;
+        self.reload_after_write()?;
+        Ok(out)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Reload<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: FindDsl<C::Id>,
+    Find<Table, C::Id>: LimitDsl,
+    Limit<Find<Table, C::Id>>: LoadQuery<Conn, C>,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Option<C>>;
+
+    fn handle(
+        &mut self,
+        Reload { id }: Reload<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Reload");
+        let row = C::read_one(&id, &*self.conn()?)?;
+        match &row {
+            Some(row) => self.update_one(id, row.clone()),
+            None => self.remove_one(&id),
+        }?;
+        Ok(row)
+    }
+}
+
+impl<Conn, Table, C, F, S> Handler<UpdateField<Conn, Table, C, F>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: FindDsl<C::Id>,
+    Find<Table, C::Id>: IntoUpdateTarget + LimitDsl,
+    Limit<Find<Table, C::Id>>: LoadQuery<Conn, C>,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    F: AsChangeset<Target = <Find<Table, C::Id> as HasTable>::Table> + 'static,
+    UpdateOne<Table, C::Id, F>: AsQuery + ExecuteDsl<Conn>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(
+        &mut self,
+        UpdateField { id, changeset }: UpdateField<Conn, Table, C, F>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("UpdateField");
+        self.record_write();
+        if C::update_one_db(&id, changeset, &*self.conn()?)? == 0 {
+            return Err(CacheError::NotFound(format!("entry with id {:?}", id)));
+        }
+        // Only updated row is reread, so there is no need for RETURNING clause
+        // or full reload of cache.
+        match C::read_one(&id, &*self.conn()?)? {
+            Some(row) => self.update_one(id, row),
+            None => self.remove_one(&id),
+        }?;
+        Ok(())
+    }
+}
+
+impl<Conn, Table, C, F, V, S> Handler<VersionedSave<Conn, Table, C, F>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: FindDsl<C::Id>,
+    Find<Table, C::Id>: LimitDsl + FilterDsl<VersionEq<V>>,
+    Limit<Find<Table, C::Id>>: LoadQuery<Conn, C>,
+    FindVersion<Table, C::Id, V>: IntoUpdateTarget + HasTable<Table = Table>,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: VersionedCache<Conn, Table, VersionColumn = V>,
+    V: Column<Table = Table> + Expression<SqlType = BigIntSql> + AppearsOnTable<Table>,
+    F: AsChangeset<Target = Table> + 'static,
+    (F, VersionBump<V>): AsChangeset<Target = Table>,
+    UpdateVersioned<Table, C::Id, V, F>: AsQuery + ExecuteDsl<Conn>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<C>;
+
+    fn handle(
+        &mut self,
+        msg: VersionedSave<Conn, Table, C, F>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("VersionedSave");
+        self.record_write();
+        let VersionedSave {
+            id,
+            expected_version,
+            changeset,
+        } = msg;
+        // Version is checked by db, so that writers in other processes, which
+        // cache doesn't know about, are also detected
+        let target = FilterDsl::filter(
+            Table::table().find(id.clone()),
+            C::VERSION_COLUMN.eq(expected_version as i64),
+        );
+        let next = diesel::expression::ops::Add::new(C::VERSION_COLUMN, Bound::new(1));
+        let bump = C::VERSION_COLUMN.eq(next);
+        let updated = diesel::update(target)
+            .set((changeset, bump))
+            .execute(&*self.conn()?)?;
+        let row = C::read_one(&id, &*self.conn()?)?
+            .ok_or_else(|| CacheError::NotFound(format!("entry with id {:?}", id)))?;
+        let actual = row.version();
+        self.update_one(id, row.clone())?;
+        if updated == 0 {
+            return Err(CacheError::VersionConflict {
+                expected: expected_version,
+                actual,
+            });
+        }
+        Ok(row)
+    }
+}
+
+impl<Conn, Table, C, W, S> Handler<UpdateMany<Conn, Table, C, W>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: FindDsl<C::Id>,
+    Find<Table, C::Id>: IntoUpdateTarget,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    W: AsChangeset<Target = <Find<Table, C::Id> as HasTable>::Table> + 'static,
+    UpdateOne<Table, C::Id, W>: AsQuery + ExecuteDsl<Conn>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<usize>;
+
+    fn handle(
+        &mut self,
+        UpdateMany(updates): UpdateMany<Conn, Table, C, W>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("UpdateMany");
+        self.record_write();
+        self.is_valid = false;
+        let rows = {
+            let conn = self.conn()?;
+            conn.transaction::<_, CacheError, _>(|| {
+                let mut rows = 0;
+                for (id, changeset) in updates {
+                    rows += C::update_one_db(&id, changeset, &conn)?;
+                }
+                Ok(rows)
+            })?
+        };
+        self.reload_after_write()?;
+        Ok(rows)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Delete<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: FindDsl<C::Id>,
+    Find<Table, C::Id>: IntoUpdateTarget,
+    DeleteOne<Table, C::Id>: ExecuteDsl<Conn>,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = ResponseActFuture<Self, Result<bool>>;
+
+    fn handle(
+        &mut self,
+        Delete { id }: Delete<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Delete");
+        self.record_write();
+        for m in &self.middlewares {
+            m.before_delete(&id);
+        }
+        let deleted_id = id.clone();
+        let delete = move |act: &mut Self| {
+            let deleted = match C::delete_one(&id, &*act.conn()?) {
+                Ok(rows) => rows > 0,
+                Err(CacheError::Db(diesel::result::Error::NotFound)) => false,
+                Err(e) => return Err(e),
+            };
+            act.remove_one(&id)?;
+            Ok(deleted)
+        };
+        Box::pin(self.with_retry(delete, 1).map(move |res, act, _| {
+            for m in &act.middlewares {
+                m.after_delete(&deleted_id, res.is_ok());
+            }
+            res
+        }))
+    }
+}
+
+impl<Conn, Table, C, S> Handler<SoftDelete<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: SoftDeleteCache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<bool>;
+
+    fn handle(
+        &mut self,
+        SoftDelete { id }: SoftDelete<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("SoftDelete");
+        self.record_write();
+        let deleted = C::soft_delete(&id, &*self.conn()?)? > 0;
+        self.remove_one(&id)?;
+        Ok(deleted)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<DeleteMany<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::PrimaryKey: ExpressionMethods,
+    Vec<C::Id>: AsInExpression<<Table::PrimaryKey as Expression>::SqlType>,
+    Table: FilterDsl<EqAny<Table::PrimaryKey, Vec<C::Id>>>,
+    FilterIds<Table, C::Id>: IntoUpdateTarget,
+    DeleteIds<Table, C::Id>: ExecuteDsl<Conn>,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<usize>;
+
+    fn handle(
+        &mut self,
+        DeleteMany { ids }: DeleteMany<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("DeleteMany");
+        self.record_write();
+        let rows = C::delete_many(ids.iter().cloned(), &*self.conn()?)?;
+        self.remove_many(&ids)?;
+        Ok(rows)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetMany<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<CacheMap<C::Id, C>>;
+
+    fn handle(
+        &mut self,
+        GetMany { ids }: GetMany<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetMany");
+        self.get_many_or_load(&ids)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetAllByIds<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<CacheMap<C::Id, C>>;
+
+    fn handle(
+        &mut self,
+        GetAllByIds { ids }: GetAllByIds<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("GetAllByIds");
+        let ids: Vec<C::Id> = ids.into_iter().collect();
+        self.get_many_or_load(&ids)
+    }
+}
+
+impl<Conn, Table, C, W, S> Handler<SaveMany<W>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    W: 'static,
+    Vec<W>: Insertable<Table>,
+    <Vec<W> as Insertable<Table>>::Values:
+        CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<usize>;
+
+    fn handle(&mut self, pred: SaveMany<W>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("SaveMany");
+        self.record_write();
+        self.is_valid = false;
+        let rows = C::write_many(pred.0, &*self.conn()?)?;
+        self.reload_after_write()?;
+        Ok(rows)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Invalidate> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, _: Invalidate, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Invalidate");
+        self.update()
+    }
+}
+
+#[cfg(all(feature = "futures", feature = "tokio"))]
+impl<Conn, Table, C, S> Handler<SpawnRefresh> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + Send + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send,
+    C::Id: Send,
+    S: BuildHasher + Default + Clone + Send + 'static,
+{
+    type Result = Result<futures::channel::oneshot::Receiver<Result<()>>>;
+
+    fn handle(&mut self, _: SpawnRefresh, context: &mut Context<Self>) -> Self::Result {
+        handler_span!("SpawnRefresh");
+        let (tx, rx) = futures::channel::oneshot::channel();
+        let conn = match self.conn.get_owned() {
+            Some(conn) => conn?,
             None => {
-                self.update()?;
-                Ok(self.get(id))
+                context.run_later(Duration::from_secs(0), move |act, _| {
+                    // Receiver may be dropped by caller not interested in result
+                    let _ = tx.send(act.update());
+                });
+                return Ok(rx);
+            }
+        };
+        self.flush_before_reload();
+        let started = Instant::now();
+        let load = Self::spawn_load(conn, self.timeout_sql(), self.capacity, self.max_entries);
+        self.apply_spawned_load(context, load, started, move |_, res| {
+            let _ = tx.send(res);
+        });
+        Ok(rx)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Shrink> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, _: Shrink, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Shrink");
+        self.shrink()
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GracefulClear> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = ResponseActFuture<Self, Result<()>>;
+
+    fn handle(&mut self, _: GracefulClear, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("GracefulClear");
+        self.is_valid = false;
+        // Actor keeps handling queued messages while delay is pending
+        Box::pin(
+            actix::clock::delay_for(Duration::from_secs(0))
+                .into_actor(self)
+                .map(|_, act, _| act.update()),
+        )
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Clear> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = ();
+
+    fn handle(&mut self, _: Clear, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Clear");
+        self.is_valid = false;
+        self.cache.replace(CacheMap::default());
+        self.entry_timestamps.clear();
+        self.secondary = None;
+        #[cfg(feature = "sorted")]
+        {
+            self.sorted = None;
+        }
+    }
+}
+
+impl<Conn, Table, C, R, S> Handler<Inspect<Conn, Table, C, R, S>>
+    for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    R: 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = MessageResult<Inspect<Conn, Table, C, R, S>>;
+
+    fn handle(
+        &mut self,
+        msg: Inspect<Conn, Table, C, R, S>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Inspect");
+        MessageResult((msg.f)(self))
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Reset> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, _: Reset, context: &mut Context<Self>) -> Self::Result {
+        handler_span!("Reset");
+        if let Some(timer) = self.timer.take() {
+            context.cancel_future(timer);
+        }
+        self.lru.clear();
+        self.seed(CacheMap::default());
+        self.stats = Default::default();
+        self.loaded_count = 0;
+        // Cache stays empty if reload fails, so it is retried on next read
+        self.is_valid = false;
+        let res = self.update();
+        if let Some(dur) = self.refresh_strategy.interval() {
+            self.timer = Some(context.spawn(TimerFunc::new(dur, Self::timer_update)));
+        }
+        res
+    }
+}
+
+impl<Conn, Table, C, S> Handler<GetIdSet<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<CacheSet<C::Id>>;
+
+    fn handle(&mut self, _: GetIdSet<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("GetIdSet");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard).keys().cloned().collect())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Merge<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<usize>;
+
+    fn handle(
+        &mut self,
+        Merge(entries): Merge<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Merge");
+        // Merged entries may differ from db, so cache is reconciled on next
+        // update.
+        self.is_valid = false;
+        self.merge(entries)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Drain<Conn, Table, C, S>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<CacheMap<C::Id, C, S>>;
+
+    fn handle(&mut self, _: Drain<Conn, Table, C, S>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Drain");
+        self.is_valid = false;
+        self.entry_timestamps.clear();
+        self.secondary = None;
+        #[cfg(feature = "sorted")]
+        {
+            self.sorted = None;
+        }
+        let old = self.cache.replace(CacheMap::default());
+        old.into_map()
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Exists<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<bool>;
+
+    fn handle(
+        &mut self,
+        Exists { id }: Exists<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Exists");
+        let reloaded = self.reload_before_lookup(None)?;
+        if self.contains(&id)? {
+            self.record_read(true);
+            return Ok(true);
+        }
+        self.record_read(false);
+        if reloaded {
+            return Ok(false);
+        }
+        self.load_missing(&id)?;
+        self.contains(&id)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<DebugDump> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, _: DebugDump, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("DebugDump");
+        let cache_guard = self.cache.read()?;
+        let values: Vec<&C> = (*cache_guard).values().collect();
+        #[cfg(feature = "tracing")]
+        tracing::debug!(entry_count = values.len(), values = ?values, "cache dump");
+        #[cfg(not(feature = "tracing"))]
+        log::debug!("cache DUMP {} entries {:#?}", values.len(), values);
+        Ok(())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<HealthCheck> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, _: HealthCheck, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("HealthCheck");
+        diesel::sql_query("SELECT 1").execute(&*self.conn()?)?;
+        Ok(())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<RawSql> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, RawSql(sql): RawSql, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("RawSql");
+        self.conn()?.batch_execute(&sql)?;
+        Ok(())
+    }
+}
+
+impl<Conn, Table, C, R, S> Handler<RawQuery<R>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    R: QueryableByName<Conn::Backend> + 'static,
+    SqlQuery: LoadQuery<Conn, R>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<Vec<R>>;
+
+    fn handle(&mut self, query: RawQuery<R>, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("RawQuery");
+        Ok(diesel::sql_query(query.0).load(&*self.conn()?)?)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Diff<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + PartialEq,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<CacheDiff<Conn, Table, C>>;
+
+    fn handle(
+        &mut self,
+        Diff(mut other): Diff<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Diff");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        let mut diff = CacheDiff {
+            only_in_cache: CacheMap::default(),
+            only_in_other: CacheMap::default(),
+            both_modified: CacheMap::default(),
+        };
+        for (id, v) in (*cache_guard).iter() {
+            match other.remove(id) {
+                None => {
+                    diff.only_in_cache.insert(id.clone(), v.clone());
+                }
+                Some(o) if o != *v => {
+                    diff.both_modified.insert(id.clone(), (v.clone(), o));
+                }
+                Some(_) => {}
             }
         }
+        diff.only_in_other = other;
+        Ok(diff)
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Count> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = Result<usize>;
+
+    fn handle(&mut self, _: Count, _: &mut Context<Self>) -> Self::Result {
+        handler_span!("Count");
+        self.refresh_if_invalid()?;
+        let cache_guard = self.cache.read()?;
+        Ok((*cache_guard).len())
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Subscribe<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send,
+    C::Id: Send,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        Subscribe(recipient): Subscribe<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Subscribe");
+        let notify = recipient.clone();
+        self.subscribers.push(Subscriber {
+            notify: Box::new(move |event| notify.do_send(event).is_ok()),
+            recipient: Box::new(recipient),
+        });
+    }
+}
+
+impl<Conn, Table, C, S> Handler<Unsubscribe<Conn, Table, C>> for CacheDbActor<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send,
+    C::Id: Send,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        Unsubscribe(recipient): Unsubscribe<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        handler_span!("Unsubscribe");
+        self.subscribers.retain(|subscriber| {
+            subscriber
+                .recipient
+                .downcast_ref::<Recipient<CacheEvent<Conn, Table, C>>>()
+                != Some(&recipient)
+        });
     }
 }
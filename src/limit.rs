@@ -0,0 +1,45 @@
+use diesel::backend::Backend;
+use diesel::query_builder::{AstPass, Query, QueryFragment, QueryId};
+use diesel::result::QueryResult;
+use diesel::RunQueryDsl;
+
+/// Query `Q` returning at most `limit` rows, see
+/// [`Cache::read_limited`](crate::Cache::read_limited).
+///
+/// Limit is appended to query by hand, as `LimitDsl` of generic table would
+/// need to be proven by every handler which loads entries.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Limited<Q> {
+    query: Q,
+    limit: usize,
+}
+
+impl<Q> Limited<Q> {
+    pub(crate) fn new(query: Q, limit: usize) -> Self {
+        Self { query, limit }
+    }
+}
+
+impl<Q: Query> Query for Limited<Q> {
+    type SqlType = Q::SqlType;
+}
+
+impl<Q, DB> QueryFragment<DB> for Limited<Q>
+where
+    Q: QueryFragment<DB>,
+    DB: Backend,
+{
+    fn walk_ast(&self, mut out: AstPass<DB>) -> QueryResult<()> {
+        self.query.walk_ast(out.reborrow())?;
+        out.push_sql(&format!(" LIMIT {}", self.limit));
+        Ok(())
+    }
+}
+
+impl<Q> QueryId for Limited<Q> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<Q, Conn> RunQueryDsl<Conn> for Limited<Q> {}
@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hash};
+
+use crate::CacheMap;
+
+/// Order of use of cached entries for eviction with
+/// [`CacheDbActorBuilder::max_entries`](crate::CacheDbActorBuilder::max_entries).
+///
+/// Used entry is pushed to the back of queue with new generation, so touching
+/// entry is `O(1)`. Older copies of id are left in queue and skipped when
+/// popped, queue is compacted when they outnumber entries.
+#[derive(Clone)]
+pub(crate) struct LruQueue<K, S> {
+    queue: VecDeque<(K, u64)>,
+    generations: CacheMap<K, u64, S>,
+    generation: u64,
+}
+
+impl<K, S> Default for LruQueue<K, S>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            generations: CacheMap::default(),
+            generation: 0,
+        }
+    }
+}
+
+impl<K, S> LruQueue<K, S>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher + Default,
+{
+    /// Marks id as most recently used
+    pub(crate) fn touch(&mut self, id: &K) {
+        self.generation += 1;
+        self.generations.insert(id.clone(), self.generation);
+        self.queue.push_back((id.clone(), self.generation));
+        if self.queue.len() > 2 * self.generations.len() + 16 {
+            self.compact();
+        }
+    }
+
+    /// Forgets id of entry removed from cache
+    pub(crate) fn remove(&mut self, id: &K) {
+        self.generations.remove(id);
+    }
+
+    /// Takes least recently used id out of queue
+    pub(crate) fn pop(&mut self) -> Option<K> {
+        while let Some((id, generation)) = self.queue.pop_front() {
+            if self.generations.get(&id) == Some(&generation) {
+                self.generations.remove(&id);
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// Takes all ids out of queue, least recently used first
+    pub(crate) fn take(&mut self) -> Vec<K> {
+        let generations = std::mem::take(&mut self.generations);
+        std::mem::take(&mut self.queue)
+            .into_iter()
+            .filter(|(id, generation)| generations.get(id) == Some(generation))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.queue.clear();
+        self.generations.clear();
+    }
+
+    /// Drops older copies of ids from queue
+    fn compact(&mut self) {
+        let generations = &self.generations;
+        self.queue
+            .retain(|(id, generation)| generations.get(id) == Some(generation));
+    }
+}
@@ -1,7 +1,9 @@
-use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
 use std::fmt::Debug;
+use std::hash::{BuildHasher, Hash};
 use std::marker::{PhantomData, Unpin};
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use actix::prelude::*;
 
@@ -10,15 +12,145 @@ use diesel::connection::Connection;
 use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
 use diesel::sql_types::HasSqlType;
 
-use crate::{Cache, ConnBackend, Result};
+use crate::snapshot::{CacheCheckpointToken, CacheReadGuard, CacheSnapshot};
+use crate::stats::CacheStats;
+use crate::{
+    Cache, CacheDbActor, CacheMap, CacheSet, ConnBackend, Result, SecondaryIndexCache,
+    TimestampedCache,
+};
 
 /// Save one entry
 #[derive(Debug, Message)]
+#[cfg_attr(
+    feature = "serde-messages",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate")
+)]
 #[rtype(result = "Result<()>")]
 pub struct Save<T>(pub T);
 
-/// Save one entry
-#[cfg(feature = "postgres")]
+/// Same as [`Save`], with entry built by closure for every attempt, so that
+/// write failed with transient error is retried, see
+/// [`CacheDbActorBuilder::retry_policy`](crate::CacheDbActorBuilder::retry_policy),
+/// e.g. `SaveWith(move || shop.clone())`.
+#[derive(Message)]
+#[rtype(result = "Result<()>")]
+pub struct SaveWith<F>(pub F);
+
+impl<F> Debug for SaveWith<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SaveWith").finish()
+    }
+}
+
+/// Insert entry or update existing one, see [`Cache::upsert`]
+#[derive(Debug, Message)]
+#[rtype(result = "Result<()>")]
+pub struct Upsert<T>(pub T);
+
+/// Updates columns set by changeset in db and reloads cache, see
+/// [`Cache::update_db`]. Returns number of updated rows. Changeset is
+/// applied to all rows of table, use [`UpdateMany`] to update entries by
+/// ids.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<usize>")]
+pub struct Update<T>(pub T);
+
+/// Puts entry in cache at once and queues its db insert, which is executed on
+/// [`Flush`], before every reload of cache and by timer. Db errors of queued
+/// writes surface only on `Flush`, e.g. when entry with the same id is
+/// already in db.
+///
+/// Written row is not known until flush, so there is no write-behind
+/// counterpart of [`SaveWithResult`].
+#[derive(Debug, Message)]
+#[rtype(result = "Result<()>")]
+pub struct SaveBehind<C>(pub C);
+
+/// Executes db writes queued by [`SaveBehind`]. Returns number of executed
+/// writes or first error.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<usize>")]
+pub struct Flush;
+
+/// Runs closure in one db transaction and applies returned [`CacheOp`]s to
+/// cache if transaction is committed. Cache is left untouched if closure
+/// fails.
+///
+/// Closure writes to db by itself and reports which entries it changed, e.g.
+/// `Transaction(|c| { ...; Ok(vec![CacheOp::Delete(1)]) })`.
+#[derive(Message)]
+#[rtype(result = "Result<()>")]
+pub struct Transaction<F>(pub F);
+
+impl<F> Debug for Transaction<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Transaction").finish()
+    }
+}
+
+/// Runs closure in one db transaction and reloads cache if transaction is
+/// committed, e.g. for writes which can't be expressed with other messages.
+/// Returns result of closure, e.g.
+/// `Transact::new(|c| Ok(diesel::delete(shop::table).execute(c)?))`.
+#[derive(Message)]
+#[rtype(result = "Result<R>")]
+pub struct Transact<F, R: 'static>(pub F, PhantomData<R>);
+
+impl<F, R> Transact<F, R> {
+    /// Constructor
+    pub fn new(f: F) -> Self {
+        Self(f, PhantomData)
+    }
+}
+
+impl<F, R> Debug for Transact<F, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Transact").finish()
+    }
+}
+
+/// Change of cache made by [`Transaction`]
+pub enum CacheOp<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Entry was inserted to db
+    Insert(C),
+    /// Entry was updated in db
+    Update(C),
+    /// Entry with id was deleted from db
+    Delete(C::Id),
+}
+
+impl<Conn, Table, C> Debug for CacheOp<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheOp::Insert(v) => f.debug_tuple("Insert").field(v).finish(),
+            CacheOp::Update(v) => f.debug_tuple("Update").field(v).finish(),
+            CacheOp::Delete(id) => f.debug_tuple("Delete").field(id).finish(),
+        }
+    }
+}
+
+/// Save many entries in one query
+#[derive(Debug, Message)]
+#[rtype(result = "Result<usize>")]
+pub struct SaveMany<T>(pub Vec<T>);
+
+/// Save one entry returning inserted row
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
 #[derive(Debug, Message)]
 #[rtype(result = "Result<C>")]
 pub struct SaveWithResult<Conn, Table, W, C>
@@ -34,6 +166,7 @@ where
     _c: PhantomData<C::Row>,
 }
 
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
 impl<Conn, Table, W, C> SaveWithResult<Conn, Table, W, C>
 where
     Conn: Connection + Unpin + 'static,
@@ -48,8 +181,188 @@ where
     }
 }
 
+/// Inserts entry or updates existing one on conflict, returning affected
+/// row. Conflict is handled by [`UpsertCache::upsert_with_result`] of entry
+/// type.
+#[cfg(feature = "postgres")]
+#[derive(Debug, Message)]
+#[rtype(result = "Result<C>")]
+pub struct UpsertWithResult<Conn, Table, W, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Data to write
+    pub w: W,
+    _c: PhantomData<C::Row>,
+}
+
+#[cfg(feature = "postgres")]
+impl<Conn, Table, W, C> UpsertWithResult<Conn, Table, W, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Constructor
+    pub fn new(w: W) -> Self {
+        Self { w, _c: PhantomData }
+    }
+}
+
+/// Gets entry by id from cache, or inserts `value` if there is no such entry
+/// and returns inserted row. Cache is not reloaded, so entries inserted to
+/// db by others are not seen.
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+#[derive(Debug, Message)]
+#[rtype(result = "Result<C>")]
+pub struct GetOrInsert<Conn, Table, W, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of entry
+    pub id: C::Id,
+    /// Data to write if there is no entry with id
+    pub value: W,
+}
+
+/// Same as [`GetOrInsert`], but data to write is made by `factory` only if
+/// there is no entry with id. Returns entry and whether it was inserted.
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+#[derive(Message)]
+#[rtype(result = "Result<(C, bool)>")]
+pub struct EntryOrInsert<Conn, Table, W, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of entry
+    pub id: C::Id,
+    /// Makes data to write if there is no entry with id
+    pub factory: Box<dyn FnOnce() -> W + Send>,
+}
+
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+impl<Conn, Table, W, C> Debug for EntryOrInsert<Conn, Table, W, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EntryOrInsert")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+/// Predicate of [`ConditionalSave`], called with all cached entries
+pub type MapPredicate<Id, C, S = RandomState> = Box<dyn Fn(&CacheMap<Id, C, S>) -> bool + Send>;
+
+/// Saves entry only if predicate on all cached entries holds. Messages are
+/// handled one by one, so no other write happens between check and insert.
+/// Returns whether entry was saved.
+#[derive(Message)]
+#[rtype(result = "Result<bool>")]
+pub struct ConditionalSave<Conn, Table, W, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Data to write
+    pub value: W,
+    /// Predicate which all cached entries should match for entry to be saved
+    pub predicate: MapPredicate<C::Id, C, S>,
+    _c: PhantomData<(Conn, Table, S)>,
+}
+
+impl<Conn, Table, W, C, S> ConditionalSave<Conn, Table, W, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Constructor
+    pub fn new(
+        value: W,
+        predicate: impl Fn(&CacheMap<C::Id, C, S>) -> bool + Send + 'static,
+    ) -> Self {
+        Self {
+            value,
+            predicate: Box::new(predicate),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, W, C, S> Debug for ConditionalSave<Conn, Table, W, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    W: Debug,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConditionalSave")
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+/// Save one entry with known id. Saved entry is read back from db by id.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<C>")]
+pub struct SaveWithId<Conn, Table, W, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of entry
+    pub id: C::Id,
+    /// Data to write
+    pub value: W,
+}
+
 /// Gets item by id
 #[derive(Debug, Message)]
+#[cfg_attr(
+    feature = "serde-messages",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(
+        crate = "serde_crate",
+        bound(
+            serialize = "C::Id: serde_crate::Serialize",
+            deserialize = "C::Id: serde_crate::Deserialize<'de>"
+        )
+    )
+)]
 #[rtype(result = "Result<Option<C>>")]
 pub struct Get<Conn, Table, C>
 where
@@ -61,6 +374,33 @@ where
 {
     /// Id of item to get
     pub id: C::Id,
+    /// Acceptable staleness of cache. If cache was reloaded within it, miss
+    /// returns `None` without db access even if cache is invalid. Otherwise
+    /// invalid or expired cache is reloaded before lookup and misses are
+    /// read from db, same as with `None`. Hits are served either way.
+    pub max_age: Option<Duration>,
+}
+
+impl<Conn, Table, C> Get<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Constructor
+    pub fn new(id: C::Id) -> Self {
+        Self { id, max_age: None }
+    }
+
+    /// Constructor with acceptable staleness of cache
+    pub fn with_max_age(id: C::Id, max_age: Duration) -> Self {
+        Self {
+            id,
+            max_age: Some(max_age),
+        }
+    }
 }
 
 impl<Conn, Table, C> Clone for Get<Conn, Table, C>
@@ -75,6 +415,7 @@ where
     fn clone(&self) -> Self {
         Self {
             id: self.id.clone(),
+            max_age: self.max_age,
         }
     }
 }
@@ -90,31 +431,1877 @@ where
 {
 }
 
-/// Gets all entries
-#[derive(Debug, Clone, Copy, Message)]
-#[rtype(result = "Result<Arc<RwLock<HashMap<C::Id, C>>>>")]
-pub struct GetAll<Conn, Table, C>
+/// Same as [`Get`], but returns `default` if there is no item with id
+#[derive(Debug, Message)]
+#[rtype(result = "Result<C>")]
+pub struct GetOrDefault<Conn, Table, C>
 where
     Conn: Connection + Unpin + 'static,
     Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
-    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
     Table::Query: QueryId + QueryFragment<Conn::Backend>,
-    C: Cache<Conn, Table> + 'static,
+    C: Cache<Conn, Table>,
 {
-    _c: PhantomData<(Conn, Table, C)>,
+    /// Id of item to get
+    pub id: C::Id,
+    /// Item returned on miss
+    pub default: C,
+}
+
+/// Gets item by id together with time when it was put in cache or last
+/// reloaded
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Option<(C, Instant)>>")]
+pub struct GetWithTimestamp<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of item to get
+    pub id: C::Id,
+}
+
+/// Rereads one item from db without full reload of cache, e.g. after it was
+/// changed by another process. Item missing in db is removed from cache.
+/// Returns reread item.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Option<C>>")]
+pub struct Reload<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of item to reload
+    pub id: C::Id,
+}
+
+/// Gets all items with secondary key, see
+/// [`SecondaryIndexCache`](crate::SecondaryIndexCache). Items are returned
+/// in no particular order.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetBySecondary<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: SecondaryIndexCache<Conn, Table>,
+{
+    /// Secondary key of items to get
+    pub key: C::SecondaryIndex,
+}
+
+/// Checks whether item with id exists without cloning it
+#[derive(Debug, Message)]
+#[rtype(result = "Result<bool>")]
+pub struct Exists<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of item to check
+    pub id: C::Id,
 }
 
-impl<Conn, Table, C> Default for GetAll<Conn, Table, C>
+/// Gets items by ids. Returns only found items.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<CacheMap<C::Id, C>>")]
+pub struct GetMany<Conn, Table, C>
 where
     Conn: Connection + Unpin + 'static,
     Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
     Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
     Table::Query: QueryId + QueryFragment<Conn::Backend>,
-    C: Cache<Conn, Table> + 'static,
+    C: Cache<Conn, Table>,
 {
-    fn default() -> Self {
-        GetAll {
-            _c: Default::default(),
-        }
-    }
+    /// Ids of items to get
+    pub ids: Vec<C::Id>,
+}
+
+/// Gets item by id from cache only, without db access even on miss, so it
+/// is cheap to send very often. Use [`Get`] when freshness matters and
+/// `Peek` when miss can be tolerated and retried later.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Option<C>>")]
+pub struct Peek<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of item to get
+    pub id: C::Id,
 }
+
+/// Gets items by ids from cache only, for best-effort reads which should
+/// not wait for db. Missing ids map to `None`. Results may be stale or
+/// absent, as cache is neither reloaded on miss nor when it is invalid.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<CacheMap<C::Id, Option<C>>>")]
+pub struct PeekMany<Conn, Table, C>(pub Vec<C::Id>)
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>;
+
+/// Same as [`GetMany`] for set of ids, so every entry is looked up once.
+/// Missing entries are loaded from db with one query.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<CacheMap<C::Id, C>>")]
+pub struct GetAllByIds<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Ids of items to get
+    pub ids: CacheSet<C::Id>,
+}
+
+/// Gets all entries. With
+/// [`CacheDbActorBuilder::max_entries`](crate::CacheDbActorBuilder::max_entries)
+/// map holds only recently used entries, not the whole table.
+#[derive(Debug, Clone, Copy, Message)]
+#[cfg_attr(
+    feature = "serde-messages",
+    derive(serde_crate::Serialize, serde_crate::Deserialize),
+    serde(crate = "serde_crate", bound = "")
+)]
+#[rtype(result = "Result<Arc<RwLock<CacheMap<C::Id, C, S>>>>")]
+pub struct GetAll<Conn, Table, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Acceptable staleness of cache. If cache was reloaded within it, it
+    /// is returned as is even if invalid, otherwise invalid or expired cache
+    /// is reloaded first, same as with `None`.
+    pub max_age: Option<Duration>,
+    _c: PhantomData<(Conn, Table, C, S)>,
+}
+
+impl<Conn, Table, C, S> GetAll<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Constructor with acceptable staleness of cache
+    pub fn with_max_age(max_age: Duration) -> Self {
+        Self {
+            max_age: Some(max_age),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C, S> Default for GetAll<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn default() -> Self {
+        GetAll {
+            max_age: None,
+            _c: Default::default(),
+        }
+    }
+}
+
+/// Gets immutable snapshot of all entries, see [`CacheSnapshot`]
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<CacheSnapshot<Conn, Table, C, S>>")]
+pub struct Snapshot<Conn, Table, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    _c: PhantomData<(Conn, Table, C, S)>,
+}
+
+impl<Conn, Table, C, S> Default for Snapshot<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn default() -> Self {
+        Snapshot {
+            _c: Default::default(),
+        }
+    }
+}
+
+/// Gets item by id from sub-cache of `C` of [`MultiCache`](crate::MultiCache)
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Option<C>>")]
+pub struct TypedGet<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of item to get
+    pub id: C::Id,
+}
+
+/// Saves one entry to table of `C` and reloads its sub-cache of
+/// [`MultiCache`](crate::MultiCache)
+#[derive(Debug, Message)]
+#[rtype(result = "Result<()>")]
+pub struct TypedSave<Conn, Table, W, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Data to write
+    pub w: W,
+    _c: PhantomData<(Conn, Table, C)>,
+}
+
+impl<Conn, Table, W, C> TypedSave<Conn, Table, W, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Constructor
+    pub fn new(w: W) -> Self {
+        Self { w, _c: PhantomData }
+    }
+}
+
+/// Borrows cache map for many lookups under one read lock, see
+/// [`CacheReadGuard`] for the deadlock risk
+#[derive(Debug, Message)]
+#[rtype(result = "Result<CacheReadGuard<Conn, Table, C, S>>")]
+pub struct BorrowCache<Conn, Table, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    _c: PhantomData<(Conn, Table, C, S)>,
+}
+
+impl<Conn, Table, C, S> Default for BorrowCache<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn default() -> Self {
+        BorrowCache {
+            _c: Default::default(),
+        }
+    }
+}
+
+/// Saves current state of cache, e.g. before risky batch of writes, so it
+/// can be brought back with [`Restore`]
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<CacheCheckpointToken<Conn, Table, C, S>>")]
+pub struct Checkpoint<Conn, Table, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    _c: PhantomData<(Conn, Table, C, S)>,
+}
+
+impl<Conn, Table, C, S> Default for Checkpoint<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn default() -> Self {
+        Checkpoint {
+            _c: Default::default(),
+        }
+    }
+}
+
+/// Brings cache back to state saved with [`Checkpoint`]. Db is not touched,
+/// so cache is marked invalid and is reconciled with db on next update.
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct Restore<Conn, Table, C, S = RandomState>(pub CacheCheckpointToken<Conn, Table, C, S>)
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static;
+
+/// Compares cached entries with entries in db, see [`CacheDrift`]. Neither
+/// cache nor db is modified.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<Vec<CacheDrift<Conn, Table, C>>>")]
+pub struct ValidateCache<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + PartialEq + 'static,
+{
+    _c: PhantomData<(Conn, Table, C)>,
+}
+
+impl<Conn, Table, C> Default for ValidateCache<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + PartialEq + 'static,
+{
+    fn default() -> Self {
+        ValidateCache {
+            _c: Default::default(),
+        }
+    }
+}
+
+/// Difference between cache and db found by [`ValidateCache`]
+pub enum CacheDrift<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Entry is in db, but not in cache
+    MissingInCache(C),
+    /// Entry with id is in cache, but not in db
+    MissingInDb(C::Id),
+    /// Cached entry differs from entry in db
+    ValueMismatch {
+        /// Entry in cache
+        cached: C,
+        /// Entry in db
+        db_value: C,
+    },
+}
+
+impl<Conn, Table, C> Clone for CacheDrift<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    fn clone(&self) -> Self {
+        match self {
+            CacheDrift::MissingInCache(v) => CacheDrift::MissingInCache(v.clone()),
+            CacheDrift::MissingInDb(id) => CacheDrift::MissingInDb(id.clone()),
+            CacheDrift::ValueMismatch { cached, db_value } => CacheDrift::ValueMismatch {
+                cached: cached.clone(),
+                db_value: db_value.clone(),
+            },
+        }
+    }
+}
+
+impl<Conn, Table, C> PartialEq for CacheDrift<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (CacheDrift::MissingInCache(a), CacheDrift::MissingInCache(b)) => a == b,
+            (CacheDrift::MissingInDb(a), CacheDrift::MissingInDb(b)) => a == b,
+            (
+                CacheDrift::ValueMismatch { cached, db_value },
+                CacheDrift::ValueMismatch {
+                    cached: other_cached,
+                    db_value: other_db_value,
+                },
+            ) => cached == other_cached && db_value == other_db_value,
+            _ => false,
+        }
+    }
+}
+
+impl<Conn, Table, C> Debug for CacheDrift<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheDrift::MissingInCache(v) => f.debug_tuple("MissingInCache").field(v).finish(),
+            CacheDrift::MissingInDb(id) => f.debug_tuple("MissingInDb").field(id).finish(),
+            CacheDrift::ValueMismatch { cached, db_value } => f
+                .debug_struct("ValueMismatch")
+                .field("cached", cached)
+                .field("db_value", db_value)
+                .finish(),
+        }
+    }
+}
+
+/// Serializes all cached entries, see
+/// [`CacheDbActor::serialize_cache`](crate::CacheDbActor::serialize_cache)
+#[cfg(any(feature = "serde", feature = "bincode"))]
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<Vec<u8>>")]
+pub struct DumpCache;
+
+/// Replaces cached entries with ones serialized by [`DumpCache`]. Cache is
+/// not reloaded from db until next update. Dump must be made with the same
+/// serializer.
+#[cfg(any(feature = "serde", feature = "bincode"))]
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<()>")]
+pub struct LoadCache(pub Vec<u8>);
+
+/// Changes interval of periodic updates of running actor, e.g. to slow
+/// them down during db maintenance. Next update is scheduled after new
+/// interval, zero interval stops periodic updates like
+/// [`CacheDbActorBuilder::refresh_interval`](crate::CacheDbActorBuilder::refresh_interval).
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<()>")]
+pub struct SetRefreshInterval(pub Duration);
+
+/// Gets ids of all entries without cloning entries
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<CacheSet<C::Id>>")]
+pub struct GetIdSet<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    _c: PhantomData<(Conn, Table, C)>,
+}
+
+impl<Conn, Table, C> Default for GetIdSet<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    fn default() -> Self {
+        GetIdSet {
+            _c: Default::default(),
+        }
+    }
+}
+
+/// Takes all entries out of cache without touching db. Cache is reloaded on
+/// next read.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<CacheMap<C::Id, C, S>>")]
+pub struct Drain<Conn, Table, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    _c: PhantomData<(Conn, Table, C, S)>,
+}
+
+impl<Conn, Table, C, S> Default for Drain<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn default() -> Self {
+        Drain {
+            _c: Default::default(),
+        }
+    }
+}
+
+/// Sends all entries one by one to bounded channel, for caches too large to
+/// be copied with [`GetAllVec`]. Returns number of sent entries.
+///
+/// Sending waits while channel is full, so slow receiver slows down
+/// streaming. Entries are copied in batches of `batch_size`, and lock of
+/// cache is held only while batch is copied, so writes are not blocked.
+/// Entries written during streaming may be missed.
+#[cfg(feature = "futures")]
+#[derive(Debug, Message)]
+#[rtype(result = "Result<usize>")]
+pub struct StreamAll<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Channel receiving entries. Streaming stops when receiver is dropped.
+    pub tx: futures::channel::mpsc::Sender<C>,
+    /// Number of entries copied under one lock
+    pub batch_size: usize,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+#[cfg(feature = "futures")]
+impl<Conn, Table, C> StreamAll<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Constructor with batch size of 1000 entries
+    pub fn new(tx: futures::channel::mpsc::Sender<C>) -> Self {
+        Self {
+            tx,
+            batch_size: 1000,
+            _c: PhantomData,
+        }
+    }
+}
+
+/// Gets snapshot of all entries as vector
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetAllVec<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    _c: PhantomData<(Conn, Table, C)>,
+}
+
+impl<Conn, Table, C> Default for GetAllVec<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    fn default() -> Self {
+        GetAllVec {
+            _c: Default::default(),
+        }
+    }
+}
+
+/// Gets all entries which field equals to value
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetAllByField<Conn, Table, C, V>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    V: PartialEq + 'static,
+{
+    /// Field of entry to compare
+    pub field: fn(&C) -> &V,
+    /// Value to look for
+    pub value: V,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C, V> GetAllByField<Conn, Table, C, V>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    V: PartialEq + 'static,
+{
+    /// Constructor
+    pub fn new(field: fn(&C) -> &V, value: V) -> Self {
+        Self {
+            field,
+            value,
+            _c: PhantomData,
+        }
+    }
+}
+
+/// Gets first entry matching predicate
+#[derive(Message)]
+#[rtype(result = "Result<Option<C>>")]
+pub struct FindFirst<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Predicate which entry should match
+    pub predicate: Box<dyn Fn(&C) -> bool + Send>,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C> FindFirst<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Constructor
+    pub fn new(predicate: impl Fn(&C) -> bool + Send + 'static) -> Self {
+        Self {
+            predicate: Box::new(predicate),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C> Debug for FindFirst<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FindFirst").finish()
+    }
+}
+
+/// Gets all entries matching predicate
+#[derive(Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct FilterAll<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Predicate which entries should match
+    pub predicate: Box<dyn Fn(&C) -> bool + Send>,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C> FilterAll<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Constructor
+    pub fn new(predicate: impl Fn(&C) -> bool + Send + 'static) -> Self {
+        Self {
+            predicate: Box::new(predicate),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C> Debug for FilterAll<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FilterAll").finish()
+    }
+}
+
+/// Checks whether any entry matches predicate without cloning entries. Stops
+/// at first matching entry.
+#[derive(Message)]
+#[rtype(result = "Result<bool>")]
+pub struct AnySatisfy<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Predicate which some entry should match
+    pub predicate: Box<dyn Fn(&C) -> bool + Send>,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C> AnySatisfy<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Constructor
+    pub fn new(predicate: impl Fn(&C) -> bool + Send + 'static) -> Self {
+        Self {
+            predicate: Box::new(predicate),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C> Debug for AnySatisfy<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AnySatisfy").finish()
+    }
+}
+
+/// Checks whether all entries match predicate without cloning entries, `true`
+/// for empty cache. Stops at first entry which doesn't match.
+#[derive(Message)]
+#[rtype(result = "Result<bool>")]
+pub struct AllSatisfy<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Predicate which every entry should match
+    pub predicate: Box<dyn Fn(&C) -> bool + Send>,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C> AllSatisfy<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Constructor
+    pub fn new(predicate: impl Fn(&C) -> bool + Send + 'static) -> Self {
+        Self {
+            predicate: Box::new(predicate),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C> Debug for AllSatisfy<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AllSatisfy").finish()
+    }
+}
+
+/// Calls closure for every entry under one read lock, in no particular
+/// order, e.g. to aggregate over entries without cloning them. Results can
+/// be accumulated in state shared with closure, e.g. `Arc<Mutex<_>>`.
+///
+/// Closure runs inside of actor, so it must not wait for messages sent to
+/// the same actor, which would deadlock.
+#[derive(Message)]
+#[rtype(result = "Result<()>")]
+pub struct ForEach<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Closure called with every entry
+    pub f: Box<dyn FnMut(&C) + Send>,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C> ForEach<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Constructor
+    pub fn new(f: impl FnMut(&C) + Send + 'static) -> Self {
+        Self {
+            f: Box::new(f),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C> Debug for ForEach<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ForEach").finish()
+    }
+}
+
+/// Closure of [`Reduce`] folding entry into accumulator
+pub type Folder<Acc, C> = Box<dyn FnMut(Acc, &C) -> Acc + Send>;
+
+/// Folds all entries into accumulator under one read lock, in no particular
+/// order, e.g. to sum field of entries without cloning them
+#[derive(Message)]
+#[rtype(result = "Result<Acc>")]
+pub struct Reduce<Conn, Table, C, Acc>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    Acc: Send + 'static,
+{
+    /// Initial value of accumulator
+    pub init: Acc,
+    /// Closure folding entry into accumulator
+    pub f: Folder<Acc, C>,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C, Acc> Reduce<Conn, Table, C, Acc>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    Acc: Send + 'static,
+{
+    /// Constructor
+    pub fn new(init: Acc, f: impl FnMut(Acc, &C) -> Acc + Send + 'static) -> Self {
+        Self {
+            init,
+            f: Box::new(f),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C, Acc> Debug for Reduce<Conn, Table, C, Acc>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    Acc: Send + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reduce").finish()
+    }
+}
+
+/// Changes cached entry in memory only, e.g. field computed by application
+/// which is not stored in db. Returns changed entry, `None` if there is no
+/// such entry in cache. Db is neither read nor written, so change is lost on
+/// next reload of cache.
+#[derive(Message)]
+#[rtype(result = "Result<Option<C>>")]
+pub struct UpdateCache<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Id of entry to change
+    pub id: C::Id,
+    /// Closure changing entry
+    pub mutate: Box<dyn FnOnce(&mut C) + Send>,
+}
+
+impl<Conn, Table, C> UpdateCache<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Constructor
+    pub fn new(id: C::Id, mutate: impl FnOnce(&mut C) + Send + 'static) -> Self {
+        Self {
+            id,
+            mutate: Box::new(mutate),
+        }
+    }
+}
+
+impl<Conn, Table, C> Debug for UpdateCache<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UpdateCache").field("id", &self.id).finish()
+    }
+}
+
+/// Replaces cached entry without touching db and returns previous one,
+/// `None` if there was no entry with id. Cache is marked invalid, so it is
+/// reconciled with db by next reload.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Option<C>>")]
+pub struct SwapEntry<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of entry to replace
+    pub id: C::Id,
+    /// Entry to put in cache
+    pub new_value: C,
+}
+
+/// Closure of [`Inspect`] called with actor
+pub type Inspector<Conn, Table, C, R, S = RandomState> =
+    Box<dyn FnOnce(&CacheDbActor<Conn, Table, C, S>) -> R + Send>;
+
+/// Calls closure with actor and returns its result, e.g. to check state of
+/// actor with its methods like
+/// [`CacheDbActor::is_valid`](crate::CacheDbActor::is_valid) in tests.
+/// Closure runs inside of actor, so it must not wait for messages sent to
+/// the same actor, which would deadlock.
+#[derive(Message)]
+#[rtype(result = "R")]
+pub struct Inspect<Conn, Table, C, R, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    R: 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Closure called with actor
+    pub f: Inspector<Conn, Table, C, R, S>,
+}
+
+impl<Conn, Table, C, R, S> Inspect<Conn, Table, C, R, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    R: 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Constructor
+    pub fn new(f: impl FnOnce(&CacheDbActor<Conn, Table, C, S>) -> R + Send + 'static) -> Self {
+        Self { f: Box::new(f) }
+    }
+}
+
+impl<Conn, Table, C, R, S> Debug for Inspect<Conn, Table, C, R, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    R: 'static,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Inspect").finish()
+    }
+}
+
+/// Loads entries matching `filter` straight from db, e.g.
+/// `GetAllFiltered::new(shop::name.eq("Nike"))`. Cache is neither read nor
+/// updated, actor only serializes query with other db operations.
+///
+/// Filters built at runtime can be boxed as
+/// `Box<dyn BoxableExpression<Table, Backend, SqlType = Bool> + Send>`.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetAllFiltered<Conn, Table, C, F>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Sql filter of entries
+    pub filter: F,
+    _c: PhantomData<(Conn, Table, C)>,
+}
+
+impl<Conn, Table, C, F> GetAllFiltered<Conn, Table, C, F>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Constructor
+    pub fn new(filter: F) -> Self {
+        Self {
+            filter,
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C, F> Debug for GetAllFiltered<Conn, Table, C, F>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GetAllFiltered").finish()
+    }
+}
+
+/// Gets all entries sorted by key
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct SortedGetAll<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    K: Ord + 'static,
+{
+    /// Key to sort entries by. Called once per entry.
+    pub key: fn(&C) -> K,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C, K> SortedGetAll<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    K: Ord + 'static,
+{
+    /// Constructor
+    pub fn new(key: fn(&C) -> K) -> Self {
+        Self {
+            key,
+            _c: PhantomData,
+        }
+    }
+}
+
+/// Gets all entries sorted by key computed with closure
+#[derive(Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetAllSorted<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    K: Ord + Clone + 'static,
+{
+    /// Key to sort entries by. Called once per entry.
+    pub key_fn: Arc<dyn Fn(&C) -> K + Send + Sync>,
+    /// Sort in descending order instead of ascending
+    pub descending: bool,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C, K> GetAllSorted<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    K: Ord + Clone + 'static,
+{
+    /// Constructor for ascending order
+    pub fn new(key_fn: impl Fn(&C) -> K + Send + Sync + 'static) -> Self {
+        Self {
+            key_fn: Arc::new(key_fn),
+            descending: false,
+            _c: PhantomData,
+        }
+    }
+
+    /// Sorts in descending order
+    pub fn descending(mut self) -> Self {
+        self.descending = true;
+        self
+    }
+}
+
+impl<Conn, Table, C, K> Debug for GetAllSorted<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    K: Ord + Clone + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GetAllSorted")
+            .field("descending", &self.descending)
+            .finish()
+    }
+}
+
+/// Gets `n` entries with the largest keys in descending order. Entries with
+/// equal keys are ordered by id.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetTopN<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    C::Id: Ord,
+    K: Ord + 'static,
+{
+    /// Maximum number of returned entries
+    pub n: usize,
+    /// Key to order entries by. Called once per entry.
+    pub key_fn: Arc<dyn Fn(&C) -> K + Send + Sync>,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C, K> GetTopN<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    C::Id: Ord,
+    K: Ord + 'static,
+{
+    /// Constructor
+    pub fn new(n: usize, key_fn: impl Fn(&C) -> K + Send + Sync + 'static) -> Self {
+        Self {
+            n,
+            key_fn: Arc::new(key_fn),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C, K> Debug for GetTopN<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    C::Id: Ord,
+    K: Ord + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GetTopN").field("n", &self.n).finish()
+    }
+}
+
+/// Gets `n` entries with the smallest keys in ascending order. Entries with
+/// equal keys are ordered by id.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetBottomN<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    C::Id: Ord,
+    K: Ord + 'static,
+{
+    /// Maximum number of returned entries
+    pub n: usize,
+    /// Key to order entries by. Called once per entry.
+    pub key_fn: Arc<dyn Fn(&C) -> K + Send + Sync>,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C, K> GetBottomN<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    C::Id: Ord,
+    K: Ord + 'static,
+{
+    /// Constructor
+    pub fn new(n: usize, key_fn: impl Fn(&C) -> K + Send + Sync + 'static) -> Self {
+        Self {
+            n,
+            key_fn: Arc::new(key_fn),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C, K> Debug for GetBottomN<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    C::Id: Ord,
+    K: Ord + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GetBottomN").field("n", &self.n).finish()
+    }
+}
+
+/// Gets entries with ids in `start..=end` sorted by id.
+///
+/// Cache map is hashed, so all entries are scanned and sorted. With `sorted`
+/// feature ids are kept in `BTreeSet` built on first `GetRange`, so range is
+/// found in `O(log n + k)`.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetRange<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    C::Id: Ord,
+{
+    /// First id of range
+    pub start: C::Id,
+    /// Last id of range, inclusive
+    pub end: C::Id,
+}
+
+/// Gets all entries grouped by key computed with closure. Entries within
+/// group are in no particular order.
+#[derive(Message)]
+#[rtype(result = "Result<CacheMap<K, Vec<C>>>")]
+pub struct GroupBy<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    K: Hash + Eq + Clone + 'static,
+{
+    /// Key to group entries by. Called once per entry.
+    pub key_fn: Arc<dyn Fn(&C) -> K + Send + Sync>,
+    _c: PhantomData<(Conn, Table)>,
+}
+
+impl<Conn, Table, C, K> GroupBy<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    K: Hash + Eq + Clone + 'static,
+{
+    /// Constructor
+    pub fn new(key_fn: impl Fn(&C) -> K + Send + Sync + 'static) -> Self {
+        Self {
+            key_fn: Arc::new(key_fn),
+            _c: PhantomData,
+        }
+    }
+}
+
+impl<Conn, Table, C, K> Debug for GroupBy<Conn, Table, C, K>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    K: Hash + Eq + Clone + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GroupBy").finish()
+    }
+}
+
+/// Marks cache invalid and reloads it after messages which are already
+/// queued are handled. Unlike [`Clear`], response resolves only when reload
+/// is done, so caller sees fresh cache right after it.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<()>")]
+pub struct GracefulClear;
+
+/// Drops all cached entries without touching db. Entries are reloaded on next
+/// read.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "()")]
+pub struct Clear;
+
+/// Reinitializes actor, e.g. after restore of db: drops all cached entries,
+/// resets statistics, reloads cache from db and schedules next periodic
+/// update a full interval after reload. Prometheus metrics are not reset.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<()>")]
+pub struct Reset;
+
+/// Reloads all entries from db. Responds after reload is finished.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<()>")]
+pub struct Invalidate;
+
+/// Schedules reload of all entries and responds at once with receiver of
+/// reload result, which may be dropped. Old entries are served while reload
+/// runs.
+///
+/// Entries are loaded in blocking thread pool of tokio with connection
+/// checked out of pool, so actor keeps handling other messages. Actor with
+/// one connection can't give it away, so its reload runs inside of actor
+/// after this message is handled. Result of load is dropped if cache is
+/// reloaded by other message meanwhile.
+#[cfg(all(feature = "futures", feature = "tokio"))]
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<futures::channel::oneshot::Receiver<Result<()>>>")]
+pub struct SpawnRefresh;
+
+/// Releases memory allocated by cache map beyond number of entries and
+/// capacity set in builder, e.g. after bulk delete. Periodic update does it
+/// automatically when map is sparse, see
+/// [`CacheDbActorBuilder::shrink_threshold`](crate::CacheDbActorBuilder::shrink_threshold).
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<()>")]
+pub struct Shrink;
+
+/// Gets all entries which were put in cache less than `max_age` ago
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetByAge<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Maximum age of returned entries
+    pub max_age: Duration,
+    _c: PhantomData<(Conn, Table, C)>,
+}
+
+impl<Conn, Table, C> GetByAge<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+{
+    /// Constructor
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            _c: PhantomData,
+        }
+    }
+}
+
+/// Gets page of entries sorted by id. Page past the last entry is empty.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetPage<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    C::Id: Ord,
+{
+    /// Number of entries to skip
+    pub offset: usize,
+    /// Maximum number of returned entries
+    pub limit: usize,
+    _c: PhantomData<(Conn, Table, C)>,
+}
+
+impl<Conn, Table, C> GetPage<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + 'static,
+    C::Id: Ord,
+{
+    /// Constructor
+    pub fn new(offset: usize, limit: usize) -> Self {
+        Self {
+            offset,
+            limit,
+            _c: PhantomData,
+        }
+    }
+}
+
+/// Removes entries which were put in cache more than `max_age` ago. Database
+/// is not touched. Returns number of evicted entries.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<usize>")]
+pub struct EvictOlderThan {
+    /// Maximum age of kept entries
+    pub max_age: Duration,
+}
+
+/// Logs all cached entries at debug level, e.g. from debug endpoint during
+/// incident. With `tracing` feature entries are emitted as structured fields
+/// of tracing event, otherwise they are logged with `log`.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<()>")]
+pub struct DebugDump;
+
+/// Checks that actor handles messages and its db connection is alive, e.g.
+/// for liveness probes. Runs `SELECT 1`, cache is not touched.
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<()>")]
+pub struct HealthCheck;
+
+/// Runs raw SQL statements, e.g. `REFRESH MATERIALIZED VIEW`, on connection
+/// of actor. Cache is not touched, but statements are serialized with writes
+/// of cache.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "Result<()>")]
+pub struct RawSql(pub String);
+
+/// Runs raw SQL query on connection of actor and loads its rows into `R`,
+/// see `diesel::sql_query`. Cache is not touched.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<R>>")]
+pub struct RawQuery<R: 'static>(pub String, PhantomData<R>);
+
+impl<R> RawQuery<R> {
+    /// Constructor
+    pub fn new(sql: impl Into<String>) -> Self {
+        Self(sql.into(), PhantomData)
+    }
+}
+
+impl<R> Debug for RawQuery<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("RawQuery").field(&self.0).finish()
+    }
+}
+
+/// Gets number of cached entries
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Result<usize>")]
+pub struct Count;
+
+/// Gets number of entries loaded by last reload without db access. Entries
+/// written or evicted since are not counted, unlike in [`Count`].
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "usize")]
+pub struct LoadedCount;
+
+/// Gets time of last reload, `None` if cache was never loaded from db
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "Option<Instant>")]
+pub struct LastRefreshInstant;
+
+/// Gets statistics of cache usage
+#[derive(Debug, Clone, Copy, Message)]
+#[rtype(result = "CacheStats")]
+pub struct GetStats;
+
+/// Updates columns of item by id in db and cache
+#[derive(Debug, Message)]
+#[rtype(result = "Result<()>")]
+pub struct UpdateField<Conn, Table, C, F>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of item to update
+    pub id: C::Id,
+    /// Changeset to apply, e.g. `shop::address.eq("Central street")`
+    pub changeset: F,
+}
+
+/// Updates item in db and cache only if its version in db is still
+/// `expected_version`, otherwise fails with
+/// [`CacheError::VersionConflict`](crate::CacheError::VersionConflict).
+/// Version is checked in `WHERE` clause of update and incremented by it, so
+/// changeset shouldn't touch version column, e.g. `doc::body.eq(body)`.
+/// Returns updated item.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<C>")]
+pub struct VersionedSave<Conn, Table, C, F>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of item to update
+    pub id: C::Id,
+    /// Version which writer has seen
+    pub expected_version: u64,
+    /// Changeset to apply, without version column
+    pub changeset: F,
+}
+
+/// Replaces all cached entries with given ones without touching db, e.g. with
+/// entries imported by other means on startup. Entries are trusted to be up
+/// to date, so cache is valid until next update. Returns number of loaded
+/// entries.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<usize>")]
+pub struct BulkLoad<C>(pub Vec<C>);
+
+/// Puts entries to cache, overwriting ones with the same ids. Db is not
+/// touched. Cache is marked invalid, so merged entries are kept only until
+/// next reload, e.g. by timer or by next read. Returns number of merged
+/// entries.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<usize>")]
+pub struct Merge<Conn, Table, C>(pub CacheMap<C::Id, C>)
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>;
+
+/// Updates many items in db by ids in one transaction and reloads cache.
+/// Nothing is updated if any update fails. Returns number of updated rows.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<usize>")]
+pub struct UpdateMany<Conn, Table, C, W>(pub Vec<(C::Id, W)>)
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>;
+
+/// Deletes item by id from db and cache. Returns whether item was deleted
+/// from db.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<bool>")]
+pub struct Delete<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of item to delete
+    pub id: C::Id,
+}
+
+/// Marks item deleted in db with [`SoftDeleteCache::soft_delete`] and
+/// removes it from cache. Returns whether item was marked deleted.
+///
+/// [`SoftDeleteCache::soft_delete`]: crate::SoftDeleteCache::soft_delete
+#[derive(Debug, Message)]
+#[rtype(result = "Result<bool>")]
+pub struct SoftDelete<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Id of item to delete
+    pub id: C::Id,
+}
+
+/// Loads entries modified after `since` from db, e.g. for incremental sync.
+/// Entries are not put in cache, so it doesn't end up partially updated.
+///
+/// Available only for entries implementing
+/// [`TimestampedCache`](crate::TimestampedCache).
+#[derive(Debug, Message)]
+#[rtype(result = "Result<Vec<C>>")]
+pub struct GetChangedSince<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: TimestampedCache<Conn, Table>,
+{
+    /// Entries modified strictly after this time are loaded
+    pub since: C::Timestamp,
+}
+
+/// Deletes items by ids from db in one query and from cache. Returns number
+/// of rows deleted from db.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<usize>")]
+pub struct DeleteMany<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Ids of items to delete
+    pub ids: Vec<C::Id>,
+}
+
+/// Change of cached entry sent to subscribers, see [`Subscribe`]
+#[derive(Message)]
+#[rtype(result = "()")]
+pub enum CacheEvent<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Entry was added to cache
+    Inserted(C),
+    /// Entry already in cache was replaced
+    Updated(C),
+    /// Entry with id was removed from cache
+    Deleted(C::Id),
+}
+
+impl<Conn, Table, C> Clone for CacheEvent<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    fn clone(&self) -> Self {
+        match self {
+            CacheEvent::Inserted(v) => CacheEvent::Inserted(v.clone()),
+            CacheEvent::Updated(v) => CacheEvent::Updated(v.clone()),
+            CacheEvent::Deleted(id) => CacheEvent::Deleted(id.clone()),
+        }
+    }
+}
+
+impl<Conn, Table, C> Debug for CacheEvent<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheEvent::Inserted(v) => f.debug_tuple("Inserted").field(v).finish(),
+            CacheEvent::Updated(v) => f.debug_tuple("Updated").field(v).finish(),
+            CacheEvent::Deleted(id) => f.debug_tuple("Deleted").field(id).finish(),
+        }
+    }
+}
+
+/// Compares cached entries with entries of `other` map, e.g. for
+/// reconciling imports. Entries are compared with `PartialEq`.
+#[derive(Debug, Message)]
+#[rtype(result = "Result<CacheDiff<Conn, Table, C>>")]
+pub struct Diff<Conn, Table, C>(pub CacheMap<C::Id, C>)
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + PartialEq;
+
+/// Difference between cache and other map, see [`Diff`]
+pub struct CacheDiff<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Entries which are cached, but missing in other map
+    pub only_in_cache: CacheMap<C::Id, C>,
+    /// Entries of other map which are not cached
+    pub only_in_other: CacheMap<C::Id, C>,
+    /// Entries with the same id, which differ. Cached entry goes first.
+    pub both_modified: CacheMap<C::Id, (C, C)>,
+}
+
+impl<Conn, Table, C> Debug for CacheDiff<Conn, Table, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacheDiff")
+            .field("only_in_cache", &self.only_in_cache)
+            .field("only_in_other", &self.only_in_other)
+            .field("both_modified", &self.both_modified)
+            .finish()
+    }
+}
+
+/// Registers recipient of [`CacheEvent`]s. Events are sent after every
+/// change of cache. Entries changed in db by others are reported on reload
+/// as `Inserted` or `Deleted` only, as entries can't be compared. Recipients
+/// whose actor is stopped are dropped automatically.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Subscribe<Conn, Table, C>(pub Recipient<CacheEvent<Conn, Table, C>>)
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send,
+    C::Id: Send;
+
+/// Removes recipient registered with [`Subscribe`]
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Unsubscribe<Conn, Table, C>(pub Recipient<CacheEvent<Conn, Table, C>>)
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send,
+    C::Id: Send;
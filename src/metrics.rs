@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use prometheus::{Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry};
+
+/// Prometheus metrics of one cache actor. Every metric carries `table` label,
+/// so actors of different tables can share registry.
+pub(crate) struct CacheMetrics {
+    reads: IntCounterVec,
+    writes: IntCounter,
+    refresh_duration: Histogram,
+    entries: IntGauge,
+}
+
+impl CacheMetrics {
+    /// Creates metrics and registers them in `registry`
+    pub(crate) fn register(registry: &Registry, table: &str) -> prometheus::Result<Self> {
+        let reads = IntCounterVec::new(
+            Opts::new("cache_reads_total", "Number of cache reads").const_label("table", table),
+            &["result"],
+        )?;
+        let writes = IntCounter::with_opts(
+            Opts::new("cache_writes_total", "Number of cache writes").const_label("table", table),
+        )?;
+        let refresh_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "cache_refresh_duration_seconds",
+                "Duration of full reloads of cache",
+            )
+            .const_label("table", table),
+        )?;
+        let entries = IntGauge::with_opts(
+            Opts::new("cache_entries", "Number of entries in cache").const_label("table", table),
+        )?;
+        registry.register(Box::new(reads.clone()))?;
+        registry.register(Box::new(writes.clone()))?;
+        registry.register(Box::new(refresh_duration.clone()))?;
+        registry.register(Box::new(entries.clone()))?;
+        Ok(Self {
+            reads,
+            writes,
+            refresh_duration,
+            entries,
+        })
+    }
+
+    pub(crate) fn read(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.reads.with_label_values(&[result]).inc();
+    }
+
+    pub(crate) fn write(&self) {
+        self.writes.inc();
+    }
+
+    pub(crate) fn refresh(&self, duration: Duration) {
+        self.refresh_duration.observe(duration.as_secs_f64());
+    }
+
+    pub(crate) fn set_entries(&self, entries: usize) {
+        self.entries.set(entries as i64);
+    }
+}
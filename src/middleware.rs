@@ -0,0 +1,43 @@
+use std::marker::Unpin;
+
+use diesel::associations::HasTable;
+use diesel::connection::Connection;
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use diesel::sql_types::HasSqlType;
+
+use crate::{Cache, ConnBackend};
+
+/// Hooks called by cache actor around handling of messages, e.g. for timing
+/// or fault injection in tests. All hooks do nothing by default. Register
+/// with [`CacheDbActorBuilder::with_middleware`](crate::CacheDbActorBuilder::with_middleware).
+///
+/// Hooks are called synchronously inside of actor in order of registration.
+pub trait CacheMiddleware<Conn, Table, C>: Send + Sync + 'static
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+{
+    /// Called before `Get` looks up entry
+    fn before_get(&self, _id: &C::Id) {}
+
+    /// Called after `Get` with whether entry was found in cache
+    fn after_get(&self, _id: &C::Id, _hit: bool) {}
+
+    /// Called before `Save` writes to db
+    fn before_save(&self) {}
+
+    /// Called after `Save` with whether it succeeded
+    fn after_save(&self, _ok: bool) {}
+
+    /// Called before `Delete` deletes from db
+    fn before_delete(&self, _id: &C::Id) {}
+
+    /// Called after `Delete` with whether it succeeded, after all retries
+    fn after_delete(&self, _id: &C::Id, _ok: bool) {}
+
+    /// Called after every reload of cache with whether it succeeded
+    fn after_reload(&self, _ok: bool) {}
+}
@@ -0,0 +1,144 @@
+use std::any::{type_name, Any, TypeId};
+use std::marker::Unpin;
+
+use actix::prelude::*;
+
+use diesel::associations::HasTable;
+use diesel::connection::Connection;
+use diesel::insertable::CanInsertInSingleQuery;
+use diesel::prelude::*;
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use diesel::sql_types::HasSqlType;
+
+use crate::messages::{TypedGet, TypedSave};
+use crate::{Cache, CacheError, CacheMap, ConnBackend, Result};
+
+/// Actor caching entries of many tables with one connection, for services
+/// with many small tables where actor per table is wasteful.
+///
+/// Every pair of table and entry type registered with
+/// [`MultiCache::register`] gets its own sub-cache, which is loaded with
+/// [`Cache::read_all`] and driven by `Cache` impl of entry type. Supports
+/// only [`TypedGet`] and [`TypedSave`], there are no periodic updates.
+pub struct MultiCache<Conn> {
+    /// Connection shared by all sub-caches
+    conn: Conn,
+    /// Sub-caches by types of table and entries, every one is
+    /// `CacheMap<C::Id, C>`
+    caches: CacheMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl<Conn> MultiCache<Conn>
+where
+    Conn: Connection + Unpin + 'static,
+{
+    /// Constructor without sub-caches
+    pub fn new(conn: Conn) -> Self {
+        Self {
+            conn,
+            caches: Default::default(),
+        }
+    }
+
+    /// Loads all entries of `C` from `Table` into new sub-cache. Registering
+    /// the same types again reloads their sub-cache.
+    pub fn register<Table, C>(mut self) -> Result<Self>
+    where
+        Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+        Table: diesel::Table + HasTable<Table = Table> + AsQuery + 'static,
+        Table::Query: QueryId + QueryFragment<Conn::Backend>,
+        C: Cache<Conn, Table> + Send + Sync,
+        C::Id: Send + Sync,
+    {
+        self.update::<Table, C>()?;
+        Ok(self)
+    }
+
+    /// Reloads sub-cache of `C` from db
+    fn update<Table, C>(&mut self) -> Result<()>
+    where
+        Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+        Table: diesel::Table + HasTable<Table = Table> + AsQuery + 'static,
+        Table::Query: QueryId + QueryFragment<Conn::Backend>,
+        C: Cache<Conn, Table> + Send + Sync,
+        C::Id: Send + Sync,
+    {
+        let cache = C::read_all(&self.conn)?;
+        self.caches
+            .insert(TypeId::of::<(Table, C)>(), Box::new(cache));
+        Ok(())
+    }
+
+    /// Sub-cache of `C`, fails if it was not registered
+    fn cache_mut<Table, C>(&mut self) -> Result<&mut CacheMap<C::Id, C>>
+    where
+        Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+        Table: diesel::Table + HasTable<Table = Table> + AsQuery + 'static,
+        Table::Query: QueryId + QueryFragment<Conn::Backend>,
+        C: Cache<Conn, Table> + Send + Sync,
+        C::Id: Send + Sync,
+    {
+        self.caches
+            .get_mut(&TypeId::of::<(Table, C)>())
+            .and_then(|it| it.downcast_mut())
+            .ok_or_else(|| CacheError::NotFound(format!("cache of {}", type_name::<C>())))
+    }
+}
+
+impl<Conn> Actor for MultiCache<Conn>
+where
+    Conn: Connection + Unpin + 'static,
+{
+    type Context = Context<Self>;
+}
+
+impl<Conn, Table, C> Handler<TypedGet<Conn, Table, C>> for MultiCache<Conn>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+{
+    type Result = Result<Option<C>>;
+
+    fn handle(
+        &mut self,
+        TypedGet { id }: TypedGet<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        if let Some(out) = self.cache_mut::<Table, C>()?.get(&id) {
+            return Ok(Some(out.clone()));
+        }
+        let loaded = C::read_many(std::slice::from_ref(&id), &self.conn)?;
+        self.cache_mut::<Table, C>()?.extend(loaded);
+        Ok(self.cache_mut::<Table, C>()?.get(&id).cloned())
+    }
+}
+
+impl<Conn, Table, W, C> Handler<TypedSave<Conn, Table, W, C>> for MultiCache<Conn>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+    W: Insertable<Table>,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+{
+    type Result = Result<()>;
+
+    fn handle(
+        &mut self,
+        pred: TypedSave<Conn, Table, W, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        // Fail before writing if sub-cache is missing
+        self.cache_mut::<Table, C>()?;
+        C::write_one(pred.w, &self.conn)?;
+        self.update::<Table, C>()
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::{Arc, Mutex};
+
+use actix::prelude::*;
+use futures::channel::mpsc;
+use futures::stream::{self, StreamExt};
+use tokio_postgres::{AsyncMessage, NoTls};
+
+use crate::messages::Invalidate;
+
+/// Channel of postgres notifications listened by cache actors. Payload of
+/// notification is name of changed table, e.g. `NOTIFY cache_invalidate, 'shop'`.
+pub const NOTIFY_CHANNEL: &str = "cache_invalidate";
+
+/// Address of actor shared with listener. Actor clears it when it stops, so
+/// listener exits on next notification. Kept as `Recipient`, so actor which
+/// holds it doesn't depend on its own `Actor` impl and its bounds.
+pub(crate) type SharedAddr = Arc<Mutex<Option<Recipient<Invalidate>>>>;
+
+/// Listens to [`NOTIFY_CHANNEL`] on separate connection and sends
+/// `Invalidate` to actor on every notification for `table`. Returns when
+/// connection is closed or actor is stopped.
+pub(crate) async fn listen(database_url: String, table: &'static str, addr: SharedAddr) {
+    let (client, mut connection) = match tokio_postgres::connect(&database_url, NoTls).await {
+        Ok(it) => it,
+        Err(e) => {
+            log::error!("cache notification listener failed to connect: {}", e);
+            return;
+        }
+    };
+    // Connection has to be polled for client queries to complete, so
+    // notifications are forwarded from separate task.
+    let (tx, mut rx) = mpsc::unbounded();
+    actix::spawn(async move {
+        let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(message) => {
+                    if tx.unbounded_send(message).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    log::error!("cache notification listener connection failed: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+    if let Err(e) = client
+        .batch_execute(&format!("LISTEN {}", NOTIFY_CHANNEL))
+        .await
+    {
+        log::error!("cache notification listener failed to listen: {}", e);
+        return;
+    }
+    while let Some(message) = rx.next().await {
+        let notification = match message {
+            AsyncMessage::Notification(it) => it,
+            _ => continue,
+        };
+        if notification.payload() != table {
+            continue;
+        }
+        let sent = match &*addr.lock().unwrap_or_else(|e| e.into_inner()) {
+            Some(addr) => addr.do_send(Invalidate).is_ok(),
+            None => false,
+        };
+        if !sent {
+            break;
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use diesel::result::{DatabaseErrorKind, Error};
+
+/// Policy of retrying db operations failed with transient errors, e.g.
+/// serialization failures of concurrent transactions. Set with
+/// [`CacheDbActorBuilder::retry_policy`](crate::CacheDbActorBuilder::retry_policy).
+pub trait RetryPolicy: Send + Sync {
+    /// Whether operation failed with `err` should be retried. `attempt` is
+    /// number of failed attempts so far, starting from one.
+    fn should_retry(&self, err: &Error, attempt: u32) -> bool;
+
+    /// Delay before next attempt
+    fn delay(&self, attempt: u32) -> Duration;
+}
+
+/// Retries serialization failures up to `max_attempts` times with fixed
+/// delay between attempts
+#[derive(Debug, Clone, Copy)]
+pub struct SerializationFailureRetry {
+    /// Maximum number of retries
+    pub max_attempts: u32,
+    /// Delay before every retry
+    pub delay: Duration,
+}
+
+impl RetryPolicy for SerializationFailureRetry {
+    fn should_retry(&self, err: &Error, attempt: u32) -> bool {
+        attempt <= self.max_attempts
+            && matches!(
+                err,
+                Error::DatabaseError(DatabaseErrorKind::SerializationFailure, _)
+            )
+    }
+
+    fn delay(&self, _: u32) -> Duration {
+        self.delay
+    }
+}
@@ -0,0 +1,102 @@
+use std::any::Any;
+use std::hash::Hash;
+
+use crate::{CacheMap, CacheSet};
+
+/// Index of cached entries by secondary key, see
+/// [`SecondaryIndexCache`](crate::SecondaryIndexCache).
+///
+/// Key type is erased, so that actor is generic over any [`Cache`](crate::Cache)
+/// and only entries which implement the trait pay for the index.
+pub(crate) struct SecondaryIndex<Id, C> {
+    keys: Box<dyn KeyIndex<Id, C>>,
+}
+
+impl<Id, C> SecondaryIndex<Id, C>
+where
+    Id: Hash + Eq + Clone + 'static,
+    C: 'static,
+{
+    /// Builds index of `entries` with keys returned by `key_of`
+    pub(crate) fn new<'a, K>(
+        key_of: fn(&C) -> Option<K>,
+        entries: impl Iterator<Item = (&'a Id, &'a C)>,
+    ) -> Self
+    where
+        K: Hash + Eq + 'static,
+    {
+        let mut keys = Keys {
+            map: CacheMap::default(),
+            key_of,
+        };
+        for (id, v) in entries {
+            keys.insert(id, v);
+        }
+        Self {
+            keys: Box::new(keys),
+        }
+    }
+
+    /// Indexes entry put in cache
+    pub(crate) fn insert(&mut self, id: &Id, v: &C) {
+        self.keys.insert(id, v)
+    }
+
+    /// Removes entry taken out of cache from index. Other entries with the
+    /// same key are kept.
+    pub(crate) fn remove(&mut self, id: &Id, v: &C) {
+        self.keys.remove(id, v)
+    }
+
+    /// Ids of entries with key
+    pub(crate) fn get<K>(&self, key: &K) -> Option<&CacheSet<Id>>
+    where
+        K: Hash + Eq + 'static,
+    {
+        self.keys
+            .as_any()
+            .downcast_ref::<Keys<K, Id, C>>()
+            .and_then(|keys| keys.map.get(key))
+    }
+}
+
+trait KeyIndex<Id, C> {
+    fn insert(&mut self, id: &Id, v: &C);
+    fn remove(&mut self, id: &Id, v: &C);
+    fn as_any(&self) -> &dyn Any;
+}
+
+struct Keys<K, Id, C> {
+    map: CacheMap<K, CacheSet<Id>>,
+    key_of: fn(&C) -> Option<K>,
+}
+
+impl<K, Id, C> KeyIndex<Id, C> for Keys<K, Id, C>
+where
+    K: Hash + Eq + 'static,
+    Id: Hash + Eq + Clone + 'static,
+    C: 'static,
+{
+    fn insert(&mut self, id: &Id, v: &C) {
+        if let Some(key) = (self.key_of)(v) {
+            self.map.entry(key).or_default().insert(id.clone());
+        }
+    }
+
+    fn remove(&mut self, id: &Id, v: &C) {
+        let key = match (self.key_of)(v) {
+            Some(key) => key,
+            None => return,
+        };
+        if let Some(ids) = self.map.get_mut(&key) {
+            ids.remove(id);
+            if ids.is_empty() {
+                self.map.remove(&key);
+            }
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
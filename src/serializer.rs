@@ -0,0 +1,71 @@
+#[cfg(feature = "bincode")]
+use bincode_crate::{config, decode_from_slice, encode_to_vec, Decode, Encode};
+#[cfg(feature = "serde")]
+use serde_crate::{de::DeserializeOwned, Serialize};
+
+use crate::Result;
+
+/// Format of entries dumped with [`DumpCache`](crate::messages::DumpCache)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Serializer {
+    /// JSON, readable but slow and large
+    #[cfg(feature = "serde")]
+    Json,
+    /// Compact binary format of `bincode`
+    #[cfg(feature = "bincode")]
+    Bincode,
+}
+
+impl Default for Serializer {
+    /// JSON if `serde` feature is enabled, otherwise bincode
+    fn default() -> Self {
+        #[cfg(feature = "serde")]
+        return Serializer::Json;
+        #[cfg(not(feature = "serde"))]
+        return Serializer::Bincode;
+    }
+}
+
+/// Entries which can be dumped with every serializer enabled by features.
+/// Implemented for all such types.
+#[cfg(all(feature = "serde", not(feature = "bincode")))]
+pub trait Serializable: Serialize + DeserializeOwned {}
+#[cfg(all(feature = "serde", not(feature = "bincode")))]
+impl<T: Serialize + DeserializeOwned> Serializable for T {}
+
+/// Entries which can be dumped with every serializer enabled by features.
+/// Implemented for all such types.
+#[cfg(all(feature = "bincode", not(feature = "serde")))]
+pub trait Serializable: Encode + Decode<()> {}
+#[cfg(all(feature = "bincode", not(feature = "serde")))]
+impl<T: Encode + Decode<()>> Serializable for T {}
+
+/// Entries which can be dumped with every serializer enabled by features.
+/// Implemented for all such types.
+#[cfg(all(feature = "serde", feature = "bincode"))]
+pub trait Serializable: Serialize + DeserializeOwned + Encode + Decode<()> {}
+#[cfg(all(feature = "serde", feature = "bincode"))]
+impl<T: Serialize + DeserializeOwned + Encode + Decode<()>> Serializable for T {}
+
+impl Serializer {
+    pub(crate) fn encode<C: Serializable>(self, entries: &[&C]) -> Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "serde")]
+            Serializer::Json => Ok(serde_json::to_vec(entries)?),
+            #[cfg(feature = "bincode")]
+            Serializer::Bincode => Ok(encode_to_vec(entries, config::standard())?),
+        }
+    }
+
+    pub(crate) fn decode<C: Serializable>(self, bytes: &[u8]) -> Result<Vec<C>> {
+        match self {
+            #[cfg(feature = "serde")]
+            Serializer::Json => Ok(serde_json::from_slice(bytes)?),
+            #[cfg(feature = "bincode")]
+            Serializer::Bincode => {
+                let (entries, _) = decode_from_slice(bytes, config::standard())?;
+                Ok(entries)
+            }
+        }
+    }
+}
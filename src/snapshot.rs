@@ -0,0 +1,257 @@
+use std::collections::hash_map::{self, RandomState};
+use std::fmt::Debug;
+use std::hash::BuildHasher;
+use std::iter::FromIterator;
+use std::ops::{Deref, Index};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+use std::time::Instant;
+
+use diesel::associations::HasTable;
+use diesel::connection::Connection;
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use diesel::sql_types::HasSqlType;
+
+use crate::{Cache, CacheError, CacheMap, ConnBackend, Result};
+
+/// Immutable view of all cached entries at some moment. Later writes to
+/// cache are not visible in snapshot, so no locking is needed to read it.
+pub struct CacheSnapshot<Conn, Table, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    entries: Arc<CacheMap<C::Id, C, S>>,
+    /// Time when snapshot was taken
+    pub captured_at: Instant,
+}
+
+impl<Conn, Table, C, S> CacheSnapshot<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    pub(crate) fn new(entries: Arc<CacheMap<C::Id, C, S>>) -> Self {
+        Self {
+            entries,
+            captured_at: Instant::now(),
+        }
+    }
+
+    /// Gets entry by id
+    pub fn get(&self, id: &C::Id) -> Option<&C> {
+        self.entries.get(id)
+    }
+}
+
+/// Gets entry by id, panics if there is no such entry
+impl<Conn, Table, C, S> Index<&C::Id> for CacheSnapshot<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Output = C;
+
+    fn index(&self, id: &C::Id) -> &C {
+        &self.entries[id]
+    }
+}
+
+/// Iterates over entries in no particular order, which are cloned if
+/// snapshot is still shared. Items are entries without ids, same as taken by
+/// [`FromIterator`], so snapshot can be rebuilt from them.
+impl<Conn, Table, C, S> IntoIterator for CacheSnapshot<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Item = C;
+    type IntoIter = hash_map::IntoValues<C::Id, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Arc::try_unwrap(self.entries)
+            .unwrap_or_else(|shared| CacheMap::clone(&shared))
+            .into_values()
+    }
+}
+
+/// Builds snapshot of entries by their ids, taken at the moment of call
+impl<Conn, Table, C, S> FromIterator<C> for CacheSnapshot<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn from_iter<I: IntoIterator<Item = C>>(iter: I) -> Self {
+        let entries = iter.into_iter().map(|it| (it.get_id(), it)).collect();
+        Self::new(Arc::new(entries))
+    }
+}
+
+/// Takes entries in no particular order, which are cloned if snapshot is
+/// still shared
+impl<Conn, Table, C, S> From<CacheSnapshot<Conn, Table, C, S>> for Vec<C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn from(snapshot: CacheSnapshot<Conn, Table, C, S>) -> Self {
+        snapshot.into_iter().collect()
+    }
+}
+
+impl<Conn, Table, C, S> Deref for CacheSnapshot<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    type Target = CacheMap<C::Id, C, S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+impl<Conn, Table, C, S> Clone for CacheSnapshot<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            entries: Arc::clone(&self.entries),
+            captured_at: self.captured_at,
+        }
+    }
+}
+
+impl<Conn, Table, C, S> Debug for CacheSnapshot<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacheSnapshot")
+            .field("entries", &self.entries)
+            .field("captured_at", &self.captured_at)
+            .finish()
+    }
+}
+
+/// State of cache taken with [`Checkpoint`](crate::messages::Checkpoint),
+/// which can be brought back with [`Restore`](crate::messages::Restore).
+/// Token is kept in memory only.
+pub struct CacheCheckpointToken<Conn, Table, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    pub(crate) entries: Arc<CacheMap<C::Id, C, S>>,
+}
+
+impl<Conn, Table, C, S> Debug for CacheCheckpointToken<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacheCheckpointToken")
+            .field("entries", &self.entries.len())
+            .finish()
+    }
+}
+
+/// Cache map borrowed with [`BorrowCache`](crate::messages::BorrowCache) for
+/// many lookups under one read lock:
+///
+/// ```ignore
+/// let guard = addr.send(BorrowCache::default()).await??;
+/// let cache = guard.read()?;
+/// let shops: Vec<_> = ids.iter().filter_map(|id| cache.get(id)).collect();
+/// ```
+///
+/// Writes of actor wait for the lock to be released. Drop the lock before
+/// sending any write message, e.g. holding it while awaiting `Save`
+/// deadlocks the actor.
+pub struct CacheReadGuard<Conn, Table, C, S = RandomState>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    pub(crate) map: Arc<RwLock<CacheMap<C::Id, C, S>>>,
+}
+
+impl<Conn, Table, C, S> CacheReadGuard<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    /// Locks map for reading until returned guard is dropped
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, CacheMap<C::Id, C, S>>> {
+        self.map.read().map_err(|_| CacheError::LockPoisoned)
+    }
+}
+
+impl<Conn, Table, C, S> Debug for CacheReadGuard<Conn, Table, C, S>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table>,
+    S: BuildHasher + Default + Clone + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacheReadGuard").finish()
+    }
+}
@@ -0,0 +1,76 @@
+use std::any::Any;
+use std::collections::BTreeSet;
+
+/// Ids of cached entries in sorted order, see
+/// [`GetRange`](crate::messages::GetRange).
+///
+/// Id type is required to be `Ord` only where index is built, so that actor
+/// stays generic over any [`Cache`](crate::Cache).
+pub(crate) struct SortedIndex<Id> {
+    ids: Box<dyn IdIndex<Id>>,
+}
+
+impl<Id> SortedIndex<Id>
+where
+    Id: Clone + 'static,
+{
+    /// Builds index of `ids`
+    pub(crate) fn new<'a>(ids: impl Iterator<Item = &'a Id>) -> Self
+    where
+        Id: Ord,
+    {
+        Self {
+            ids: Box::new(Ids(ids.cloned().collect())),
+        }
+    }
+
+    /// Indexes id of entry put in cache
+    pub(crate) fn insert(&mut self, id: &Id) {
+        self.ids.insert(id)
+    }
+
+    /// Removes id of entry taken out of cache from index
+    pub(crate) fn remove(&mut self, id: &Id) {
+        self.ids.remove(id)
+    }
+
+    /// Ids in `start..=end` in ascending order. Range with `start` after
+    /// `end` is empty.
+    pub(crate) fn range(&self, start: Id, end: Id) -> impl Iterator<Item = &Id>
+    where
+        Id: Ord,
+    {
+        self.ids
+            .as_any()
+            .downcast_ref::<Ids<Id>>()
+            .filter(|_| start <= end)
+            .map(move |ids| ids.0.range(start..=end))
+            .into_iter()
+            .flatten()
+    }
+}
+
+trait IdIndex<Id> {
+    fn insert(&mut self, id: &Id);
+    fn remove(&mut self, id: &Id);
+    fn as_any(&self) -> &dyn Any;
+}
+
+struct Ids<Id>(BTreeSet<Id>);
+
+impl<Id> IdIndex<Id> for Ids<Id>
+where
+    Id: Ord + Clone + 'static,
+{
+    fn insert(&mut self, id: &Id) {
+        self.0.insert(id.clone());
+    }
+
+    fn remove(&mut self, id: &Id) {
+        self.0.remove(id);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Instant;
+
+use actix::prelude::*;
+
+/// Statistics of cache usage
+#[derive(Debug, Clone, Copy, Default, PartialEq, MessageResponse)]
+pub struct CacheStats {
+    /// Number of reads served from cache
+    pub hits: u64,
+    /// Number of reads which triggered update of cache
+    pub misses: u64,
+    /// Number of full reloads of cache from db
+    pub refreshes: u64,
+    /// Time of last full reload of cache
+    pub last_refresh: Option<Instant>,
+    /// Number of entries in cache
+    pub entry_count: usize,
+}
+
+impl CacheStats {
+    /// Fraction of reads served from cache. Returns `0.0` if there were no
+    /// reads yet.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+
+    /// Checks that hit rate is at least `min`. Useful for health checks.
+    pub fn hit_rate_threshold(&self, min: f64) -> bool {
+        self.hit_rate() >= min
+    }
+}
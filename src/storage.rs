@@ -0,0 +1,137 @@
+use std::hash::{BuildHasher, Hash};
+#[cfg(not(feature = "arc-swap"))]
+use std::sync::PoisonError;
+use std::sync::{Arc, RwLock};
+
+#[cfg(not(feature = "arc-swap"))]
+use crate::CacheError;
+use crate::{CacheMap, Result};
+
+#[cfg(not(feature = "arc-swap"))]
+pub(crate) type ReadGuard<'a, K, V, S> = std::sync::RwLockReadGuard<'a, CacheMap<K, V, S>>;
+#[cfg(feature = "arc-swap")]
+pub(crate) type ReadGuard<'a, K, V, S> = arc_swap::Guard<Arc<CacheMap<K, V, S>>>;
+
+/// Map of cached entries.
+///
+/// By default map is kept behind `RwLock`. With `arc-swap` feature reads are
+/// lock-free and every write swaps in modified copy of the whole map.
+pub(crate) struct Storage<K, V, S> {
+    #[cfg(not(feature = "arc-swap"))]
+    map: Arc<RwLock<CacheMap<K, V, S>>>,
+    #[cfg(feature = "arc-swap")]
+    map: Arc<arc_swap::ArcSwap<CacheMap<K, V, S>>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, S: BuildHasher + Clone> Storage<K, V, S> {
+    pub(crate) fn new(map: CacheMap<K, V, S>) -> Self {
+        Self {
+            #[cfg(not(feature = "arc-swap"))]
+            map: Arc::new(RwLock::new(map)),
+            #[cfg(feature = "arc-swap")]
+            map: Arc::new(arc_swap::ArcSwap::from_pointee(map)),
+        }
+    }
+
+    /// Storage with the same map, so writes to one of them are visible in
+    /// the other
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            map: Arc::clone(&self.map),
+        }
+    }
+
+    /// Replaces all entries in place, so shared storages see new entries too.
+    /// Returns previous entries. Poisoned lock is recovered, as the whole map
+    /// is overwritten anyway.
+    #[cfg(not(feature = "arc-swap"))]
+    pub(crate) fn replace(&self, map: CacheMap<K, V, S>) -> Self {
+        let mut guard = self.map.write().unwrap_or_else(PoisonError::into_inner);
+        Self::new(std::mem::replace(&mut *guard, map))
+    }
+
+    /// Replaces all entries in place, so shared storages see new entries too.
+    /// Returns previous entries.
+    #[cfg(feature = "arc-swap")]
+    pub(crate) fn replace(&self, map: CacheMap<K, V, S>) -> Self {
+        let old = self.map.swap(Arc::new(map));
+        Self {
+            map: Arc::new(arc_swap::ArcSwap::new(old)),
+        }
+    }
+
+    #[cfg(not(feature = "arc-swap"))]
+    pub(crate) fn read(&self) -> Result<ReadGuard<'_, K, V, S>> {
+        self.map.read().map_err(|_| CacheError::LockPoisoned)
+    }
+
+    #[cfg(feature = "arc-swap")]
+    pub(crate) fn read(&self) -> Result<ReadGuard<'_, K, V, S>> {
+        Ok(self.map.load())
+    }
+
+    #[cfg(not(feature = "arc-swap"))]
+    pub(crate) fn modify<R>(&self, f: impl FnOnce(&mut CacheMap<K, V, S>) -> R) -> Result<R> {
+        let mut map = self.map.write().map_err(|_| CacheError::LockPoisoned)?;
+        Ok(f(&mut map))
+    }
+
+    #[cfg(feature = "arc-swap")]
+    pub(crate) fn modify<R>(&self, f: impl FnOnce(&mut CacheMap<K, V, S>) -> R) -> Result<R> {
+        let mut map = CacheMap::clone(&self.map.load());
+        let out = f(&mut map);
+        self.map.store(Arc::new(map));
+        Ok(out)
+    }
+
+    /// Map shared with callers of `GetAll`. With `arc-swap` feature it is a
+    /// snapshot which is not updated by later writes.
+    #[cfg(not(feature = "arc-swap"))]
+    pub(crate) fn shared(&self) -> Arc<RwLock<CacheMap<K, V, S>>> {
+        Arc::clone(&self.map)
+    }
+
+    /// Map shared with callers of `GetAll`. With `arc-swap` feature it is a
+    /// snapshot which is not updated by later writes.
+    #[cfg(feature = "arc-swap")]
+    pub(crate) fn shared(&self) -> Arc<RwLock<CacheMap<K, V, S>>> {
+        Arc::new(RwLock::new(CacheMap::clone(&self.map.load())))
+    }
+
+    /// Takes map out of storage. Map is copied only if it is still shared
+    /// with callers of `GetAll` or snapshots.
+    #[cfg(not(feature = "arc-swap"))]
+    pub(crate) fn into_map(self) -> Result<CacheMap<K, V, S>> {
+        match Arc::try_unwrap(self.map) {
+            Ok(lock) => lock.into_inner().map_err(|_| CacheError::LockPoisoned),
+            Err(shared) => Ok(CacheMap::clone(
+                &*shared.read().map_err(|_| CacheError::LockPoisoned)?,
+            )),
+        }
+    }
+
+    /// Takes map out of storage. Map is copied only if it is still shared
+    /// with callers of `GetAll` or snapshots.
+    #[cfg(feature = "arc-swap")]
+    pub(crate) fn into_map(self) -> Result<CacheMap<K, V, S>> {
+        let map = match Arc::try_unwrap(self.map) {
+            Ok(swap) => swap.into_inner(),
+            Err(shared) => shared.load_full(),
+        };
+        Ok(Arc::try_unwrap(map).unwrap_or_else(|shared| CacheMap::clone(&shared)))
+    }
+
+    /// Copy of map which is not updated by later writes. With `arc-swap`
+    /// feature current map is shared without copying.
+    #[cfg(not(feature = "arc-swap"))]
+    pub(crate) fn snapshot(&self) -> Result<Arc<CacheMap<K, V, S>>> {
+        Ok(Arc::new(CacheMap::clone(&*self.read()?)))
+    }
+
+    /// Copy of map which is not updated by later writes. With `arc-swap`
+    /// feature current map is shared without copying.
+    #[cfg(feature = "arc-swap")]
+    pub(crate) fn snapshot(&self) -> Result<Arc<CacheMap<K, V, S>>> {
+        Ok(self.map.load_full())
+    }
+}
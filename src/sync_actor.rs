@@ -0,0 +1,231 @@
+use std::marker::{PhantomData, Unpin};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+use actix::prelude::*;
+
+use diesel::associations::HasTable;
+use diesel::connection::Connection;
+use diesel::insertable::CanInsertInSingleQuery;
+use diesel::prelude::*;
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use diesel::sql_types::HasSqlType;
+
+use crate::messages::{Count, Exists, Get, GetAll, GetMany, Invalidate, Save};
+use crate::{Cache, CacheError, CacheMap, ConnBackend, Result};
+
+/// Cache actor for running many instances in `SyncArbiter`. All instances
+/// share one cache map, while every instance has its own connection, so
+/// reads and writes don't wait for each other's db queries.
+///
+/// Supports only basic messages. Cache is reloaded on writes and misses,
+/// there are no periodic updates.
+pub struct SyncCacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Send + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Send + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+{
+    /// Connection of this instance
+    conn: Conn,
+    /// Entries shared by all instances
+    cache: Arc<RwLock<CacheMap<C::Id, C>>>,
+    /// Phantom marker for saving table inside structure
+    t: PhantomData<Table>,
+}
+
+impl<Conn, Table, C> SyncCacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Send + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Send + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+{
+    /// Loads all entries from db and starts `n_workers` instances in
+    /// `SyncArbiter`. `conn_factory` is called once for initial load and
+    /// once for every instance.
+    pub fn sync_arbiter_start<F>(n_workers: usize, conn_factory: F) -> Result<Addr<Self>>
+    where
+        F: Fn() -> Conn + Send + Sync + 'static,
+    {
+        let cache = Arc::new(RwLock::new(C::read_all(&conn_factory())?));
+        Ok(SyncArbiter::start(n_workers, move || SyncCacheDbActor {
+            conn: conn_factory(),
+            cache: Arc::clone(&cache),
+            t: PhantomData,
+        }))
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, CacheMap<C::Id, C>>> {
+        self.cache.read().map_err(|_| CacheError::LockPoisoned)
+    }
+
+    /// Reloads shared map in place, so other instances see new entries
+    fn update(&self) -> Result<()> {
+        let cache = C::read_all(&self.conn)?;
+        *self.cache.write().map_err(|_| CacheError::LockPoisoned)? = cache;
+        Ok(())
+    }
+}
+
+impl<Conn, Table, C> Actor for SyncCacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Send + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Send + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+{
+    type Context = SyncContext<Self>;
+}
+
+impl<Conn, Table, C> Handler<Get<Conn, Table, C>> for SyncCacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Send + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Send + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+{
+    type Result = Result<Option<C>>;
+
+    fn handle(
+        &mut self,
+        Get { id, .. }: Get<Conn, Table, C>,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        if let Some(out) = self.read()?.get(&id) {
+            return Ok(Some(out.clone()));
+        }
+        self.update()?;
+        Ok(self.read()?.get(&id).cloned())
+    }
+}
+
+impl<Conn, Table, C> Handler<GetMany<Conn, Table, C>> for SyncCacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Send + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Send + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+{
+    type Result = Result<CacheMap<C::Id, C>>;
+
+    fn handle(
+        &mut self,
+        GetMany { ids }: GetMany<Conn, Table, C>,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        let get_many = |cache: &CacheMap<C::Id, C>| -> CacheMap<C::Id, C> {
+            ids.iter()
+                .filter_map(|id| cache.get(id).map(|it| (id.clone(), it.clone())))
+                .collect()
+        };
+        let out = get_many(&*self.read()?);
+        if ids.iter().all(|id| out.contains_key(id)) {
+            return Ok(out);
+        }
+        self.update()?;
+        Ok(get_many(&*self.read()?))
+    }
+}
+
+impl<Conn, Table, C> Handler<GetAll<Conn, Table, C>> for SyncCacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Send + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Send + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+{
+    type Result = Result<Arc<RwLock<CacheMap<C::Id, C>>>>;
+
+    fn handle(&mut self, _: GetAll<Conn, Table, C>, _: &mut Self::Context) -> Self::Result {
+        Ok(Arc::clone(&self.cache))
+    }
+}
+
+impl<Conn, Table, C> Handler<Exists<Conn, Table, C>> for SyncCacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Send + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Send + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+{
+    type Result = Result<bool>;
+
+    fn handle(
+        &mut self,
+        Exists { id }: Exists<Conn, Table, C>,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        if self.read()?.contains_key(&id) {
+            return Ok(true);
+        }
+        self.update()?;
+        Ok(self.read()?.contains_key(&id))
+    }
+}
+
+impl<Conn, Table, C> Handler<Count> for SyncCacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Send + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Send + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+{
+    type Result = Result<usize>;
+
+    fn handle(&mut self, _: Count, _: &mut Self::Context) -> Self::Result {
+        Ok(self.read()?.len())
+    }
+}
+
+impl<Conn, Table, C> Handler<Invalidate> for SyncCacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Send + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Send + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, _: Invalidate, _: &mut Self::Context) -> Self::Result {
+        self.update()
+    }
+}
+
+impl<Conn, Table, C, W> Handler<Save<W>> for SyncCacheDbActor<Conn, Table, C>
+where
+    Conn: Connection + Send + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Send + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Table::FromClause: QueryFragment<Conn::Backend>,
+    C: Cache<Conn, Table> + Send + Sync,
+    C::Id: Send + Sync,
+    W: Insertable<Table> + Send + 'static,
+    W::Values: CanInsertInSingleQuery<Conn::Backend> + QueryFragment<Conn::Backend>,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, pred: Save<W>, _: &mut Self::Context) -> Self::Result {
+        C::write_one(pred.0, &self.conn)?;
+        self.update()
+    }
+}
@@ -0,0 +1,218 @@
+use std::hash::Hash;
+use std::marker::Unpin;
+use std::sync::{Arc, RwLock};
+
+use actix::prelude::*;
+
+use diesel::associations::HasTable;
+use diesel::connection::Connection;
+use diesel::query_builder::{AsQuery, QueryFragment, QueryId};
+use diesel::sql_types::HasSqlType;
+
+use crate::messages::{Clear, Count, Delete, Exists, Get, GetAll, GetMany, Invalidate, Save};
+use crate::{Cache, CacheError, CacheMap, ConnBackend, Result};
+
+/// In-memory stand-in for [`CacheDbActor`](crate::CacheDbActor) for unit
+/// tests of code using cache, which don't need db. Handles basic messages:
+/// `Save` inserts entry converted from written value, reads serve entries
+/// of map and `Invalidate` does nothing.
+///
+/// Fake has different type than real actor, so code under test should take
+/// `Recipient`s of messages instead of `Addr`. Table and connection types
+/// of messages are those of real actor, as only [`Cache`] impl for them is
+/// used.
+pub struct FakeCacheDbActor<Id, C> {
+    cache: Arc<RwLock<CacheMap<Id, C>>>,
+    get_id: fn(&C) -> Id,
+}
+
+impl<Id, C> FakeCacheDbActor<Id, C>
+where
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Clone + Unpin + 'static,
+{
+    /// Constructor. `get_id` gets id of saved entries, e.g. `|shop| shop.id`.
+    pub fn new(get_id: fn(&C) -> Id) -> Self {
+        Self::with_entries(get_id, Default::default())
+    }
+
+    /// Constructor with initial entries
+    pub fn with_entries(get_id: fn(&C) -> Id, entries: CacheMap<Id, C>) -> Self {
+        Self {
+            cache: Arc::new(RwLock::new(entries)),
+            get_id,
+        }
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&CacheMap<Id, C>) -> R) -> Result<R> {
+        let cache_guard = self.cache.read().map_err(|_| CacheError::LockPoisoned)?;
+        Ok(f(&cache_guard))
+    }
+
+    fn modify<R>(&self, f: impl FnOnce(&mut CacheMap<Id, C>) -> R) -> Result<R> {
+        let mut cache_guard = self.cache.write().map_err(|_| CacheError::LockPoisoned)?;
+        Ok(f(&mut cache_guard))
+    }
+}
+
+impl<Id, C> Actor for FakeCacheDbActor<Id, C>
+where
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Clone + Unpin + 'static,
+{
+    type Context = Context<Self>;
+}
+
+impl<Id, C, W> Handler<Save<W>> for FakeCacheDbActor<Id, C>
+where
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Clone + Unpin + 'static,
+    W: Into<C>,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, Save(w): Save<W>, _: &mut Context<Self>) -> Self::Result {
+        let v = w.into();
+        let id = (self.get_id)(&v);
+        self.modify(|cache| cache.insert(id, v))?;
+        Ok(())
+    }
+}
+
+impl<Conn, Table, Id, C> Handler<Get<Conn, Table, C>> for FakeCacheDbActor<Id, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Cache<Conn, Table, Id = Id> + Unpin,
+{
+    type Result = Result<Option<C>>;
+
+    fn handle(
+        &mut self,
+        Get { id, .. }: Get<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        self.read(|cache| cache.get(&id).cloned())
+    }
+}
+
+impl<Conn, Table, Id, C> Handler<GetMany<Conn, Table, C>> for FakeCacheDbActor<Id, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Cache<Conn, Table, Id = Id> + Unpin,
+{
+    type Result = Result<CacheMap<Id, C>>;
+
+    fn handle(
+        &mut self,
+        GetMany { ids }: GetMany<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        self.read(|cache| {
+            ids.iter()
+                .filter_map(|id| cache.get(id).map(|it| (id.clone(), it.clone())))
+                .collect()
+        })
+    }
+}
+
+impl<Conn, Table, Id, C> Handler<GetAll<Conn, Table, C>> for FakeCacheDbActor<Id, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Cache<Conn, Table, Id = Id> + Unpin,
+{
+    type Result = Result<Arc<RwLock<CacheMap<Id, C>>>>;
+
+    fn handle(&mut self, _: GetAll<Conn, Table, C>, _: &mut Context<Self>) -> Self::Result {
+        Ok(Arc::clone(&self.cache))
+    }
+}
+
+impl<Conn, Table, Id, C> Handler<Exists<Conn, Table, C>> for FakeCacheDbActor<Id, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Cache<Conn, Table, Id = Id> + Unpin,
+{
+    type Result = Result<bool>;
+
+    fn handle(
+        &mut self,
+        Exists { id }: Exists<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        self.read(|cache| cache.contains_key(&id))
+    }
+}
+
+impl<Conn, Table, Id, C> Handler<Delete<Conn, Table, C>> for FakeCacheDbActor<Id, C>
+where
+    Conn: Connection + Unpin + 'static,
+    Conn::Backend: ConnBackend<Table> + HasSqlType<Table::SqlType>,
+    Table: diesel::Table + HasTable<Table = Table> + AsQuery + Unpin + 'static,
+    Table::Query: QueryId + QueryFragment<Conn::Backend>,
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Cache<Conn, Table, Id = Id> + Unpin,
+{
+    type Result = Result<bool>;
+
+    fn handle(
+        &mut self,
+        Delete { id }: Delete<Conn, Table, C>,
+        _: &mut Context<Self>,
+    ) -> Self::Result {
+        self.modify(|cache| cache.remove(&id).is_some())
+    }
+}
+
+impl<Id, C> Handler<Count> for FakeCacheDbActor<Id, C>
+where
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Clone + Unpin + 'static,
+{
+    type Result = Result<usize>;
+
+    fn handle(&mut self, _: Count, _: &mut Context<Self>) -> Self::Result {
+        self.read(|cache| cache.len())
+    }
+}
+
+impl<Id, C> Handler<Invalidate> for FakeCacheDbActor<Id, C>
+where
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Clone + Unpin + 'static,
+{
+    type Result = Result<()>;
+
+    fn handle(&mut self, _: Invalidate, _: &mut Context<Self>) -> Self::Result {
+        Ok(())
+    }
+}
+
+impl<Id, C> Handler<Clear> for FakeCacheDbActor<Id, C>
+where
+    Id: Hash + Eq + Clone + Unpin + 'static,
+    C: Clone + Unpin + 'static,
+{
+    type Result = ();
+
+    fn handle(&mut self, _: Clear, _: &mut Context<Self>) -> Self::Result {
+        if let Err(e) = self.modify(|cache| cache.clear()) {
+            log::error!("fake cache clear failed: {}", e);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use diesel::ConnectionError;
+
+use crate::CacheError;
+
+/// Mode of TLS connection to postgres, same as `sslmode` of libpq
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SslMode {
+    /// Connect without TLS
+    Disable,
+    /// Use TLS if server supports it
+    Prefer,
+    /// Always use TLS, without verifying certificate of server
+    Require,
+    /// Always use TLS and verify that certificate of server is signed by
+    /// trusted authority
+    VerifyCa,
+    /// Same as `VerifyCa`, and also verify that host name matches certificate
+    VerifyFull,
+}
+
+impl SslMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            SslMode::Disable => "disable",
+            SslMode::Prefer => "prefer",
+            SslMode::Require => "require",
+            SslMode::VerifyCa => "verify-ca",
+            SslMode::VerifyFull => "verify-full",
+        }
+    }
+}
+
+/// TLS settings of postgres connection, used by
+/// [`CacheDbActorBuilder::establish_tls`](crate::CacheDbActorBuilder::establish_tls).
+///
+/// `PgConnection` connects with libpq, which does TLS itself with openssl it
+/// is linked with, so settings are passed to it as connection parameters.
+#[derive(Debug, Clone)]
+pub struct TlsOptions {
+    ssl_mode: SslMode,
+    certificate: Option<PathBuf>,
+}
+
+impl TlsOptions {
+    /// Constructor, requires TLS and trusts system root certificates
+    pub fn new() -> Self {
+        Self {
+            ssl_mode: SslMode::Require,
+            certificate: None,
+        }
+    }
+
+    /// Sets mode of TLS connection
+    pub fn ssl_mode(mut self, ssl_mode: SslMode) -> Self {
+        self.ssl_mode = ssl_mode;
+        self
+    }
+
+    /// Sets root certificate which certificate of server is verified with
+    pub fn certificate(mut self, path: &Path) -> Self {
+        self.certificate = Some(path.to_owned());
+        self
+    }
+
+    /// Adds settings to `database_url`, either `postgres://` url or string
+    /// of `key=value` pairs, e.g. to create connection pool with them. Url
+    /// should not have TLS parameters of its own.
+    pub fn connection_url(&self, database_url: &str) -> String {
+        let is_uri =
+            database_url.starts_with("postgres://") || database_url.starts_with("postgresql://");
+        let mut params = vec![("sslmode", self.ssl_mode.as_str().to_owned())];
+        if let Some(path) = &self.certificate {
+            params.push(("sslrootcert", path.to_string_lossy().into_owned()));
+        }
+        let mut url = database_url.to_owned();
+        for (key, value) in params {
+            if is_uri {
+                url.push(if url.contains('?') { '&' } else { '?' });
+                url.push_str(&format!("{}={}", key, value));
+            } else {
+                let value = value.replace('\\', "\\\\").replace('\'', "\\'");
+                url.push_str(&format!(" {}='{}'", key, value));
+            }
+        }
+        url
+    }
+}
+
+impl Default for TlsOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reports failures of TLS handshake separately, as libpq gives only message
+pub(crate) fn connection_error(e: ConnectionError) -> CacheError {
+    match e {
+        ConnectionError::BadConnection(msg)
+            if msg.contains("SSL") || msg.contains("certificate") =>
+        {
+            CacheError::Tls(msg)
+        }
+        e => CacheError::Connection(e),
+    }
+}
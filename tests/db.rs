@@ -7,6 +7,7 @@ pub struct PgDb {
 }
 
 impl PgDb {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let id = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -28,7 +29,7 @@ impl PgDb {
 
         #[rustfmt::skip]
         let output = Command::new("docker")
-            .args(&[
+            .args([
                 "run",
                 "-d",
                 "--rm",
@@ -51,7 +52,7 @@ impl PgDb {
 
     pub fn kill(&self) {
         let output = Command::new("docker")
-            .args(&["kill", self.container_name.as_str()])
+            .args(["kill", self.container_name.as_str()])
             .output()
             .expect("failed to kill postgres");
         println!("{:?}", output);
@@ -62,11 +63,11 @@ impl PgDb {
 impl Drop for PgDb {
     fn drop(&mut self) {
         Command::new("docker")
-            .args(&["stop", &self.container_name])
+            .args(["stop", &self.container_name])
             .output()
             .unwrap();
         Command::new("docker")
-            .args(&["rm", &self.container_name])
+            .args(["rm", &self.container_name])
             .output()
             .unwrap();
     }
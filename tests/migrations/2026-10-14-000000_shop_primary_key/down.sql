@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE shop DROP CONSTRAINT shop_pkey;
\ No newline at end of file
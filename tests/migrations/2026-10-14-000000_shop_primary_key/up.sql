@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE shop ADD PRIMARY KEY (id);
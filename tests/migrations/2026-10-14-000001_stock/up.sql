@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE stock (
+    shop_id INTEGER NOT NULL,
+    item_id INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    PRIMARY KEY (shop_id, item_id)
+);
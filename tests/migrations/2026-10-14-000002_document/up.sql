@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE document (
+    id SERIAL PRIMARY KEY,
+    body TEXT NOT NULL,
+    version BIGINT NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+CREATE TABLE event (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,166 @@
+#![cfg(feature = "sqlite")]
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate diesel;
+
+use std::time::Duration;
+
+use actix::Actor;
+use actix_diesel_cache::{messages::*, CacheDbActor, CacheDbActorBuilder};
+use diesel::{connection::SimpleConnection, prelude::*, SqliteConnection};
+
+table! {
+    shop (id) {
+        id -> Integer,
+        name -> Text,
+        address -> Text,
+    }
+}
+
+#[derive(Queryable, Clone, Debug, Eq, PartialEq)]
+pub struct Shop {
+    id: i32,
+    name: String,
+    address: String,
+}
+
+#[derive(Insertable, Clone, Debug)]
+#[table_name = "shop"]
+pub struct ShopInsert {
+    name: String,
+    address: String,
+}
+
+impl actix_diesel_cache::Cache<SqliteConnection, shop::table> for Shop {
+    type Id = i32;
+    fn get_id(&self) -> Self::Id {
+        self.id
+    }
+
+    fn read_many(
+        ids: &[Self::Id],
+        c: &SqliteConnection,
+    ) -> actix_diesel_cache::Result<actix_diesel_cache::CacheMap<Self::Id, Self>> {
+        let shops: Vec<Shop> = shop::table.filter(shop::id.eq_any(ids.to_vec())).load(c)?;
+        Ok(shops.into_iter().map(|s| (s.id, s)).collect())
+    }
+}
+
+fn init_db() -> SqliteConnection {
+    init_db_at(":memory:")
+}
+
+fn init_db_at(path: &str) -> SqliteConnection {
+    let conn = SqliteConnection::establish(path).unwrap();
+    conn.batch_execute(
+        "create table shop (
+            id integer primary key autoincrement not null,
+            name text not null,
+            address text not null
+        )",
+    )
+    .unwrap();
+    conn
+}
+
+#[actix_rt::test]
+async fn savewithresult_works() {
+    let addr = CacheDbActor::<_, shop::table, Shop>::new(init_db())
+        .unwrap()
+        .start();
+
+    for (i, name) in ["Nike", "Adidas"].iter().enumerate() {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        let shop: Shop = addr.send(SaveWithResult::new(shop)).await.unwrap().unwrap();
+        assert_eq!(shop.id, i as i32 + 1);
+        assert_eq!(shop.name, *name);
+
+        let cached = addr.send(Get::new(shop.id)).await.unwrap().unwrap();
+        assert_eq!(cached, Some(shop));
+    }
+}
+
+#[actix_rt::test]
+async fn save_get_works() {
+    let addr = CacheDbActor::<_, shop::table, Shop>::new(init_db())
+        .unwrap()
+        .start();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let shop = addr.send(Get::new(1)).await.unwrap().unwrap().unwrap();
+    assert_eq!(shop.name, "Nike");
+    assert_eq!(addr.send(Get::new(2)).await.unwrap().unwrap(), None);
+}
+
+#[actix_rt::test]
+async fn get_all_works() {
+    let addr = CacheDbActor::<_, shop::table, Shop>::new(init_db())
+        .unwrap()
+        .start();
+    for name in &["Nike", "Adidas"] {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+
+    let all = addr.send(GetAll::default()).await.unwrap().unwrap();
+    let all = all.read().unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[&2].name, "Adidas");
+}
+
+#[actix_rt::test]
+async fn delete_works() {
+    let addr = CacheDbActor::<_, shop::table, Shop>::new(init_db())
+        .unwrap()
+        .start();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    addr.send(Save(shop)).await.unwrap().unwrap();
+
+    assert!(addr.send(Delete { id: 1 }).await.unwrap().unwrap());
+    assert!(!addr.send(Delete { id: 1 }).await.unwrap().unwrap());
+    assert_eq!(addr.send(Get::new(1)).await.unwrap().unwrap(), None);
+}
+
+#[actix_rt::test]
+async fn timer_refresh_works() {
+    // In-memory db is private to connection, so file is shared instead
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .subsec_nanos();
+    let path = std::env::temp_dir().join(format!("actix_diesel_cache_{}.db", nanos));
+    let path = path.to_str().unwrap().to_owned();
+    let interval = Duration::from_millis(100);
+    let addr = CacheDbActorBuilder::<_, shop::table, Shop>::new(init_db_at(&path))
+        .refresh_interval(interval)
+        .build()
+        .unwrap()
+        .start();
+
+    // Row inserted behind actor's back is loaded by periodic update
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&SqliteConnection::establish(&path).unwrap())
+        .unwrap();
+    actix_rt::time::delay_for(interval * 3).await;
+    assert_eq!(addr.send(Count).await.unwrap().unwrap(), 1);
+
+    std::fs::remove_file(&path).unwrap();
+}
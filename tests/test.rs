@@ -1,8 +1,28 @@
-use std::{collections::HashMap, sync::RwLockReadGuard};
+#![cfg(feature = "postgres")]
+#![allow(non_local_definitions)]
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{BuildHasher, BuildHasherDefault},
+    sync::atomic::{AtomicI32, Ordering},
+    sync::RwLockReadGuard,
+};
 
 use actix::{Actor, Addr};
-use actix_diesel_cache::{messages::*, CacheDbActor};
-use diesel::{table, PgConnection};
+#[cfg(feature = "bincode")]
+use actix_diesel_cache::Serializer;
+use actix_diesel_cache::{
+    messages::*, CacheDbActor, CacheDbActorBuilder, CacheError, CacheHandle, CacheMap, CacheRef,
+    CacheSnapshot, MultiCache, RefreshStrategy, SyncCacheDbActor,
+};
+use diesel::{
+    connection::SimpleConnection,
+    insertable::CanInsertInSingleQuery,
+    pg::{upsert::excluded, Pg},
+    prelude::*,
+    query_builder::{QueryFragment, UndecoratedInsertRecord},
+    table, PgConnection,
+};
 #[macro_use]
 extern crate diesel;
 #[macro_use]
@@ -21,14 +41,84 @@ table! {
     }
 }
 
-#[derive(Queryable, Insertable, Clone, Debug, Eq, PartialEq)]
+#[derive(
+    Queryable,
+    Insertable,
+    Clone,
+    Debug,
+    Eq,
+    PartialEq,
+    serde_crate::Serialize,
+    serde_crate::Deserialize,
+    bincode_crate::Encode,
+    bincode_crate::Decode,
+)]
 #[table_name = "shop"]
+#[serde(crate = "serde_crate")]
+#[bincode(crate = "bincode_crate")]
 pub struct Shop {
     id: i32,
     name: String,
     address: String,
 }
 
+table! {
+    #[sql_name = "shop"]
+    shop_alias (id) {
+        id -> Integer,
+        name -> Text,
+        address -> Text,
+    }
+}
+
+table! {
+    stock (shop_id, item_id) {
+        shop_id -> Integer,
+        item_id -> Integer,
+        amount -> Integer,
+    }
+}
+
+#[derive(Queryable, Insertable, Clone, Debug, Eq, PartialEq)]
+#[table_name = "stock"]
+pub struct Stock {
+    shop_id: i32,
+    item_id: i32,
+    amount: i32,
+}
+
+table! {
+    document (id) {
+        id -> Integer,
+        body -> Text,
+        version -> BigInt,
+    }
+}
+
+#[derive(Queryable, Insertable, Clone, Debug, Eq, PartialEq)]
+#[table_name = "document"]
+pub struct Document {
+    id: i32,
+    body: String,
+    version: i64,
+}
+
+table! {
+    event (id) {
+        id -> Integer,
+        name -> Text,
+        updated_at -> Timestamp,
+    }
+}
+
+#[derive(Queryable, Insertable, Clone, Debug, Eq, PartialEq)]
+#[table_name = "event"]
+pub struct Event {
+    id: i32,
+    name: String,
+    updated_at: std::time::SystemTime,
+}
+
 #[derive(Queryable, Insertable, Clone, Debug)]
 #[table_name = "shop"]
 pub struct ShopInsert {
@@ -45,6 +135,227 @@ impl actix_diesel_cache::Cache<PooledConnection, shop::table> for Shop {
     fn get_id(&self) -> Self::Id {
         self.id
     }
+
+    fn id_ref(&self) -> std::borrow::Cow<'_, i32> {
+        std::borrow::Cow::Borrowed(&self.id)
+    }
+
+    fn read_many(
+        ids: &[Self::Id],
+        c: &PooledConnection,
+    ) -> actix_diesel_cache::Result<CacheMap<Self::Id, Self>> {
+        let shops: Vec<Shop> = shop::table.filter(shop::id.eq_any(ids.to_vec())).load(c)?;
+        Ok(shops.into_iter().map(|s| (s.id, s)).collect())
+    }
+
+    fn upsert<W>(w: W, c: &PooledConnection) -> actix_diesel_cache::Result<usize>
+    where
+        W: Insertable<shop::table>,
+        W::Values:
+            CanInsertInSingleQuery<Pg> + QueryFragment<Pg> + UndecoratedInsertRecord<shop::table>,
+    {
+        Ok(diesel::insert_into(shop::table)
+            .values(w)
+            .on_conflict(shop::id)
+            .do_update()
+            .set((
+                shop::name.eq(excluded(shop::name)),
+                shop::address.eq(excluded(shop::address)),
+            ))
+            .execute(c)?)
+    }
+}
+
+impl actix_diesel_cache::UpsertCache<PooledConnection, shop::table> for Shop {
+    fn upsert_with_result<W>(w: W, c: &PooledConnection) -> actix_diesel_cache::Result<Self>
+    where
+        W: Insertable<shop::table>,
+        W::Values:
+            CanInsertInSingleQuery<Pg> + QueryFragment<Pg> + UndecoratedInsertRecord<shop::table>,
+    {
+        Ok(diesel::insert_into(shop::table)
+            .values(w)
+            .on_conflict(shop::id)
+            .do_update()
+            .set((
+                shop::name.eq(excluded(shop::name)),
+                shop::address.eq(excluded(shop::address)),
+            ))
+            .get_result(c)?)
+    }
+}
+
+impl actix_diesel_cache::SecondaryIndexCache<PooledConnection, shop::table> for Shop {
+    type SecondaryIndex = String;
+
+    fn secondary_key(&self) -> Option<Self::SecondaryIndex> {
+        Some(self.name.clone())
+    }
+}
+
+impl actix_diesel_cache::Cache<PooledConnection, shop_alias::table> for Shop {
+    type Id = i32;
+    fn get_id(&self) -> Self::Id {
+        self.id
+    }
+
+    fn read_many(
+        ids: &[Self::Id],
+        c: &PooledConnection,
+    ) -> actix_diesel_cache::Result<CacheMap<Self::Id, Self>> {
+        let shops: Vec<Shop> = shop_alias::table
+            .filter(shop_alias::id.eq_any(ids.to_vec()))
+            .load(c)?;
+        Ok(shops.into_iter().map(|s| (s.id, s)).collect())
+    }
+}
+
+type ShopFor<Table> = actix_diesel_cache::CacheFor<Table, Shop>;
+
+/// Same entries as `Shop` in `shop_alias`, looked up by name
+impl actix_diesel_cache::Cache<PooledConnection, shop_alias::table> for ShopFor<shop_alias::table> {
+    type Id = String;
+    fn get_id(&self) -> Self::Id {
+        self.name.clone()
+    }
+
+    fn read_many(
+        ids: &[Self::Id],
+        c: &PooledConnection,
+    ) -> actix_diesel_cache::Result<CacheMap<Self::Id, Self>> {
+        let shops: Vec<Shop> = shop_alias::table
+            .filter(shop_alias::name.eq_any(ids.to_vec()))
+            .load(c)?;
+        Ok(shops
+            .into_iter()
+            .map(|s| (s.name.clone(), ShopFor::new(s)))
+            .collect())
+    }
+}
+
+impl actix_diesel_cache::Cache<PgConnection, shop::table> for Shop {
+    type Id = i32;
+    fn get_id(&self) -> Self::Id {
+        self.id
+    }
+
+    fn read_many(
+        ids: &[Self::Id],
+        c: &PgConnection,
+    ) -> actix_diesel_cache::Result<CacheMap<Self::Id, Self>> {
+        let shops: Vec<Shop> = shop::table.filter(shop::id.eq_any(ids.to_vec())).load(c)?;
+        Ok(shops.into_iter().map(|s| (s.id, s)).collect())
+    }
+}
+
+#[cfg(feature = "derive")]
+#[derive(Queryable, actix_diesel_cache::Cache, Clone, Debug, Eq, PartialEq)]
+#[table_name = "shop"]
+#[cache(id = "id", secondary = "name")]
+pub struct DerivedShop {
+    id: i32,
+    name: String,
+    address: String,
+}
+
+impl actix_diesel_cache::Cache<PooledConnection, stock::table> for Stock {
+    type Id = (i32, i32);
+    fn get_id(&self) -> Self::Id {
+        (self.shop_id, self.item_id)
+    }
+
+    /// Stocks with negative amount are soft deleted
+    fn read_all(c: &PooledConnection) -> actix_diesel_cache::Result<CacheMap<Self::Id, Self>> {
+        let stocks: Vec<Stock> = stock::table.filter(stock::amount.ge(0)).load(c)?;
+        Ok(stocks.into_iter().map(|s| (s.get_id(), s)).collect())
+    }
+
+    /// Composite ids can't be compared with `eq_any`, so stocks of shops are
+    /// loaded and filtered by item
+    fn read_many(
+        ids: &[Self::Id],
+        c: &PooledConnection,
+    ) -> actix_diesel_cache::Result<CacheMap<Self::Id, Self>> {
+        let shop_ids: Vec<i32> = ids.iter().map(|(shop_id, _)| *shop_id).collect();
+        let stocks: Vec<Stock> = stock::table
+            .filter(stock::shop_id.eq_any(shop_ids))
+            .filter(stock::amount.ge(0))
+            .load(c)?;
+        Ok(stocks
+            .into_iter()
+            .map(|s| (s.get_id(), s))
+            .filter(|(id, _)| ids.contains(id))
+            .collect())
+    }
+
+    fn on_refresh<S: BuildHasher>(
+        old_state: &CacheMap<Self::Id, Self, S>,
+        new_state: &CacheMap<Self::Id, Self, S>,
+    ) {
+        if new_state.contains_key(&(42, 1)) {
+            let added = new_state.len() as i32 - old_state.len() as i32;
+            STOCK_ADDED_ON_REFRESH.store(added, Ordering::SeqCst);
+        }
+    }
+}
+
+static STOCK_ADDED_ON_REFRESH: AtomicI32 = AtomicI32::new(0);
+
+impl actix_diesel_cache::SoftDeleteCache<PooledConnection, stock::table> for Stock {
+    fn soft_delete(id: &Self::Id, c: &PooledConnection) -> actix_diesel_cache::Result<usize> {
+        Ok(diesel::update(stock::table.find(*id))
+            .set(stock::amount.eq(-1))
+            .execute(c)?)
+    }
+}
+
+impl actix_diesel_cache::Cache<PooledConnection, document::table> for Document {
+    type Id = i32;
+    fn get_id(&self) -> Self::Id {
+        self.id
+    }
+
+    fn read_many(
+        ids: &[Self::Id],
+        c: &PooledConnection,
+    ) -> actix_diesel_cache::Result<CacheMap<Self::Id, Self>> {
+        let documents: Vec<Document> = document::table
+            .filter(document::id.eq_any(ids.to_vec()))
+            .load(c)?;
+        Ok(documents.into_iter().map(|it| (it.id, it)).collect())
+    }
+}
+
+impl actix_diesel_cache::VersionedCache<PooledConnection, document::table> for Document {
+    type VersionColumn = document::version;
+    const VERSION_COLUMN: Self::VersionColumn = document::version;
+
+    fn version(&self) -> u64 {
+        self.version as u64
+    }
+}
+
+impl actix_diesel_cache::Cache<PooledConnection, event::table> for Event {
+    type Id = i32;
+    fn get_id(&self) -> Self::Id {
+        self.id
+    }
+
+    fn read_many(
+        ids: &[Self::Id],
+        c: &PooledConnection,
+    ) -> actix_diesel_cache::Result<CacheMap<Self::Id, Self>> {
+        let events: Vec<Event> = event::table
+            .filter(event::id.eq_any(ids.to_vec()))
+            .load(c)?;
+        Ok(events.into_iter().map(|it| (it.id, it)).collect())
+    }
+}
+
+impl actix_diesel_cache::TimestampedCache<PooledConnection, event::table> for Event {
+    type TimestampColumn = event::updated_at;
+    type Timestamp = std::time::SystemTime;
+    const TIMESTAMP_COLUMN: event::updated_at = event::updated_at;
 }
 
 /// Inits pool of connections to database
@@ -56,12 +367,19 @@ pub fn init_db_pool(db_url: &str) -> Pool {
         .unwrap()
 }
 
+pub type ShopCache = CacheDbActor<PooledConnection, shop::table, Shop>;
+pub type PgShopCache = CacheDbActor<PgConnection, shop::table, Shop>;
+
 pub struct CacheWrap {
-    pub addr: Addr<CacheDbActor<PooledConnection, shop::table, Shop>>,
+    pub addr: Addr<ShopCache>,
+    pub pool: Pool,
     pub db: PgDb,
 }
 
-fn setup() -> CacheWrap {
+fn setup_with<F>(build: F) -> CacheWrap
+where
+    F: FnOnce(PooledConnection) -> ShopCache,
+{
     let db = PgDb::new();
     let pool = init_db_pool(db.url.as_str());
 
@@ -70,8 +388,12 @@ fn setup() -> CacheWrap {
 
     let conn = pool.get().unwrap();
 
-    let addr = actix_diesel_cache::CacheDbActor::new(conn).unwrap().start();
-    CacheWrap { addr, db }
+    let addr = build(conn).start();
+    CacheWrap { addr, pool, db }
+}
+
+fn setup() -> CacheWrap {
+    setup_with(|conn| actix_diesel_cache::CacheDbActor::new(conn).unwrap())
 }
 
 #[actix_rt::test]
@@ -92,7 +414,7 @@ async fn save_works() {
     wrap.addr.send(Save(shop2.clone())).await.unwrap().unwrap();
 
     let shops = wrap.addr.send(GetAll::default()).await.unwrap().unwrap();
-    let shops: RwLockReadGuard<HashMap<_, Shop>> = shops.read().unwrap();
+    let shops: RwLockReadGuard<CacheMap<_, Shop>> = shops.read().unwrap();
 
     assert!(shops.len() == 2);
 
@@ -104,6 +426,34 @@ async fn save_works() {
     assert_eq!(shop.address, shop2.address);
 }
 
+#[actix_rt::test]
+async fn with_hasher_works() {
+    type Hasher = BuildHasherDefault<DefaultHasher>;
+
+    let db = PgDb::new();
+    let pool = init_db_pool(db.url.as_str());
+    embedded_migrations::run(&pool.get().unwrap()).unwrap();
+    let addr = CacheDbActorBuilder::new(pool.get().unwrap())
+        .with_hasher::<Hasher>()
+        .build()
+        .map(|actor: CacheDbActor<_, shop::table, Shop, Hasher>| actor.start())
+        .unwrap();
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let shops = addr.send(GetAll::default()).await.unwrap().unwrap();
+    {
+        let shops: RwLockReadGuard<CacheMap<_, Shop, Hasher>> = shops.read().unwrap();
+        assert_eq!(shops.len(), 1);
+    }
+    let found = addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(found.map(|it| it.name), Some(String::from("Nike")));
+}
+
 #[actix_rt::test]
 async fn savewithresult_works() {
     let wrap = setup();
@@ -123,14 +473,3233 @@ async fn savewithresult_works() {
     assert_eq!(shop.name, shop1.name);
     assert_eq!(shop.address, shop1.address);
 
-    let shop: Shop = wrap
+    let shop: Shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap().unwrap();
+
+    assert_eq!(shop.name, shop1.name);
+    assert_eq!(shop.address, shop1.address);
+}
+
+#[actix_rt::test]
+async fn getallbyfield_works() {
+    let wrap = setup();
+
+    let shop1 = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+
+    let shop2 = ShopInsert {
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+
+    let shop3 = ShopInsert {
+        name: String::from("Puma"),
+        address: String::from("Some street"),
+    };
+
+    wrap.addr.send(Save(shop1.clone())).await.unwrap().unwrap();
+    wrap.addr.send(Save(shop2.clone())).await.unwrap().unwrap();
+    wrap.addr.send(Save(shop3.clone())).await.unwrap().unwrap();
+
+    let mut shops: Vec<Shop> = wrap
         .addr
-        .send(Get { id: 1 })
+        .send(GetAllByField::new(
+            |s: &Shop| &s.address,
+            String::from("Central street"),
+        ))
         .await
         .unwrap()
+        .unwrap();
+    shops.sort_by_key(|s| s.id);
+
+    assert_eq!(shops.len(), 2);
+    assert_eq!(shops[0].name, shop1.name);
+    assert_eq!(shops[1].name, shop2.name);
+}
+
+#[actix_rt::test]
+async fn sortedgetall_works() {
+    let wrap = setup();
+
+    let names = ["Nike", "Adidas", "Puma"];
+    for name in names.iter() {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+
+    let shops: Vec<Shop> = wrap
+        .addr
+        .send(SortedGetAll::new(|s: &Shop| s.name.clone()))
+        .await
         .unwrap()
         .unwrap();
+    let sorted: Vec<_> = shops.iter().map(|s| s.name.as_str()).collect();
 
-    assert_eq!(shop.name, shop1.name);
-    assert_eq!(shop.address, shop1.address);
+    assert_eq!(sorted, vec!["Adidas", "Nike", "Puma"]);
+}
+
+static EVICTED: AtomicI32 = AtomicI32::new(0);
+
+#[actix_rt::test]
+async fn on_evict_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .on_evict(|id, _| EVICTED.store(id, Ordering::SeqCst))
+            .build()
+            .unwrap()
+    });
+
+    let shop1 = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+
+    let shop2 = ShopInsert {
+        name: String::from("Adidas"),
+        address: String::from("Some street"),
+    };
+
+    wrap.addr.send(Save(shop1)).await.unwrap().unwrap();
+
+    let conn = wrap.pool.get().unwrap();
+    diesel::delete(shop::table.filter(shop::id.eq(1)))
+        .execute(&conn)
+        .unwrap();
+
+    // Reloading entries after save should evict deleted shop
+    wrap.addr.send(Save(shop2)).await.unwrap().unwrap();
+
+    assert_eq!(EVICTED.load(Ordering::SeqCst), 1);
+}
+
+#[cfg(feature = "futures")]
+#[actix_rt::test]
+async fn preheat_from_stream_works() {
+    let shops = vec![
+        Shop {
+            id: 1,
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        },
+        Shop {
+            id: 2,
+            name: String::from("Adidas"),
+            address: String::from("Some street"),
+        },
+    ];
+
+    let wrap = setup_with(|conn| {
+        let mut builder = CacheDbActorBuilder::new(conn);
+        let preheated =
+            futures::executor::block_on(builder.preheat_from_stream(futures::stream::iter(shops)))
+                .unwrap();
+        assert_eq!(preheated, 2);
+        builder.build().unwrap()
+    });
+
+    // Preheated entries are reconciled with empty table on start
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(shop, None);
+}
+
+#[cfg(feature = "futures")]
+#[actix_rt::test]
+async fn stream_all_works() {
+    use futures::stream::StreamExt;
+
+    let wrap = setup();
+    for name in &["Nike", "Adidas", "Puma"] {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+
+    // Channel is smaller than cache, so sending waits for receiver
+    let (tx, rx) = futures::channel::mpsc::channel(1);
+    let mut msg = StreamAll::new(tx);
+    msg.batch_size = 2;
+    let sent = wrap.addr.send(msg);
+    let mut names: Vec<String> = rx.map(|it: Shop| it.name).collect().await;
+    names.sort();
+    assert_eq!(names, vec!["Adidas", "Nike", "Puma"]);
+    assert_eq!(sent.await.unwrap().unwrap(), 3);
+}
+
+#[actix_rt::test]
+async fn query_timeout_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .query_timeout(std::time::Duration::from_millis(100))
+            .build()
+            .unwrap()
+    });
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    // Reload waits for lock held by other connection until timeout
+    let conn = wrap.pool.get().unwrap();
+    conn.batch_execute("BEGIN; LOCK TABLE shop IN ACCESS EXCLUSIVE MODE;")
+        .unwrap();
+    let res = wrap.addr.send(Invalidate).await.unwrap();
+    assert!(matches!(res, Err(CacheError::Db(_))));
+    conn.batch_execute("ROLLBACK").unwrap();
+
+    let rows = wrap.addr.send(GetAllVec::default()).await.unwrap().unwrap();
+    let names: Vec<_> = rows.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Nike"]);
+}
+
+#[cfg(all(feature = "futures", feature = "tokio"))]
+#[actix_rt::test]
+async fn spawn_refresh_works() {
+    let wrap = setup();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&wrap.pool.get().unwrap())
+        .unwrap();
+
+    let done = wrap.addr.send(SpawnRefresh).await.unwrap().unwrap();
+    done.await.unwrap().unwrap();
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn getstats_works() {
+    let wrap = setup();
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let _ = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    let _ = wrap.addr.send(Get::new(2)).await.unwrap().unwrap();
+
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 1);
+    assert_eq!(stats.entry_count, 1);
+    assert!(stats.refreshes >= 2);
+    assert!(stats.last_refresh.is_some());
+    assert!((stats.hit_rate() - 0.5).abs() < f64::EPSILON);
+    assert!(stats.hit_rate_threshold(0.5));
+    assert!(!stats.hit_rate_threshold(0.9));
+}
+
+#[actix_rt::test]
+async fn savewithid_works() {
+    let wrap = setup();
+
+    let shop1 = Shop {
+        id: 5,
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+
+    let shop: Shop = wrap
+        .addr
+        .send(SaveWithId {
+            id: shop1.id,
+            value: shop1.clone(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(shop, shop1);
+
+    let shop = wrap.addr.send(Get::new(5)).await.unwrap().unwrap();
+    assert_eq!(shop, Some(shop1));
+
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.hits, 1);
+}
+
+#[actix_rt::test]
+async fn zip_with_works() {
+    let wrap = setup();
+
+    let shop1 = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop1.clone())).await.unwrap().unwrap();
+
+    let other: Addr<ShopCache> = CacheDbActor::new(wrap.pool.get().unwrap()).unwrap().start();
+
+    let (a, b) = actix_diesel_cache::client::zip_with(&wrap.addr, &other, 1)
+        .await
+        .unwrap();
+    let (a, b) = (a.unwrap().unwrap(), b.unwrap().unwrap());
+
+    assert_eq!(a, b);
+    assert_eq!(a.name, shop1.name);
+}
+
+#[actix_rt::test]
+async fn delete_works() {
+    let wrap = setup();
+
+    let shop1 = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop1)).await.unwrap().unwrap();
+
+    let deleted = wrap.addr.send(Delete { id: 1 }).await.unwrap().unwrap();
+    assert!(deleted);
+    let deleted = wrap.addr.send(Delete { id: 1 }).await.unwrap().unwrap();
+    assert!(!deleted);
+
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(shop, None);
+}
+
+#[actix_rt::test]
+async fn refresh_interval_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActor::with_refresh_interval(conn, std::time::Duration::from_millis(100)).unwrap()
+    });
+
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&conn)
+        .unwrap();
+
+    actix_rt::time::delay_for(std::time::Duration::from_millis(500)).await;
+
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert!(shop.is_some());
+    assert_eq!(wrap.addr.send(GetStats).await.unwrap().hits, stats.hits + 1);
+}
+
+#[actix_rt::test]
+async fn getmany_works() {
+    let wrap = setup();
+
+    let conn = wrap.pool.get().unwrap();
+    for name in ["Nike", "Adidas", "Puma"].iter() {
+        diesel::insert_into(shop::table)
+            .values(ShopInsert {
+                name: String::from(*name),
+                address: String::from("Central street"),
+            })
+            .execute(&conn)
+            .unwrap();
+    }
+
+    let shops = wrap
+        .addr
+        .send(GetMany { ids: vec![1, 3, 4] })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(shops.len(), 2);
+    assert_eq!(shops[&1].name, "Nike");
+    assert_eq!(shops[&3].name, "Puma");
+}
+
+#[actix_rt::test]
+async fn savemany_works() {
+    let wrap = setup();
+
+    let shops = vec![
+        ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Some street"),
+        },
+    ];
+
+    let rows = wrap.addr.send(SaveMany(shops)).await.unwrap().unwrap();
+    assert_eq!(rows, 2);
+
+    let shops = wrap.addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert_eq!(shops.read().unwrap().len(), 2);
+
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 2);
+
+    let mut shops: Vec<Shop> = wrap.addr.send(GetAllVec::default()).await.unwrap().unwrap();
+    shops.sort_by_key(|s| s.id);
+    assert_eq!(shops[0].name, "Nike");
+    assert_eq!(shops[1].name, "Adidas");
+}
+
+#[actix_rt::test]
+async fn invalidate_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActor::with_refresh_interval(conn, std::time::Duration::from_secs(0)).unwrap()
+    });
+
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&conn)
+        .unwrap();
+
+    let shops = wrap.addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert!(shops.read().unwrap().is_empty());
+
+    wrap.addr.send(Invalidate).await.unwrap().unwrap();
+
+    let shops = wrap.addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert_eq!(shops.read().unwrap().len(), 1);
+}
+
+#[actix_rt::test]
+async fn exists_works() {
+    let wrap = setup();
+
+    let shop1 = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop1)).await.unwrap().unwrap();
+
+    let exists = wrap.addr.send(Exists { id: 1 }).await.unwrap().unwrap();
+    assert!(exists);
+    let exists = wrap.addr.send(Exists { id: 2 }).await.unwrap().unwrap();
+    assert!(!exists);
+}
+
+#[actix_rt::test]
+async fn skip_initial_load_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .refresh_interval(std::time::Duration::from_secs(0))
+            .skip_initial_load(true)
+            .cache_capacity(16)
+            .build()
+            .unwrap()
+    });
+
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&conn)
+        .unwrap();
+
+    // Entries are loaded on first read
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[cfg(feature = "derive")]
+#[actix_rt::test]
+async fn derive_works() {
+    let wrap = setup();
+    let shop = Shop {
+        id: 1,
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let addr = CacheDbActor::<_, shop::table, DerivedShop>::new(wrap.pool.get().unwrap())
+        .unwrap()
+        .start();
+    let shop = addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(
+        shop,
+        Some(DerivedShop {
+            id: 1,
+            name: String::from("Adidas"),
+            address: String::from("Central street"),
+        })
+    );
+    let key = String::from("Adidas");
+    let by_name = addr.send(GetBySecondary { key }).await.unwrap().unwrap();
+    assert_eq!(Some(by_name), shop.map(|shop| vec![shop]));
+}
+
+#[actix_rt::test]
+async fn cache_handle_works() {
+    fn assert_shareable<T: Clone + Send + Sync>(_: &T) {}
+
+    let wrap = setup();
+    let handle = CacheHandle::from(wrap.addr.clone());
+    assert_shareable(&handle);
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    handle.clone().save(shop).await.unwrap();
+    let shop = handle.get(1).await.unwrap().unwrap();
+    assert_eq!(shop.name, "Nike");
+    let all = handle.get_all().await.unwrap();
+    assert_eq!(all.read().unwrap().len(), 1);
+}
+
+#[actix_rt::test]
+async fn cache_ref_works() {
+    fn assert_shareable<T: Clone + Send + Sync>(_: &T) {}
+
+    let wrap = setup();
+    let cache: CacheRef<i32, Shop, ShopInsert> = CacheRef::new(wrap.addr.clone());
+    assert_shareable(&cache);
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    cache.clone().save(shop).await.unwrap();
+    let shop = cache.get(1).await.unwrap().unwrap();
+    assert_eq!(shop.name, "Nike");
+    let all = cache.get_all().await.unwrap();
+    assert_eq!(all.read().unwrap().len(), 1);
+}
+
+#[cfg(feature = "actix-web")]
+#[actix_rt::test]
+async fn cache_ref_extractor_works() {
+    use actix_web::{test::TestRequest, FromRequest};
+    type ShopCache = CacheRef<i32, Shop, ShopInsert>;
+
+    let wrap = setup();
+    let cache = ShopCache::new(wrap.addr.clone());
+    let req = TestRequest::default().app_data(cache).to_http_request();
+    let cache = ShopCache::extract(&req).await.unwrap();
+    assert!(cache.get(1).await.unwrap().is_none());
+
+    // Not registered handle fails request
+    let req = TestRequest::default().to_http_request();
+    assert!(ShopCache::extract(&req).await.is_err());
+
+    // Handle registered in app is taken by handler
+    let app = cache.register(actix_web::App::new()).route(
+        "/",
+        actix_web::web::get().to(|cache: ShopCache| async move {
+            let found = cache.get(1).await.unwrap().is_some();
+            Ok::<_, actix_web::Error>(actix_web::HttpResponse::Ok().body(found.to_string()))
+        }),
+    );
+    let mut app = actix_web::test::init_service(app).await;
+    let resp =
+        actix_web::test::call_service(&mut app, TestRequest::get().uri("/").to_request()).await;
+    assert!(resp.status().is_success());
+}
+
+#[cfg(feature = "actix-web")]
+#[actix_rt::test]
+async fn cache_error_response_works() {
+    use actix_web::http::StatusCode;
+
+    let status = |e: CacheError| actix_web::Error::from(e).as_response_error().status_code();
+    assert_eq!(
+        status(CacheError::Db(diesel::result::Error::NotFound)),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        status(CacheError::NotFound(String::from("shop"))),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        status(CacheError::VersionConflict {
+            expected: 1,
+            actual: 2
+        }),
+        StatusCode::CONFLICT
+    );
+    assert_eq!(status(CacheError::Busy), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        status(CacheError::LockPoisoned),
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+}
+
+#[actix_rt::test]
+async fn upsert_with_result_works() {
+    let wrap = setup();
+    let mut shop = Shop {
+        id: 1,
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    let row = wrap
+        .addr
+        .send(UpsertWithResult::new(shop.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(row, shop);
+    shop.name = String::from("Nike");
+    let row = wrap
+        .addr
+        .send(UpsertWithResult::new(shop.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(row, shop);
+
+    let got = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(got, Some(shop));
+}
+
+#[actix_rt::test]
+async fn upsert_with_result_updates_existing_row() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let shop = Shop {
+        id: 1,
+        name: String::from("Nike"),
+        address: String::from("Some street"),
+    };
+    let row = wrap
+        .addr
+        .send(UpsertWithResult::new(shop.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(row, shop);
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+    let got = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(got, Some(shop));
+}
+
+#[actix_rt::test]
+async fn upsert_works() {
+    let wrap = setup();
+    let mut shop = Shop {
+        id: 1,
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Upsert(shop.clone())).await.unwrap().unwrap();
+    shop.name = String::from("Nike");
+    wrap.addr.send(Upsert(shop.clone())).await.unwrap().unwrap();
+
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+    let got = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(got, Some(shop));
+}
+
+#[actix_rt::test]
+async fn evict_older_than_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActor::with_refresh_interval(conn, std::time::Duration::from_secs(0)).unwrap()
+    });
+    let shop = Shop {
+        id: 1,
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop.clone())).await.unwrap().unwrap();
+
+    let max_age = std::time::Duration::from_millis(100);
+    let shops = wrap
+        .addr
+        .send(GetByAge::new(max_age))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(shops, vec![shop]);
+
+    actix_rt::time::delay_for(max_age * 2).await;
+    let shops = wrap
+        .addr
+        .send(GetByAge::new(max_age))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(shops.is_empty());
+
+    let evicted = wrap
+        .addr
+        .send(EvictOlderThan { max_age })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(evicted, 1);
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 0);
+}
+
+#[actix_rt::test]
+async fn getbysecondary_works() {
+    let wrap = setup();
+    let mut shop = Shop {
+        id: 1,
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Upsert(shop.clone())).await.unwrap().unwrap();
+
+    let key = String::from("Adidas");
+    let got = wrap
+        .addr
+        .send(GetBySecondary { key })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(got, vec![shop.clone()]);
+
+    shop.name = String::from("Nike");
+    wrap.addr.send(Upsert(shop.clone())).await.unwrap().unwrap();
+    let key = String::from("Adidas");
+    let got = wrap
+        .addr
+        .send(GetBySecondary { key })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(got.is_empty());
+    let key = String::from("Nike");
+    let got = wrap
+        .addr
+        .send(GetBySecondary { key })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(got, vec![shop.clone()]);
+
+    // Entries with the same key are all indexed, delete of one keeps others
+    let other = Shop {
+        id: 2,
+        name: String::from("Nike"),
+        address: String::from("Main street"),
+    };
+    wrap.addr
+        .send(Upsert(other.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    let key = String::from("Nike");
+    let mut got = wrap
+        .addr
+        .send(GetBySecondary { key })
+        .await
+        .unwrap()
+        .unwrap();
+    got.sort_by_key(|shop| shop.id);
+    assert_eq!(got, vec![shop, other.clone()]);
+
+    wrap.addr.send(Delete { id: 1 }).await.unwrap().unwrap();
+    let key = String::from("Nike");
+    let got = wrap
+        .addr
+        .send(GetBySecondary { key })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(got, vec![other]);
+}
+
+#[actix_rt::test]
+async fn findfirst_works() {
+    let wrap = setup();
+    let shop = Shop {
+        id: 1,
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop.clone())).await.unwrap().unwrap();
+
+    let prefix = String::from("Central");
+    let found = wrap
+        .addr
+        .send(FindFirst::new(move |s: &Shop| {
+            s.address.starts_with(&prefix)
+        }))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, Some(shop));
+
+    let found = wrap
+        .addr
+        .send(FindFirst::new(|s: &Shop| s.name == "Nike"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, None);
+}
+
+#[actix_rt::test]
+async fn filterall_works() {
+    let wrap = setup();
+    let shops = vec![
+        ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Puma"),
+            address: String::from("Main street"),
+        },
+    ];
+    wrap.addr.send(SaveMany(shops)).await.unwrap().unwrap();
+
+    let mut found = wrap
+        .addr
+        .send(FilterAll::new(|s: &Shop| s.address == "Central street"))
+        .await
+        .unwrap()
+        .unwrap();
+    found.sort_by_key(|s| s.id);
+    let names: Vec<_> = found.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Adidas", "Nike"]);
+}
+
+#[actix_rt::test]
+async fn update_works() {
+    let wrap = setup();
+    let shops = vec![
+        ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Some street"),
+        },
+    ];
+    wrap.addr.send(SaveMany(shops)).await.unwrap().unwrap();
+
+    let update = Update(shop::address.eq("Main street"));
+    let rows = wrap.addr.send(update).await.unwrap().unwrap();
+    assert_eq!(rows, 2);
+    let all = wrap.addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert!(all
+        .read()
+        .unwrap()
+        .values()
+        .all(|s| s.address == "Main street"));
+}
+
+#[actix_rt::test]
+async fn transact_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let rows = wrap
+        .addr
+        .send(Transact::new(|c: &PooledConnection| {
+            let rows = diesel::update(shop::table)
+                .set(shop::name.eq("Puma"))
+                .execute(c)?;
+            Ok(rows)
+        }))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(rows, 1);
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap().unwrap();
+    assert_eq!(shop.name, "Puma");
+
+    // Failed closure rolls back its writes
+    let res = wrap
+        .addr
+        .send(Transact::new(|c: &PooledConnection| {
+            diesel::delete(shop::table).execute(c)?;
+            Err::<(), _>(CacheError::NotFound(String::from("shop")))
+        }))
+        .await
+        .unwrap();
+    assert!(res.is_err());
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn swap_entry_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let new_value = Shop {
+        id: 1,
+        name: String::from("Puma"),
+        address: String::from("Central street"),
+    };
+    let old = wrap.addr.send(SwapEntry { id: 1, new_value }).await;
+    assert_eq!(
+        old.unwrap().unwrap().map(|s| s.name),
+        Some(String::from("Nike"))
+    );
+    let new_value = Shop {
+        id: 2,
+        name: String::from("Adidas"),
+        address: String::from("Main street"),
+    };
+    let old = wrap.addr.send(SwapEntry { id: 2, new_value }).await;
+    assert_eq!(old.unwrap().unwrap(), None);
+
+    // Db is not changed, so next read reloads original entries
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn update_cache_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let updated = wrap
+        .addr
+        .send(UpdateCache::new(1, |s: &mut Shop| {
+            s.name = String::from("Puma")
+        }))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.map(|s| s.name), Some(String::from("Puma")));
+    let cached = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(cached.map(|s| s.name), Some(String::from("Puma")));
+    let missing = wrap.addr.send(UpdateCache::new(2, |_: &mut Shop| ())).await;
+    assert!(missing.unwrap().unwrap().is_none());
+
+    // Change is not written to db, so reload reverts it
+    wrap.addr.send(Invalidate).await.unwrap().unwrap();
+    let cached = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(cached.map(|s| s.name), Some(String::from("Nike")));
+}
+
+#[actix_rt::test]
+async fn any_all_satisfy_works() {
+    let wrap = setup();
+    let shops = vec![
+        ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        },
+    ];
+    wrap.addr.send(SaveMany(shops)).await.unwrap().unwrap();
+
+    let any = wrap
+        .addr
+        .send(AnySatisfy::new(|s: &Shop| s.name == "Nike"))
+        .await;
+    assert!(any.unwrap().unwrap());
+    let any = wrap
+        .addr
+        .send(AnySatisfy::new(|s: &Shop| s.name == "Puma"))
+        .await;
+    assert!(!any.unwrap().unwrap());
+    let all = wrap
+        .addr
+        .send(AllSatisfy::new(|s: &Shop| s.address == "Central street"))
+        .await;
+    assert!(all.unwrap().unwrap());
+    let all = wrap
+        .addr
+        .send(AllSatisfy::new(|s: &Shop| s.name == "Nike"))
+        .await;
+    assert!(!all.unwrap().unwrap());
+}
+
+#[actix_rt::test]
+async fn for_each_works() {
+    let wrap = setup();
+    let shops = vec![
+        ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Main street"),
+        },
+    ];
+    wrap.addr.send(SaveMany(shops)).await.unwrap().unwrap();
+
+    let total = std::sync::Arc::new(AtomicI32::new(0));
+    let sum = std::sync::Arc::clone(&total);
+    wrap.addr
+        .send(ForEach::new(move |s: &Shop| {
+            sum.fetch_add(s.id, Ordering::SeqCst);
+        }))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(total.load(Ordering::SeqCst), 3);
+}
+
+#[actix_rt::test]
+async fn reduce_works() {
+    let wrap = setup();
+    let shops = vec![
+        ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Main street"),
+        },
+    ];
+    wrap.addr.send(SaveMany(shops)).await.unwrap().unwrap();
+
+    let sum = wrap
+        .addr
+        .send(Reduce::new(0, |acc, s: &Shop| acc + s.id))
+        .await;
+    assert_eq!(sum.unwrap().unwrap(), 3);
+    let longest = Reduce::new(0, |acc, s: &Shop| acc.max(s.name.len()));
+    assert_eq!(wrap.addr.send(longest).await.unwrap().unwrap(), 6);
+}
+
+#[actix_rt::test]
+async fn composite_key_works() {
+    let wrap = setup();
+    let addr = CacheDbActor::<_, stock::table, Stock>::new(wrap.pool.get().unwrap())
+        .unwrap()
+        .start();
+
+    let stock1 = Stock {
+        shop_id: 1,
+        item_id: 1,
+        amount: 10,
+    };
+    let stock2 = Stock {
+        shop_id: 1,
+        item_id: 2,
+        amount: 20,
+    };
+    addr.send(Save(stock1.clone())).await.unwrap().unwrap();
+    addr.send(Save(stock2.clone())).await.unwrap().unwrap();
+
+    let got = addr.send(Get::new((1, 2))).await.unwrap().unwrap();
+    assert_eq!(got, Some(stock2.clone()));
+
+    let all = addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert_eq!(all.read().unwrap().len(), 2);
+
+    let deleted = addr.send(Delete { id: (1, 1) }).await.unwrap().unwrap();
+    assert!(deleted);
+    let got = addr.send(Get::new((1, 1))).await.unwrap().unwrap();
+    assert_eq!(got, None);
+    let got = addr.send(Get::new((1, 2))).await.unwrap().unwrap();
+    assert_eq!(got, Some(stock2));
+}
+
+#[actix_rt::test]
+async fn updatefield_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let changeset = shop::address.eq("Main street");
+    wrap.addr
+        .send(UpdateField { id: 1, changeset })
+        .await
+        .unwrap()
+        .unwrap();
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap().unwrap();
+    assert_eq!(shop.address, "Main street");
+
+    let changeset = shop::address.eq("Main street");
+    let res = wrap
+        .addr
+        .send(UpdateField { id: 2, changeset })
+        .await
+        .unwrap();
+    assert!(matches!(res, Err(CacheError::NotFound(_))));
+}
+
+#[actix_rt::test]
+async fn clear_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let before = wrap.addr.send(GetAll::default()).await.unwrap().unwrap();
+    wrap.addr.send(Clear).await.unwrap();
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.entry_count, 0);
+
+    // Reloaded lazily on next read
+    let after = wrap.addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert_eq!(after.read().unwrap().len(), 1);
+    assert_eq!(before.read().unwrap().len(), 1);
+}
+
+#[actix_rt::test]
+async fn reset_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+
+    // Row inserted behind actor's back is loaded, counters start over
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Some street"),
+        })
+        .execute(&wrap.pool.get().unwrap())
+        .unwrap();
+    wrap.addr.send(Reset).await.unwrap().unwrap();
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.entry_count, 2);
+    assert_eq!(stats.hits, 0);
+    assert_eq!(stats.refreshes, 1);
+}
+
+#[cfg(feature = "r2d2")]
+#[actix_rt::test]
+async fn from_pool_works() {
+    let wrap = setup();
+    let addr = ShopCache::from_pool(wrap.pool.clone()).unwrap().start();
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    addr.send(Save(shop)).await.unwrap().unwrap();
+    let shop = addr.send(Get::new(1)).await.unwrap().unwrap().unwrap();
+    assert_eq!(shop.name, "Nike");
+
+    // Write through pooled actor is visible to actor with own connection
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert!(shop.is_some());
+}
+
+#[actix_rt::test]
+async fn write_behind_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .refresh_interval(std::time::Duration::from_secs(0))
+            .build()
+            .unwrap()
+    });
+    let shop = Shop {
+        id: 100,
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr
+        .send(SaveBehind(shop.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let got = wrap.addr.send(Get::new(100)).await.unwrap().unwrap();
+    assert_eq!(got, Some(shop.clone()));
+    let conn = wrap.pool.get().unwrap();
+    let rows: Vec<Shop> = shop::table.load(&conn).unwrap();
+    assert!(rows.is_empty());
+
+    let flushed = wrap.addr.send(Flush).await.unwrap().unwrap();
+    assert_eq!(flushed, 1);
+    let rows: Vec<Shop> = shop::table.load(&conn).unwrap();
+    assert_eq!(rows, vec![shop]);
+
+    // Other saves are written at once
+    let shop = ShopInsert {
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    let rows: Vec<Shop> = shop::table.load(&conn).unwrap();
+    assert_eq!(rows.len(), 2);
+}
+
+#[actix_rt::test]
+async fn getallsorted_works() {
+    let wrap = setup();
+    let shops = vec![
+        ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Puma"),
+            address: String::from("Central street"),
+        },
+    ];
+    wrap.addr.send(SaveMany(shops)).await.unwrap().unwrap();
+
+    let sorted = wrap
+        .addr
+        .send(GetAllSorted::new(|s: &Shop| s.name.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    let names: Vec<_> = sorted.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Adidas", "Nike", "Puma"]);
+
+    let sorted = wrap
+        .addr
+        .send(GetAllSorted::new(|s: &Shop| s.name.clone()).descending())
+        .await
+        .unwrap()
+        .unwrap();
+    let names: Vec<_> = sorted.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Puma", "Nike", "Adidas"]);
+}
+
+#[actix_rt::test]
+async fn refresh_strategy_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .refresh_strategy(RefreshStrategy::OnDemand)
+            .build()
+            .unwrap()
+    });
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    // Saved entry is seen only after explicit invalidation
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 0);
+    wrap.addr.send(Invalidate).await.unwrap().unwrap();
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn set_refresh_interval_works() {
+    let wrap = setup();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&wrap.pool.get().unwrap())
+        .unwrap();
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 0);
+
+    // Row inserted behind actor's back is loaded by rescheduled update
+    let interval = std::time::Duration::from_millis(100);
+    wrap.addr
+        .send(SetRefreshInterval(interval))
+        .await
+        .unwrap()
+        .unwrap();
+    actix_rt::time::delay_for(interval * 3).await;
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn loaded_count_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    // Save reloads cache
+    assert_eq!(wrap.addr.send(LoadedCount).await.unwrap(), 1);
+    let before = wrap.addr.send(LastRefreshInstant).await.unwrap().unwrap();
+
+    diesel::delete(shop::table)
+        .execute(&wrap.pool.get().unwrap())
+        .unwrap();
+    wrap.addr.send(Invalidate).await.unwrap().unwrap();
+    assert_eq!(wrap.addr.send(LoadedCount).await.unwrap(), 0);
+    let after = wrap.addr.send(LastRefreshInstant).await.unwrap().unwrap();
+    assert!(after >= before);
+}
+
+#[actix_rt::test]
+async fn peek_many_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    // Row inserted behind actor's back is not loaded on miss
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Some street"),
+        })
+        .execute(&wrap.pool.get().unwrap())
+        .unwrap();
+    let peeked = wrap.addr.send(PeekMany(vec![1, 2])).await.unwrap().unwrap();
+    assert_eq!(peeked[&1].as_ref().map(|s| s.name.as_str()), Some("Nike"));
+    assert_eq!(peeked[&2], None);
+}
+
+#[actix_rt::test]
+async fn read_all_with_capacity_works() {
+    use actix_diesel_cache::Cache;
+
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let conn = wrap.pool.get().unwrap();
+    let all = <Shop as Cache<PooledConnection, shop::table>>::read_all_with_capacity(&conn, 100);
+    let all = all.unwrap();
+    assert_eq!(all.len(), 1);
+    assert!(all.capacity() >= 100);
+}
+
+#[actix_rt::test]
+async fn inspect_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let inspect = Inspect::new(
+        |actor: &CacheDbActor<PooledConnection, shop::table, Shop>| {
+            (actor.is_valid(), actor.entries_count_at_last_refresh())
+        },
+    );
+    assert_eq!(wrap.addr.send(inspect).await.unwrap(), (true, 1));
+    wrap.addr.send(Clear).await.unwrap();
+    let inspect =
+        Inspect::new(|actor: &CacheDbActor<PooledConnection, shop::table, Shop>| actor.is_valid());
+    assert!(!wrap.addr.send(inspect).await.unwrap());
+}
+
+#[actix_rt::test]
+async fn get_or_default_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let default = Shop {
+        id: 0,
+        name: String::from("Unknown"),
+        address: String::new(),
+    };
+    let found = wrap.addr.send(GetOrDefault {
+        id: 1,
+        default: default.clone(),
+    });
+    assert_eq!(found.await.unwrap().unwrap().name, "Nike");
+    let missing = wrap.addr.send(GetOrDefault { id: 2, default });
+    assert_eq!(missing.await.unwrap().unwrap().name, "Unknown");
+}
+
+#[actix_rt::test]
+async fn peek_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    // Row inserted behind actor's back is not loaded on miss
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Some street"),
+        })
+        .execute(&wrap.pool.get().unwrap())
+        .unwrap();
+    let peeked = wrap.addr.send(Peek { id: 1 }).await.unwrap().unwrap();
+    assert_eq!(peeked.map(|s| s.name), Some(String::from("Nike")));
+    assert_eq!(wrap.addr.send(Peek { id: 2 }).await.unwrap().unwrap(), None);
+}
+
+#[actix_rt::test]
+async fn get_range_works() {
+    let wrap = setup();
+    for name in &["Nike", "Adidas", "Puma", "Reebok"] {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+
+    let shops = wrap
+        .addr
+        .send(GetRange { start: 2, end: 3 })
+        .await
+        .unwrap()
+        .unwrap();
+    let names: Vec<_> = shops.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Adidas", "Puma"]);
+
+    // Range follows later writes
+    wrap.addr.send(Delete { id: 2 }).await.unwrap().unwrap();
+    let shops = wrap
+        .addr
+        .send(GetRange { start: 1, end: 10 })
+        .await
+        .unwrap()
+        .unwrap();
+    let names: Vec<_> = shops.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Nike", "Puma", "Reebok"]);
+
+    let shops = wrap
+        .addr
+        .send(GetRange { start: 3, end: 2 })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(shops.is_empty());
+}
+
+#[actix_rt::test]
+async fn group_by_works() {
+    let wrap = setup();
+    let shops = vec![
+        ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Some street"),
+        },
+        ShopInsert {
+            name: String::from("Puma"),
+            address: String::from("Central street"),
+        },
+    ];
+    wrap.addr.send(SaveMany(shops)).await.unwrap().unwrap();
+
+    let groups = wrap
+        .addr
+        .send(GroupBy::new(|s: &Shop| s.address.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(groups.len(), 2);
+    let mut names: Vec<_> = groups["Central street"]
+        .iter()
+        .map(|s| s.name.as_str())
+        .collect();
+    names.sort_unstable();
+    assert_eq!(names, vec!["Nike", "Puma"]);
+    assert_eq!(groups["Some street"].len(), 1);
+}
+
+type ShopEvent = CacheEvent<PooledConnection, shop::table, Shop>;
+
+#[derive(Default)]
+struct EventLog(Vec<String>);
+
+impl Actor for EventLog {
+    type Context = actix::Context<Self>;
+}
+
+impl actix::Handler<ShopEvent> for EventLog {
+    type Result = ();
+
+    fn handle(&mut self, event: ShopEvent, _: &mut Self::Context) {
+        self.0.push(match event {
+            CacheEvent::Inserted(shop) => format!("inserted {}", shop.name),
+            CacheEvent::Updated(shop) => format!("updated {}", shop.name),
+            CacheEvent::Deleted(id) => format!("deleted {}", id),
+        });
+    }
+}
+
+#[derive(actix::Message)]
+#[rtype(result = "Vec<String>")]
+struct TakeEvents;
+
+impl actix::Handler<TakeEvents> for EventLog {
+    type Result = actix::MessageResult<TakeEvents>;
+
+    fn handle(&mut self, _: TakeEvents, _: &mut Self::Context) -> Self::Result {
+        actix::MessageResult(std::mem::take(&mut self.0))
+    }
+}
+
+#[actix_rt::test]
+async fn subscribe_works() {
+    let wrap = setup();
+    let log = EventLog::default().start();
+    wrap.addr
+        .send(Subscribe(log.clone().recipient()))
+        .await
+        .unwrap();
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    let changeset = shop::name.eq("Adidas");
+    wrap.addr
+        .send(UpdateField { id: 1, changeset })
+        .await
+        .unwrap()
+        .unwrap();
+    wrap.addr.send(Delete { id: 1 }).await.unwrap().unwrap();
+    let events = log.send(TakeEvents).await.unwrap();
+    assert_eq!(events, vec!["inserted Nike", "updated Adidas", "deleted 1"]);
+
+    wrap.addr
+        .send(Unsubscribe(log.clone().recipient()))
+        .await
+        .unwrap();
+    let shop = ShopInsert {
+        name: String::from("Puma"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    let events = log.send(TakeEvents).await.unwrap();
+    assert!(events.is_empty());
+}
+
+#[actix_rt::test]
+async fn deletemany_works() {
+    let wrap = setup();
+    let shops = vec![
+        ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Puma"),
+            address: String::from("Central street"),
+        },
+    ];
+    wrap.addr.send(SaveMany(shops)).await.unwrap().unwrap();
+
+    let deleted = wrap
+        .addr
+        .send(DeleteMany {
+            ids: vec![1, 2, 42],
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(deleted, 2);
+    let rows = wrap.addr.send(GetAllVec::default()).await.unwrap().unwrap();
+    let names: Vec<_> = rows.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Puma"]);
+    let rows = shop::table.load::<Shop>(&wrap.pool.get().unwrap()).unwrap();
+    assert_eq!(rows.len(), 1);
+}
+
+#[actix_rt::test]
+async fn snapshot_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let snapshot = wrap.addr.send(Snapshot::default()).await.unwrap().unwrap();
+    assert_eq!(snapshot.len(), 1);
+    assert_eq!(snapshot[&1].name, "Nike");
+
+    // Later writes are not visible in taken snapshot
+    wrap.addr.send(Delete { id: 1 }).await.unwrap().unwrap();
+    assert_eq!(snapshot.clone().len(), 1);
+    let later = wrap.addr.send(Snapshot::default()).await.unwrap().unwrap();
+    assert!(later.is_empty());
+    assert!(later.captured_at >= snapshot.captured_at);
+
+    assert_eq!(snapshot.get(&1).map(|it| it.id), Some(1));
+    assert!(snapshot.get(&2).is_none());
+    let entries: Vec<Shop> = snapshot.clone().into_iter().collect();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "Nike");
+
+    let rebuilt: CacheSnapshot<PooledConnection, shop::table, Shop> =
+        snapshot.clone().into_iter().collect();
+    assert_eq!(rebuilt[&1].name, "Nike");
+    let shops: Vec<Shop> = snapshot.into();
+    assert_eq!(shops.len(), 1);
+}
+
+#[actix_rt::test]
+async fn borrow_cache_works() {
+    let wrap = setup();
+    for name in &["Nike", "Adidas"] {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+
+    let guard = wrap
+        .addr
+        .send(BorrowCache::default())
+        .await
+        .unwrap()
+        .unwrap();
+    {
+        let cache = guard.read().unwrap();
+        let names: Vec<_> = [1, 2, 3]
+            .iter()
+            .filter_map(|id| cache.get(id))
+            .map(|it| it.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Nike", "Adidas"]);
+    }
+    // Lock is released, so writes go on
+    wrap.addr.send(Delete { id: 1 }).await.unwrap().unwrap();
+}
+
+#[actix_rt::test]
+async fn from_preloaded_works() {
+    let shop = Shop {
+        id: 7,
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    let data: CacheMap<_, _> = vec![(7, shop.clone())].into_iter().collect();
+    let wrap = setup_with(move |conn| ShopCache::from_preloaded(conn, data));
+
+    // Served from preloaded data, although db is empty
+    let found = wrap.addr.send(Get::new(7)).await.unwrap().unwrap();
+    assert_eq!(found, Some(shop));
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.refreshes, 0);
+
+    wrap.addr.send(Invalidate).await.unwrap().unwrap();
+    let found = wrap.addr.send(Get::new(7)).await.unwrap().unwrap();
+    assert_eq!(found, None);
+}
+
+#[actix_rt::test]
+async fn validate_cache_works() {
+    let wrap = setup();
+
+    for name in ["Nike", "Adidas"].iter() {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+    let drift = wrap
+        .addr
+        .send(ValidateCache::default())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(drift.is_empty());
+
+    // Change db behind the back of cache
+    let conn = wrap.pool.get().unwrap();
+    diesel::update(shop::table.find(1))
+        .set(shop::address.eq("Main street"))
+        .execute(&conn)
+        .unwrap();
+    diesel::delete(shop::table.find(2)).execute(&conn).unwrap();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Puma"),
+            address: String::from("Central street"),
+        })
+        .execute(&conn)
+        .unwrap();
+
+    let mut drift = wrap
+        .addr
+        .send(ValidateCache::default())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(drift.len(), 3);
+    drift.sort_by_key(|it| match it {
+        CacheDrift::ValueMismatch { .. } => 0,
+        CacheDrift::MissingInDb(_) => 1,
+        CacheDrift::MissingInCache(_) => 2,
+    });
+    assert_eq!(
+        drift[0],
+        CacheDrift::ValueMismatch {
+            cached: Shop {
+                id: 1,
+                name: String::from("Nike"),
+                address: String::from("Central street"),
+            },
+            db_value: Shop {
+                id: 1,
+                name: String::from("Nike"),
+                address: String::from("Main street"),
+            },
+        }
+    );
+    assert_eq!(drift[1], CacheDrift::MissingInDb(2));
+    assert_eq!(
+        drift[2],
+        CacheDrift::MissingInCache(Shop {
+            id: 3,
+            name: String::from("Puma"),
+            address: String::from("Central street"),
+        })
+    );
+
+    // Validation leaves cache untouched
+    let shop = wrap.addr.send(Get::new(2)).await.unwrap().unwrap();
+    assert!(shop.is_some());
+}
+
+#[cfg(feature = "serde")]
+#[actix_rt::test]
+async fn dump_load_cache_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    let dump = wrap.addr.send(DumpCache).await.unwrap().unwrap();
+
+    // Restored cache is served without db, where entry is already deleted
+    let conn = wrap.pool.get().unwrap();
+    diesel::delete(shop::table.find(1)).execute(&conn).unwrap();
+    let addr: Addr<ShopCache> = CacheDbActorBuilder::new(wrap.pool.get().unwrap())
+        .refresh_interval(std::time::Duration::from_secs(0))
+        .skip_initial_load(true)
+        .build()
+        .unwrap()
+        .start();
+    addr.send(LoadCache(dump)).await.unwrap().unwrap();
+    let shop = addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(
+        shop,
+        Some(Shop {
+            id: 1,
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+    );
+
+    let err = addr.send(LoadCache(b"garbage".to_vec())).await.unwrap();
+    assert!(matches!(err, Err(CacheError::Serde(_))));
+}
+
+#[actix_rt::test]
+async fn on_refresh_works() {
+    let wrap = setup();
+    let addr = CacheDbActor::<_, stock::table, Stock>::new(wrap.pool.get().unwrap())
+        .unwrap()
+        .start();
+
+    let stock = Stock {
+        shop_id: 42,
+        item_id: 1,
+        amount: 10,
+    };
+    addr.send(Save(stock)).await.unwrap().unwrap();
+    assert_eq!(STOCK_ADDED_ON_REFRESH.load(Ordering::SeqCst), 1);
+}
+
+#[actix_rt::test]
+async fn getpage_works() {
+    let wrap = setup();
+
+    for name in ["Nike", "Adidas", "Puma"].iter() {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+
+    let page = wrap.addr.send(GetPage::new(1, 5)).await.unwrap().unwrap();
+    let ids: Vec<_> = page.iter().map(|s| s.id).collect();
+    assert_eq!(ids, vec![2, 3]);
+
+    let page = wrap.addr.send(GetPage::new(0, 1)).await.unwrap().unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].name, "Nike");
+
+    let page = wrap.addr.send(GetPage::new(3, 5)).await.unwrap().unwrap();
+    assert!(page.is_empty());
+}
+
+#[cfg(feature = "metrics")]
+#[actix_rt::test]
+async fn metrics_works() {
+    let registry = prometheus::Registry::new();
+    let wrap = {
+        let registry = registry.clone();
+        setup_with(move |conn| {
+            CacheDbActorBuilder::new(conn)
+                .refresh_interval(std::time::Duration::from_secs(0))
+                .metrics_registry(registry)
+                .build()
+                .unwrap()
+        })
+    };
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+
+    let families = registry.gather();
+    let value = |name: &str| {
+        let family = families.iter().find(|f| f.get_name() == name).unwrap();
+        let metric = &family.get_metric()[0];
+        assert!(metric
+            .get_label()
+            .iter()
+            .any(|l| l.get_name() == "table" && l.get_value() == "shop"));
+        metric
+    };
+    assert_eq!(value("cache_writes_total").get_counter().get_value(), 1.0);
+    assert_eq!(value("cache_entries").get_gauge().get_value(), 1.0);
+    assert_eq!(value("cache_reads_total").get_counter().get_value(), 1.0);
+    assert_eq!(
+        value("cache_refresh_duration_seconds")
+            .get_histogram()
+            .get_sample_count(),
+        1
+    );
+}
+
+#[actix_rt::test]
+async fn updatemany_works() {
+    let wrap = setup();
+    for name in ["Nike", "Adidas"].iter() {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+
+    let updates = vec![
+        (1, shop::address.eq("Main street")),
+        (2, shop::address.eq("Side street")),
+        (3, shop::address.eq("No street")),
+    ];
+    let rows = wrap.addr.send(UpdateMany(updates)).await.unwrap().unwrap();
+    assert_eq!(rows, 2);
+    let shop = wrap.addr.send(Get::new(2)).await.unwrap().unwrap().unwrap();
+    assert_eq!(shop.address, "Side street");
+
+    // Second update violates primary key, so first one is rolled back
+    let updates = vec![(1, shop::id.eq(5)), (2, shop::id.eq(5))];
+    let res = wrap.addr.send(UpdateMany(updates)).await.unwrap();
+    assert!(matches!(res, Err(CacheError::Db(_))));
+    let exists = wrap.addr.send(Exists { id: 1 }).await.unwrap().unwrap();
+    assert!(exists);
+}
+
+#[actix_rt::test]
+async fn getorinsert_works() {
+    let wrap = setup();
+
+    let value = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    let inserted = wrap
+        .addr
+        .send(GetOrInsert { id: 1, value })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(inserted.id, 1);
+    assert_eq!(inserted.name, "Nike");
+
+    // Existing entry is returned, value is not inserted
+    let value = ShopInsert {
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    let found = wrap
+        .addr
+        .send(GetOrInsert { id: 1, value })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, inserted);
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn stale_while_revalidate_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .refresh_interval(std::time::Duration::from_secs(0))
+            .skip_initial_load(true)
+            .stale_while_revalidate(true)
+            .build()
+            .unwrap()
+    });
+
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&conn)
+        .unwrap();
+
+    // Stale cache is returned at once, reload happens afterwards
+    let shops = wrap.addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert!(shops.read().unwrap().is_empty());
+
+    actix_rt::time::delay_for(std::time::Duration::from_millis(100)).await;
+
+    let shops = wrap.addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert_eq!(shops.read().unwrap().len(), 1);
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.refreshes, 1);
+}
+
+#[cfg(all(feature = "r2d2", feature = "futures", feature = "tokio"))]
+#[actix_rt::test]
+async fn revalidate_in_background_works() {
+    let wrap = setup();
+    let addr: Addr<ShopCache> = CacheDbActorBuilder::from_pool(wrap.pool.clone())
+        .refresh_interval(std::time::Duration::from_secs(0))
+        .skip_initial_load(true)
+        .stale_while_revalidate(true)
+        .revalidate_in_background(true)
+        .build()
+        .unwrap()
+        .start();
+
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&wrap.pool.get().unwrap())
+        .unwrap();
+
+    // Stale cache is returned at once, entries are loaded in thread pool
+    let shops = addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert!(shops.read().unwrap().is_empty());
+
+    actix_rt::time::delay_for(std::time::Duration::from_millis(100)).await;
+
+    let shops = addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert_eq!(shops.read().unwrap().len(), 1);
+    let stats = addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.refreshes, 1);
+}
+
+#[actix_rt::test]
+async fn new_supervised_works() {
+    let wrap = setup();
+    let addr = PgShopCache::new_supervised(&wrap.db.url).unwrap();
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    addr.send(Save(shop)).await.unwrap().unwrap();
+    let shop = addr.send(Get::new(1)).await.unwrap().unwrap().unwrap();
+    assert_eq!(shop.name, "Nike");
+
+    let res = PgShopCache::new_supervised("postgres://nowhere");
+    assert!(matches!(res, Err(CacheError::Connection(_))));
+}
+
+#[actix_rt::test]
+async fn sync_arbiter_works() {
+    let wrap = setup();
+    let pool = wrap.pool.clone();
+    let addr = SyncCacheDbActor::<_, shop::table, Shop>::sync_arbiter_start(2, move || {
+        pool.get().unwrap()
+    })
+    .unwrap();
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    addr.send(Save(shop)).await.unwrap().unwrap();
+
+    // Entry saved by one worker is visible to all of them
+    for _ in 0..4 {
+        let shop = addr.send(Get::new(1)).await.unwrap().unwrap().unwrap();
+        assert_eq!(shop.name, "Nike");
+    }
+    let count = addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn multi_cache_works() {
+    let wrap = setup();
+    let addr = MultiCache::new(wrap.pool.get().unwrap())
+        .register::<shop::table, Shop>()
+        .unwrap()
+        .register::<document::table, Document>()
+        .unwrap()
+        .start();
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    addr.send(TypedSave::<_, shop::table, _, Shop>::new(shop))
+        .await
+        .unwrap()
+        .unwrap();
+    let document = Document {
+        id: 1,
+        body: String::from("Terms"),
+        version: 0,
+    };
+    addr.send(TypedSave::<_, document::table, _, Document>::new(document))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let shop = addr
+        .send(TypedGet::<_, shop::table, Shop> { id: 1 })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(shop.unwrap().name, "Nike");
+    let document = addr
+        .send(TypedGet::<_, document::table, Document> { id: 1 })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(document.unwrap().body, "Terms");
+
+    // Types which were not registered are not found
+    let res = addr
+        .send(TypedGet::<_, stock::table, Stock> { id: (1, 1) })
+        .await
+        .unwrap();
+    assert!(matches!(res, Err(CacheError::NotFound(_))));
+}
+
+#[actix_rt::test]
+async fn getidset_works() {
+    let wrap = setup();
+    for name in ["Nike", "Adidas"].iter() {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+
+    let ids = wrap.addr.send(GetIdSet::default()).await.unwrap().unwrap();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&1));
+    assert!(ids.contains(&2));
+}
+
+#[test]
+fn stopping_flushes_write_behind() {
+    let db = PgDb::new();
+    let pool = init_db_pool(db.url.as_str());
+    embedded_migrations::run(&pool.get().unwrap()).unwrap();
+
+    let system = actix::System::new("stopping_flushes_write_behind");
+    let conn = pool.get().unwrap();
+    actix::Arbiter::spawn(async move {
+        let addr: Addr<ShopCache> = CacheDbActorBuilder::new(conn).build().unwrap().start();
+        let shop = Shop {
+            id: 1,
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        };
+        addr.send(SaveBehind(shop)).await.unwrap().unwrap();
+        actix::System::current().stop();
+    });
+    system.run().unwrap();
+
+    let shops: Vec<Shop> = shop::table.load(&pool.get().unwrap()).unwrap();
+    assert_eq!(shops.len(), 1);
+}
+
+#[actix_rt::test]
+async fn drain_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let drained = wrap.addr.send(Drain::default()).await.unwrap().unwrap();
+    assert_eq!(drained.len(), 1);
+    assert_eq!(drained[&1].name, "Nike");
+
+    // Next read reloads entries from db
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.entry_count, 0);
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn aliased_table_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let addr = CacheDbActor::<_, shop_alias::table, Shop>::new(wrap.pool.get().unwrap())
+        .unwrap()
+        .start();
+    let shop = addr.send(Get::new(1)).await.unwrap().unwrap().unwrap();
+    assert_eq!(shop.name, "Nike");
+}
+
+#[actix_rt::test]
+async fn merge_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let entries: CacheMap<_, _> = vec![
+        (
+            1,
+            Shop {
+                id: 1,
+                name: String::from("Nike"),
+                address: String::from("Main street"),
+            },
+        ),
+        (
+            2,
+            Shop {
+                id: 2,
+                name: String::from("Adidas"),
+                address: String::from("Central street"),
+            },
+        ),
+    ]
+    .into_iter()
+    .collect();
+    let merged = wrap.addr.send(Merge(entries)).await.unwrap().unwrap();
+    assert_eq!(merged, 2);
+
+    // Merged entries are served while staleness is acceptable
+    let max_age = std::time::Duration::from_secs(60);
+    let shop = wrap
+        .addr
+        .send(Get::with_max_age(1, max_age))
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(shop.address, "Main street");
+    let shop = wrap
+        .addr
+        .send(Get::with_max_age(2, max_age))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(shop.is_some());
+
+    // Full read reconciles cache with db
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn conditional_save_works() {
+    let wrap = setup();
+    let unique_name = |name: &'static str| {
+        let shop = ShopInsert {
+            name: String::from(name),
+            address: String::from("Central street"),
+        };
+        ConditionalSave::new(shop, move |shops: &CacheMap<i32, Shop>| {
+            shops.values().all(|s| s.name != name)
+        })
+    };
+
+    let saved = wrap.addr.send(unique_name("Nike")).await.unwrap().unwrap();
+    assert!(saved);
+    let saved = wrap.addr.send(unique_name("Nike")).await.unwrap().unwrap();
+    assert!(!saved);
+    let saved = wrap
+        .addr
+        .send(unique_name("Adidas"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(saved);
+
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 2);
+}
+
+#[cfg(feature = "r2d2")]
+#[actix_rt::test]
+async fn from_connection_pool_works() {
+    let wrap = setup();
+    let addr = PgShopCache::from_connection_pool(wrap.pool.clone())
+        .unwrap()
+        .start();
+
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    addr.send(Save(shop)).await.unwrap().unwrap();
+    let shop = addr.send(Get::new(1)).await.unwrap().unwrap().unwrap();
+    assert_eq!(shop.name, "Nike");
+
+    // Only connection of `wrap.addr` stays checked out
+    let state = wrap.pool.state();
+    assert_eq!(state.connections - state.idle_connections, 1);
+}
+
+#[actix_rt::test]
+async fn get_with_timestamp_works() {
+    let wrap = setup();
+    for name in ["Nike", "Adidas"].iter() {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+
+    // Full reload puts all entries at the same instant
+    let (_, first) = wrap
+        .addr
+        .send(GetWithTimestamp { id: 1 })
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let (_, second) = wrap
+        .addr
+        .send(GetWithTimestamp { id: 2 })
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(first, second);
+
+    let changeset = shop::address.eq("Main street");
+    wrap.addr
+        .send(UpdateField { id: 1, changeset })
+        .await
+        .unwrap()
+        .unwrap();
+    let (shop, updated) = wrap
+        .addr
+        .send(GetWithTimestamp { id: 1 })
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(shop.address, "Main street");
+    assert!(updated > first);
+
+    let missing = wrap
+        .addr
+        .send(GetWithTimestamp { id: 3 })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(missing.is_none());
+}
+
+#[actix_rt::test]
+async fn soft_delete_works() {
+    let wrap = setup();
+    let addr = CacheDbActor::<_, stock::table, Stock>::new(wrap.pool.get().unwrap())
+        .unwrap()
+        .start();
+    let stock = Stock {
+        shop_id: 1,
+        item_id: 1,
+        amount: 10,
+    };
+    addr.send(Save(stock)).await.unwrap().unwrap();
+
+    let deleted = addr.send(SoftDelete { id: (1, 1) }).await.unwrap().unwrap();
+    assert!(deleted);
+    // Reload doesn't bring entry back, while row is kept in db
+    let got = addr.send(Get::new((1, 1))).await.unwrap().unwrap();
+    assert_eq!(got, None);
+    let conn = wrap.pool.get().unwrap();
+    let rows: Vec<Stock> = stock::table.load(&conn).unwrap();
+    assert_eq!(rows.len(), 1);
+}
+
+#[actix_rt::test]
+async fn cache_capacity_keeps_read_all_filter() {
+    let wrap = setup();
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(stock::table)
+        .values(Stock {
+            shop_id: 1,
+            item_id: 1,
+            amount: -1,
+        })
+        .execute(&conn)
+        .unwrap();
+
+    let actor: CacheDbActor<_, stock::table, Stock> = CacheDbActorBuilder::new(conn)
+        .with_initial_capacity(16)
+        .build()
+        .unwrap();
+    let addr = actor.start();
+    let got = addr.send(Get::new((1, 1))).await.unwrap().unwrap();
+    assert_eq!(got, None);
+}
+
+#[actix_rt::test]
+async fn lazy_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .refresh_interval(std::time::Duration::from_secs(0))
+            .lazy(true)
+            .build()
+            .unwrap()
+    });
+
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&conn)
+        .unwrap();
+
+    // Shop inserted after build is loaded on first `Get`
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert!(shop.is_some());
+}
+
+#[actix_rt::test]
+async fn get_all_filtered_works() {
+    let wrap = setup();
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(shop::table)
+        .values(vec![
+            ShopInsert {
+                name: String::from("Nike"),
+                address: String::from("Central street"),
+            },
+            ShopInsert {
+                name: String::from("Puma"),
+                address: String::from("Main street"),
+            },
+        ])
+        .execute(&conn)
+        .unwrap();
+
+    let found = wrap
+        .addr
+        .send(GetAllFiltered::new(shop::name.eq("Puma")))
+        .await
+        .unwrap()
+        .unwrap();
+    let names: Vec<_> = found.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Puma"]);
+}
+
+#[actix_rt::test]
+async fn get_top_n_works() {
+    let wrap = setup();
+    let shops = vec![
+        ShopInsert {
+            name: String::from("Adidas"),
+            address: String::from("Main street"),
+        },
+        ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        },
+        ShopInsert {
+            name: String::from("Puma"),
+            address: String::from("Main street"),
+        },
+    ];
+    wrap.addr.send(SaveMany(shops)).await.unwrap().unwrap();
+
+    // Shops on the same street are ordered by id
+    let top = wrap
+        .addr
+        .send(GetTopN::new(2, |s: &Shop| s.address.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    let names: Vec<_> = top.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Puma", "Adidas"]);
+
+    let bottom = wrap
+        .addr
+        .send(GetBottomN::new(2, |s: &Shop| s.address.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    let names: Vec<_> = bottom.into_iter().map(|s| s.name).collect();
+    assert_eq!(names, vec!["Nike", "Adidas"]);
+}
+
+#[actix_rt::test]
+async fn reload_works() {
+    let wrap = setup();
+    let shop = Shop {
+        id: 1,
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let conn = wrap.pool.get().unwrap();
+    diesel::update(shop::table.find(1))
+        .set(shop::name.eq("Nike"))
+        .execute(&conn)
+        .unwrap();
+    let shop = wrap.addr.send(Reload { id: 1 }).await.unwrap().unwrap();
+    assert_eq!(shop.map(|s| s.name), Some(String::from("Nike")));
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(shop.map(|s| s.name), Some(String::from("Nike")));
+
+    diesel::delete(shop::table.find(1)).execute(&conn).unwrap();
+    let shop = wrap.addr.send(Reload { id: 1 }).await.unwrap().unwrap();
+    assert_eq!(shop, None);
+}
+
+type ShopOp = CacheOp<PooledConnection, shop::table, Shop>;
+type TxResult = actix_diesel_cache::Result<Vec<ShopOp>>;
+
+#[actix_rt::test]
+async fn transaction_works() {
+    let wrap = setup();
+    let adidas = Shop {
+        id: 1,
+        name: String::from("Adidas"),
+        address: String::from("Central street"),
+    };
+    wrap.addr
+        .send(Transaction(move |c: &PooledConnection| -> TxResult {
+            diesel::insert_into(shop::table)
+                .values(&adidas)
+                .execute(c)?;
+            Ok(vec![ShopOp::Insert(adidas)])
+        }))
+        .await
+        .unwrap()
+        .unwrap();
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert!(shop.is_some());
+
+    // Failed transaction is rolled back and cache is left as is
+    let err = wrap
+        .addr
+        .send(Transaction(|c: &PooledConnection| -> TxResult {
+            diesel::delete(shop::table.find(1)).execute(c)?;
+            Err(CacheError::NotFound(String::from("shop 2")))
+        }))
+        .await
+        .unwrap();
+    assert!(matches!(err, Err(CacheError::NotFound(_))));
+    let conn = wrap.pool.get().unwrap();
+    let rows: Vec<Shop> = shop::table.load(&conn).unwrap();
+    assert_eq!(rows.len(), 1);
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert!(shop.is_some());
+}
+
+#[actix_rt::test]
+async fn max_entries_works() {
+    let wrap = setup();
+    let conn = wrap.pool.get().unwrap();
+    for name in ["Nike", "Adidas", "Puma"].iter() {
+        diesel::insert_into(shop::table)
+            .values(ShopInsert {
+                name: String::from(*name),
+                address: String::from("Central street"),
+            })
+            .execute(&conn)
+            .unwrap();
+    }
+    let addr: Addr<ShopCache> = CacheDbActorBuilder::new(wrap.pool.get().unwrap())
+        .refresh_interval(std::time::Duration::from_secs(0))
+        .max_entries(2)
+        .build()
+        .unwrap()
+        .start();
+    // Initial load is limited too
+    let count = addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 2);
+
+    for id in 1..=3 {
+        let shop = addr.send(Get::new(id)).await.unwrap().unwrap();
+        assert!(shop.is_some());
+    }
+    // Shop 1 is least recently used
+    let ids = addr.send(GetIdSet::default()).await.unwrap().unwrap();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&2));
+    assert!(ids.contains(&3));
+
+    // Repeated use of the same entry doesn't make others more recent
+    for _ in 0..50 {
+        addr.send(Get::new(3)).await.unwrap().unwrap();
+    }
+    addr.send(Get::new(1)).await.unwrap().unwrap();
+    let ids = addr.send(GetIdSet::default()).await.unwrap().unwrap();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&1));
+    assert!(ids.contains(&3));
+}
+
+#[actix_rt::test]
+async fn health_check_works() {
+    let wrap = setup();
+    wrap.addr.send(HealthCheck).await.unwrap().unwrap();
+}
+
+#[cfg(feature = "tokio")]
+#[actix_rt::test]
+async fn new_async_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let addr = ShopCache::new_async(wrap.pool.get().unwrap())
+        .await
+        .unwrap()
+        .start();
+    let count = addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[actix_rt::test]
+async fn cache_for_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    let addr: Addr<CacheDbActor<_, _, ShopFor<shop_alias::table>>> =
+        CacheDbActor::new(wrap.pool.get().unwrap()).unwrap().start();
+    let shop = addr
+        .send(Get::new(String::from("Nike")))
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert_eq!(shop.id, 1);
+}
+
+#[actix_rt::test]
+async fn versioned_save_works() {
+    let wrap = setup();
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(document::table)
+        .values(Document {
+            id: 1,
+            body: String::from("draft"),
+            version: 0,
+        })
+        .execute(&conn)
+        .unwrap();
+    let addr = CacheDbActor::<_, document::table, Document>::new(wrap.pool.get().unwrap())
+        .unwrap()
+        .start();
+
+    let save = |body: &str, expected_version| VersionedSave {
+        id: 1,
+        expected_version,
+        changeset: document::body.eq(body.to_owned()),
+    };
+    let doc = addr.send(save("final", 0)).await.unwrap().unwrap();
+    assert_eq!(doc.version, 1);
+
+    // Writer which has seen old version is rejected
+    let err = addr.send(save("stale", 0)).await.unwrap();
+    assert!(matches!(
+        err,
+        Err(CacheError::VersionConflict {
+            expected: 0,
+            actual: 1
+        })
+    ));
+    let doc = addr.send(Get::new(1)).await.unwrap().unwrap().unwrap();
+    assert_eq!(doc.body, "final");
+
+    // Write done bypassing cache is detected by version in db
+    diesel::update(document::table.find(1))
+        .set(document::version.eq(2))
+        .execute(&conn)
+        .unwrap();
+    let err = addr.send(save("stale", 1)).await.unwrap();
+    assert!(matches!(
+        err,
+        Err(CacheError::VersionConflict {
+            expected: 1,
+            actual: 2
+        })
+    ));
+    let doc = addr.send(save("final", 2)).await.unwrap().unwrap();
+    assert_eq!(doc.version, 3);
+}
+
+#[cfg(feature = "r2d2")]
+#[actix_rt::test]
+async fn busy_pool_works() {
+    let wrap = setup();
+    let pool = Pool::builder()
+        .connection_timeout(std::time::Duration::from_millis(100))
+        .max_size(1)
+        .build(ConnectionManager::new(&wrap.db.url))
+        .unwrap();
+    let addr: Addr<ShopCache> = CacheDbActorBuilder::from_pool(pool.clone())
+        .refresh_interval(std::time::Duration::from_secs(0))
+        .pool_checkout_retries(2)
+        .build()
+        .unwrap()
+        .start();
+
+    let held = pool.get().unwrap();
+    let err = addr.send(HealthCheck).await.unwrap();
+    assert!(matches!(err, Err(CacheError::Busy)));
+    drop(held);
+    addr.send(HealthCheck).await.unwrap().unwrap();
+}
+
+#[actix_rt::test]
+async fn bulk_load_works() {
+    let wrap = setup();
+    let shops = vec![
+        Shop {
+            id: 1,
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        },
+        Shop {
+            id: 2,
+            name: String::from("Adidas"),
+            address: String::from("Main street"),
+        },
+    ];
+    let loaded = wrap.addr.send(BulkLoad(shops)).await.unwrap().unwrap();
+    assert_eq!(loaded, 2);
+
+    // Entries are served from cache, db is empty
+    let shop = wrap.addr.send(Get::new(2)).await.unwrap().unwrap().unwrap();
+    assert_eq!(shop.name, "Adidas");
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 2);
+}
+
+#[actix_rt::test]
+async fn checkpoint_works() {
+    let wrap = setup();
+    let shop = Shop {
+        id: 1,
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(BulkLoad(vec![shop])).await.unwrap().unwrap();
+    let token = wrap
+        .addr
+        .send(Checkpoint::default())
+        .await
+        .unwrap()
+        .unwrap();
+    wrap.addr.send(BulkLoad(vec![])).await.unwrap().unwrap();
+
+    wrap.addr.send(Restore(token)).await.unwrap();
+    let max_age = std::time::Duration::from_secs(60);
+    let shop = wrap
+        .addr
+        .send(Get::with_max_age(1, max_age))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(shop.is_some());
+    // Restored cache is reconciled with empty db on next read
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 0);
+}
+
+#[actix_rt::test]
+async fn read_many_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActor::with_refresh_interval(conn, std::time::Duration::from_secs(0)).unwrap()
+    });
+    let conn = wrap.pool.get().unwrap();
+    for name in ["Nike", "Adidas", "Puma"].iter() {
+        diesel::insert_into(shop::table)
+            .values(ShopInsert {
+                name: String::from(*name),
+                address: String::from("Central street"),
+            })
+            .execute(&conn)
+            .unwrap();
+    }
+
+    // Only missing entries are loaded, there is no full reload
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert!(shop.is_some());
+    let shops = wrap
+        .addr
+        .send(GetMany { ids: vec![1, 2] })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(shops.len(), 2);
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 2);
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.refreshes, 1);
+}
+
+#[actix_rt::test]
+async fn debug_dump_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    wrap.addr.send(DebugDump).await.unwrap().unwrap();
+}
+
+#[actix_rt::test]
+async fn graceful_clear_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActor::with_refresh_interval(conn, std::time::Duration::from_secs(0)).unwrap()
+    });
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&conn)
+        .unwrap();
+
+    // Reload is done once response is received
+    wrap.addr.send(GracefulClear).await.unwrap().unwrap();
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.refreshes, 2);
+    assert_eq!(stats.entry_count, 1);
+}
+
+#[actix_rt::test]
+async fn shrink_works() {
+    let wrap = setup();
+    for name in ["Nike", "Adidas", "Puma"].iter() {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+    wrap.addr.send(Delete { id: 1 }).await.unwrap().unwrap();
+    wrap.addr.send(Delete { id: 2 }).await.unwrap().unwrap();
+
+    wrap.addr.send(Shrink).await.unwrap().unwrap();
+    let shop = wrap.addr.send(Get::new(3)).await.unwrap().unwrap();
+    assert_eq!(shop.unwrap().name, "Puma");
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 1);
+}
+
+#[cfg(feature = "bincode")]
+#[actix_rt::test]
+async fn bincode_dump_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .with_serializer(Serializer::Bincode)
+            .build()
+            .unwrap()
+    });
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    let dump = wrap.addr.send(DumpCache).await.unwrap().unwrap();
+
+    wrap.addr.send(Clear).await.unwrap();
+    wrap.addr.send(LoadCache(dump)).await.unwrap().unwrap();
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(shop.unwrap().name, "Nike");
+
+    let err = wrap.addr.send(LoadCache(vec![0xff])).await.unwrap();
+    assert!(matches!(err, Err(CacheError::BincodeDecode(_))));
+}
+
+#[actix_rt::test]
+async fn get_changed_since_works() {
+    let wrap = setup();
+    let conn = wrap.pool.get().unwrap();
+    let since = std::time::SystemTime::now();
+    let minute = std::time::Duration::from_secs(60);
+    diesel::insert_into(event::table)
+        .values(&vec![
+            Event {
+                id: 1,
+                name: String::from("old"),
+                updated_at: since - minute,
+            },
+            Event {
+                id: 2,
+                name: String::from("new"),
+                updated_at: since + minute,
+            },
+        ])
+        .execute(&conn)
+        .unwrap();
+    let addr = CacheDbActor::<_, event::table, Event>::new(wrap.pool.get().unwrap())
+        .unwrap()
+        .start();
+
+    let changed = addr.send(GetChangedSince { since }).await.unwrap().unwrap();
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0].name, "new");
+}
+
+#[actix_rt::test]
+async fn ttl_works() {
+    let ttl = std::time::Duration::from_millis(100);
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .refresh_interval(std::time::Duration::from_secs(0))
+            .ttl(ttl)
+            .build()
+            .unwrap()
+    });
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&conn)
+        .unwrap();
+
+    // Cache is not reloaded before ttl passes
+    let count = wrap.addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 0);
+    actix_rt::time::delay_for(ttl * 2).await;
+    let all = wrap.addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert_eq!(all.read().unwrap().len(), 1);
+    let stats = wrap.addr.send(GetStats).await.unwrap();
+    assert_eq!(stats.refreshes, 2);
+}
+
+#[actix_rt::test]
+async fn max_age_works() {
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .refresh_interval(std::time::Duration::from_secs(0))
+            .build()
+            .unwrap()
+    });
+    let conn = wrap.pool.get().unwrap();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&conn)
+        .unwrap();
+
+    // Cache loaded just now is fresh enough, so miss doesn't touch db
+    let max_age = std::time::Duration::from_secs(60);
+    let shop = wrap
+        .addr
+        .send(Get::with_max_age(1, max_age))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(shop, None);
+    let all = wrap
+        .addr
+        .send(GetAll::with_max_age(max_age))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(all.read().unwrap().is_empty());
+
+    // Zero staleness reloads cache
+    let fresh = std::time::Duration::from_secs(0);
+    let shop = wrap
+        .addr
+        .send(Get::with_max_age(1, fresh))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(shop.unwrap().name, "Nike");
+}
+
+#[actix_rt::test]
+async fn get_all_by_ids_works() {
+    let wrap = setup();
+    for name in ["Nike", "Adidas", "Puma"].iter() {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+
+    let shops = wrap
+        .addr
+        .send(GetAllByIds {
+            ids: vec![1, 3, 42].into_iter().collect(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(shops.len(), 2);
+    assert_eq!(shops[&3].name, "Puma");
+    assert!(!shops.contains_key(&42));
+}
+
+#[actix_rt::test]
+async fn diff_works() {
+    let wrap = setup();
+    for name in ["Nike", "Adidas"].iter() {
+        let shop = ShopInsert {
+            name: String::from(*name),
+            address: String::from("Central street"),
+        };
+        wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    }
+    let shop = |id, name: &str| Shop {
+        id,
+        name: String::from(name),
+        address: String::from("Central street"),
+    };
+    let other: CacheMap<i32, Shop> = vec![(2, shop(2, "Reebok")), (3, shop(3, "Puma"))]
+        .into_iter()
+        .collect();
+
+    let diff = wrap.addr.send(Diff(other)).await.unwrap().unwrap();
+    assert_eq!(diff.only_in_cache.keys().collect::<Vec<_>>(), vec![&1]);
+    assert_eq!(diff.only_in_other.keys().collect::<Vec<_>>(), vec![&3]);
+    assert_eq!(diff.both_modified[&2].0.name, "Adidas");
+    assert_eq!(diff.both_modified[&2].1.name, "Reebok");
+}
+
+#[derive(QueryableByName)]
+struct ShopName {
+    #[sql_type = "diesel::sql_types::Text"]
+    name: String,
+}
+
+#[actix_rt::test]
+async fn raw_sql_works() {
+    let wrap = setup();
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+
+    wrap.addr
+        .send(RawSql(String::from("UPDATE shop SET name = 'Puma'")))
+        .await
+        .unwrap()
+        .unwrap();
+    let names = wrap
+        .addr
+        .send(RawQuery::<ShopName>::new("SELECT name FROM shop"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(names.len(), 1);
+    assert_eq!(names[0].name, "Puma");
+
+    // Cache is not touched until reload
+    let shop = wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    assert_eq!(shop.unwrap().name, "Nike");
+}
+
+#[actix_rt::test]
+async fn entry_or_insert_works() {
+    let wrap = setup();
+    let factory = |name: &'static str| -> Box<dyn FnOnce() -> ShopInsert + Send> {
+        Box::new(move || ShopInsert {
+            name: String::from(name),
+            address: String::from("Central street"),
+        })
+    };
+
+    let (inserted, is_new) = wrap
+        .addr
+        .send(EntryOrInsert {
+            id: 1,
+            factory: factory("Nike"),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(is_new);
+    assert_eq!(inserted.name, "Nike");
+
+    // Factory is not called for existing entry
+    let (found, is_new) = wrap
+        .addr
+        .send(EntryOrInsert {
+            id: 1,
+            factory: Box::new(|| -> ShopInsert { panic!("factory called") }),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!is_new);
+    assert_eq!(found, inserted);
+}
+
+#[cfg(feature = "postgres-notify")]
+#[actix_rt::test]
+async fn listen_notifications_works() {
+    let db = PgDb::new();
+    let pool = init_db_pool(db.url.as_str());
+    embedded_migrations::run(&pool.get().unwrap()).unwrap();
+    let addr: Addr<ShopCache> = CacheDbActorBuilder::new(pool.get().unwrap())
+        .refresh_interval(std::time::Duration::from_secs(0))
+        .listen_notifications(&db.url)
+        .build()
+        .unwrap()
+        .start();
+    // Give listener time to subscribe
+    actix_rt::time::delay_for(std::time::Duration::from_millis(500)).await;
+
+    let conn = pool.get().unwrap();
+    diesel::insert_into(shop::table)
+        .values(ShopInsert {
+            name: String::from("Nike"),
+            address: String::from("Central street"),
+        })
+        .execute(&conn)
+        .unwrap();
+    diesel::sql_query("NOTIFY cache_invalidate, 'shop'")
+        .execute(&conn)
+        .unwrap();
+    actix_rt::time::delay_for(std::time::Duration::from_millis(500)).await;
+
+    let all = addr.send(GetAll::default()).await.unwrap().unwrap();
+    assert_eq!(all.read().unwrap().len(), 1);
+}
+
+#[cfg(feature = "testing")]
+#[actix_rt::test]
+async fn fake_cache_works() {
+    use actix_diesel_cache::FakeCacheDbActor;
+
+    let addr = FakeCacheDbActor::new(|shop: &Shop| shop.id).start();
+    let shop = Shop {
+        id: 1,
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    addr.send(Save(shop.clone())).await.unwrap().unwrap();
+
+    let found = addr
+        .send(Get::<PooledConnection, shop::table, Shop>::new(1))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found, Some(shop));
+    let deleted = addr
+        .send(Delete::<PooledConnection, shop::table, Shop> { id: 1 })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(deleted);
+    let count = addr.send(Count).await.unwrap().unwrap();
+    assert_eq!(count, 0);
+}
+
+struct CountingRetry(std::sync::Arc<AtomicI32>);
+
+impl actix_diesel_cache::RetryPolicy for CountingRetry {
+    fn should_retry(&self, _: &diesel::result::Error, attempt: u32) -> bool {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        attempt <= 2
+    }
+
+    fn delay(&self, _: u32) -> std::time::Duration {
+        std::time::Duration::from_millis(10)
+    }
+}
+
+#[actix_rt::test]
+async fn retry_policy_works() {
+    let checks = std::sync::Arc::new(AtomicI32::new(0));
+    let policy = CountingRetry(std::sync::Arc::clone(&checks));
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .refresh_interval(std::time::Duration::from_secs(0))
+            .retry_policy(policy)
+            .build()
+            .unwrap()
+    });
+    let conn = wrap.pool.get().unwrap();
+    diesel::sql_query("DROP TABLE shop").execute(&conn).unwrap();
+
+    // Two retries are made, then error is returned
+    let res = wrap.addr.send(Delete { id: 1 }).await.unwrap();
+    assert!(matches!(res, Err(CacheError::Db(_))));
+    assert_eq!(checks.load(Ordering::SeqCst), 3);
+
+    let shop = ShopInsert {
+        name: "Shop".to_string(),
+        address: "Address".to_string(),
+    };
+    let res = wrap
+        .addr
+        .send(SaveWith(move || shop.clone()))
+        .await
+        .unwrap();
+    assert!(matches!(res, Err(CacheError::Db(_))));
+    assert_eq!(checks.load(Ordering::SeqCst), 6);
+}
+
+#[derive(Default)]
+struct CountingMiddleware {
+    hits: std::sync::Arc<AtomicI32>,
+    saves: std::sync::Arc<AtomicI32>,
+}
+
+impl actix_diesel_cache::CacheMiddleware<PooledConnection, shop::table, Shop>
+    for CountingMiddleware
+{
+    fn after_get(&self, _: &i32, hit: bool) {
+        if hit {
+            self.hits.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn after_save(&self, ok: bool) {
+        if ok {
+            self.saves.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[actix_rt::test]
+async fn middleware_works() {
+    let middleware = CountingMiddleware::default();
+    let hits = std::sync::Arc::clone(&middleware.hits);
+    let saves = std::sync::Arc::clone(&middleware.saves);
+    let wrap = setup_with(|conn| {
+        CacheDbActorBuilder::new(conn)
+            .with_middleware(middleware)
+            .build()
+            .unwrap()
+    });
+    let shop = ShopInsert {
+        name: String::from("Nike"),
+        address: String::from("Central street"),
+    };
+    wrap.addr.send(Save(shop)).await.unwrap().unwrap();
+    wrap.addr.send(Get::new(1)).await.unwrap().unwrap();
+    wrap.addr.send(Get::new(42)).await.unwrap().unwrap();
+
+    assert_eq!(saves.load(Ordering::SeqCst), 1);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn tls_connection_url_works() {
+    use actix_diesel_cache::{SslMode, TlsOptions};
+
+    let options = TlsOptions::new()
+        .ssl_mode(SslMode::VerifyFull)
+        .certificate(std::path::Path::new("/etc/ssl/root.crt"));
+    assert_eq!(
+        options.connection_url("postgres://localhost/db"),
+        "postgres://localhost/db?sslmode=verify-full&sslrootcert=/etc/ssl/root.crt"
+    );
+    assert_eq!(
+        TlsOptions::new().connection_url("host=localhost dbname=db"),
+        "host=localhost dbname=db sslmode='require'"
+    );
 }